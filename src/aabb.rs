@@ -0,0 +1,98 @@
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Axis aligned bounding box, used to cheaply reject rays before testing the actual geometry.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// Builds the smallest box containing all the points.
+    pub fn from_points(points: &[Vec3]) -> Aabb {
+        points.iter().fold(
+            Aabb::new(
+                Vec3::new(f64::MAX, f64::MAX, f64::MAX),
+                Vec3::new(f64::MIN, f64::MIN, f64::MIN),
+            ),
+            |bbox, &point| Aabb::surrounding_box(bbox, Aabb::new(point, point)),
+        )
+    }
+
+    /// Builds the smallest box containing both boxes.
+    pub fn surrounding_box(a: Aabb, b: Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::new(
+                a.min.x.min(b.min.x),
+                a.min.y.min(b.min.y),
+                a.min.z.min(b.min.z),
+            ),
+            max: Vec3::new(
+                a.max.x.max(b.max.x),
+                a.max.y.max(b.max.y),
+                a.max.z.max(b.max.z),
+            ),
+        }
+    }
+
+    /// Grows flat boxes (eg. for planar primitives) so they have some thickness on every axis.
+    pub fn padded(self, delta: f64) -> Aabb {
+        let pad = |min: f64, max: f64| {
+            if max - min < delta {
+                (min - delta / 2.0, max + delta / 2.0)
+            } else {
+                (min, max)
+            }
+        };
+
+        let (min_x, max_x) = pad(self.min.x, self.max.x);
+        let (min_y, max_y) = pad(self.min.y, self.max.y);
+        let (min_z, max_z) = pad(self.min.z, self.max.z);
+
+        Aabb {
+            min: Vec3::new(min_x, min_y, min_z),
+            max: Vec3::new(max_x, max_y, max_z),
+        }
+    }
+
+    /// Slab test.
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.hit_interval(ray, t_min, t_max).is_some()
+    }
+
+    /// Slab test, returning the parametric range the ray spends inside the box.
+    pub fn hit_interval(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+
+        let slabs = [
+            (self.min.x, self.max.x, ray.origin.x, ray.direction.x),
+            (self.min.y, self.max.y, ray.origin.y, ray.direction.y),
+            (self.min.z, self.max.z, ray.origin.z, ray.direction.z),
+        ];
+
+        for (min, max, origin, direction) in slabs.iter() {
+            let inv_d = 1.0 / direction;
+            let mut t0 = (min - origin) * inv_d;
+            let mut t1 = (max - origin) * inv_d;
+
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+
+            if t_max <= t_min {
+                return None;
+            }
+        }
+
+        Some((t_min, t_max))
+    }
+}
@@ -0,0 +1,392 @@
+use crate::cryptomatte::{Coverage, Matte};
+use crate::integrators::path::{Emitter, Event};
+use crate::lpe::LightPathExpression;
+use crate::materials::{Compositing, Scattering};
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+use std::f64;
+use std::str::FromStr;
+
+/// Arbitrary output variable, a pass rendered next to the beauty image for compositing.
+///
+/// The lighting passes (direct and indirect diffuse and specular, and emission) add up to the
+/// beauty image. Only integrators overriding `Integrator::radiance_with_aovs` fill them in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Aov {
+    /// Reflectance of the first surface seen, or the emitted light of lights.
+    Albedo,
+    /// Unit shading normal of the first surface seen, in world space.
+    Normal,
+    /// Distance of the first surface seen along the viewing direction.
+    Depth,
+    /// World position of the first surface seen.
+    Position,
+    /// Index of the first object seen in `Scene::world` plus one, 0 where there is none. Not
+    /// averaged, pixels keep the object seen by the sample closest to their center.
+    ObjectId,
+    /// Light reaching the camera after a single diffuse bounce.
+    DiffuseDirect,
+    /// Light reaching the camera after more bounces, the first of them diffuse.
+    DiffuseIndirect,
+    /// Light reaching the camera after a single glossy or specular bounce, refraction included.
+    SpecularDirect,
+    /// Light reaching the camera after more bounces, the first of them glossy or specular.
+    SpecularIndirect,
+    /// Light from emitters and the sky seen directly by the camera.
+    Emission,
+    /// Fraction of the pixel covered by the scene, without holdouts. Shadow catchers cover it as
+    /// much as they're shadowed.
+    Alpha,
+}
+
+impl Aov {
+    pub const ALL: &'static [Aov] = &[
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::Position,
+        Aov::ObjectId,
+        Aov::DiffuseDirect,
+        Aov::DiffuseIndirect,
+        Aov::SpecularDirect,
+        Aov::SpecularIndirect,
+        Aov::Emission,
+        Aov::Alpha,
+    ];
+
+    pub const NAMES: &'static [&'static str] = &[
+        "albedo",
+        "normal",
+        "depth",
+        "position",
+        "object_id",
+        "diffuse_direct",
+        "diffuse_indirect",
+        "specular_direct",
+        "specular_indirect",
+        "emission",
+        "alpha",
+    ];
+
+    pub fn name(self) -> &'static str {
+        Aov::NAMES[self as usize]
+    }
+
+    /// Whether the pass is one of the lighting passes, black unless the integrator fills it in.
+    pub fn is_lighting(self) -> bool {
+        matches!(
+            self,
+            Aov::DiffuseDirect
+                | Aov::DiffuseIndirect
+                | Aov::SpecularDirect
+                | Aov::SpecularIndirect
+                | Aov::Emission
+        )
+    }
+
+    /// Names of the channels of the pass, the components of its values in order.
+    pub fn channels(self) -> &'static [&'static str] {
+        match self {
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Depth => &["Z"],
+            Aov::ObjectId => &["id"],
+            Aov::Alpha => &["A"],
+            _ => &["R", "G", "B"],
+        }
+    }
+
+    /// Value mapped to [0, 1] for 8-bit images: colours are gamma corrected like the beauty
+    /// image, distances are divided by `extent` and IDs get a colour each.
+    pub fn display(self, value: Vec3, extent: f64) -> Vec3 {
+        let clamp = |v: Vec3| {
+            Vec3::new(
+                v.x.clamp(0.0, 1.0),
+                v.y.clamp(0.0, 1.0),
+                v.z.clamp(0.0, 1.0),
+            )
+        };
+        match self {
+            Aov::Normal => clamp((value + Vec3::new(1.0, 1.0, 1.0)) / 2.0),
+            Aov::Position => clamp((value / extent + Vec3::new(1.0, 1.0, 1.0)) / 2.0),
+            Aov::Depth => clamp(Vec3::new(value.x, value.x, value.x) / extent),
+            Aov::ObjectId if value.x > 0.0 => false_colour(value.x as u64 - 1),
+            Aov::ObjectId => Vec3::origin(),
+            Aov::Alpha => clamp(Vec3::new(value.x, value.x, value.x)),
+            _ => {
+                let c = clamp(value);
+                Vec3::new(c.x.sqrt(), c.y.sqrt(), c.z.sqrt())
+            }
+        }
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Aov, String> {
+        match Aov::NAMES.iter().position(|&known| known == name) {
+            Some(index) => Ok(Aov::ALL[index]),
+            None => Err(format!(
+                "unknown AOV `{}`, expected one of {}",
+                name,
+                Aov::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Distinct, stable colour for an identifier.
+pub fn false_colour(id: u64) -> Vec3 {
+    // SplitMix64 finalizer
+    let mut x = id.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^= x >> 31;
+
+    let channel = |shift: u64| 0.2 + 0.8 * ((x >> shift) & 0xFF) as f64 / 255.0;
+    Vec3::new(channel(0), channel(8), channel(16))
+}
+
+/// Every pass for a single camera ray, scalar passes stored in `x`, the light of the paths
+/// matching each of a list of light path expressions, and the names of what the ray hit first.
+#[derive(Debug, Clone)]
+pub struct AovSample<'a> {
+    values: [Vec3; 11],
+    expressions: &'a [LightPathExpression],
+    matched: Vec<Vec3>,
+    names: [Option<&'a str>; 2],
+}
+
+impl<'a> AovSample<'a> {
+    /// The passes describing the first surface `ray` hits, with the lighting passes still black.
+    pub fn at_first_hit(ray: &Ray, scene: &'a Scene) -> AovSample<'a> {
+        let mut sample = AovSample {
+            values: [Vec3::origin(); 11],
+            expressions: &[],
+            matched: Vec::new(),
+            names: [None; 2],
+        };
+        if let Some((index, hit)) = scene.hit_object(ray, 0.001, f64::MAX) {
+            let depth = scene.camera.depth(hit.position);
+            sample.set(Aov::Albedo, hit.material.albedo(ray, &hit));
+            sample.set(Aov::Normal, hit.normal.make_unit_vec());
+            sample.set(Aov::Depth, Vec3::new(depth, 0.0, 0.0));
+            sample.set(Aov::Position, hit.position);
+            sample.set(Aov::ObjectId, Vec3::new((index + 1) as f64, 0.0, 0.0));
+            let alpha = match hit.material.compositing() {
+                Compositing::Holdout => 0.0,
+                _ => 1.0,
+            };
+            sample.set(Aov::Alpha, Vec3::new(alpha, 0.0, 0.0));
+            for &matte in Matte::ALL {
+                sample.names[matte as usize] = matte.name_of(&hit);
+            }
+        }
+        sample
+    }
+
+    /// Also collects the light of paths matching each of `expressions`.
+    pub fn with_expressions(mut self, expressions: &'a [LightPathExpression]) -> AovSample<'a> {
+        self.expressions = expressions;
+        self.matched = vec![Vec3::origin(); expressions.len()];
+        self
+    }
+
+    pub fn get(&self, aov: Aov) -> Vec3 {
+        self.values[aov as usize]
+    }
+
+    pub fn set(&mut self, aov: Aov, value: Vec3) {
+        self.values[aov as usize] = value;
+    }
+
+    /// Light of the paths matching `expressions[index]`.
+    pub fn matched(&self, index: usize) -> Vec3 {
+        self.matched[index]
+    }
+
+    /// Name of the object or material the ray hit first, if it has one.
+    pub fn name(&self, matte: Matte) -> Option<&'a str> {
+        self.names[matte as usize]
+    }
+
+    /// Adds light found by a path to the lighting pass its `events` belong to, and to the
+    /// expressions it matches.
+    pub fn add_light(&mut self, events: &[Event], emitter: Emitter, light: Vec3) {
+        for (expression, matched) in self.expressions.iter().zip(&mut self.matched) {
+            if expression.matches(events, emitter) {
+                *matched += light;
+            }
+        }
+
+        let aov = match events {
+            [] => Aov::Emission,
+            [first, rest @ ..] => match (first.scattering, rest.is_empty()) {
+                (Scattering::Diffuse, true) => Aov::DiffuseDirect,
+                (Scattering::Diffuse, false) => Aov::DiffuseIndirect,
+                (_, true) => Aov::SpecularDirect,
+                (_, false) => Aov::SpecularIndirect,
+            },
+        };
+        self.values[aov as usize] += light;
+    }
+}
+
+/// The passes `aovs`, light path expressions and ID mattes of a pixel, accumulated over its
+/// samples.
+#[derive(Debug, Clone)]
+pub struct AovPixel<'a> {
+    sums: Vec<Vec3>,
+    matched: Vec<Vec3>,
+    mattes: Vec<(Matte, Coverage<'a>)>,
+    samples: usize,
+    /// Object ID of the sample closest to the pixel center, and its distance from the center.
+    object_id: Option<(f64, Vec3)>,
+}
+
+impl<'a> AovPixel<'a> {
+    pub fn new(aovs: &[Aov], expressions: usize) -> AovPixel<'a> {
+        AovPixel {
+            sums: vec![Vec3::origin(); aovs.len()],
+            matched: vec![Vec3::origin(); expressions],
+            mattes: Vec::new(),
+            samples: 0,
+            object_id: None,
+        }
+    }
+
+    /// Also ranks the names seen by the samples for each of `mattes`.
+    pub fn with_mattes(mut self, mattes: &[Matte]) -> AovPixel<'a> {
+        self.mattes = mattes
+            .iter()
+            .map(|&matte| (matte, Coverage::new()))
+            .collect();
+        self
+    }
+
+    /// Adds a sample taken at `offset` from the pixel center, in pixels.
+    pub fn add(&mut self, aovs: &[Aov], sample: &AovSample<'a>, offset: f64) {
+        for (sum, &aov) in self.sums.iter_mut().zip(aovs) {
+            *sum += sample.get(aov);
+        }
+        for (index, matched) in self.matched.iter_mut().enumerate() {
+            *matched += sample.matched(index);
+        }
+        for (matte, coverage) in &mut self.mattes {
+            coverage.add(sample.name(*matte));
+        }
+        self.add_object_id(offset, sample.get(Aov::ObjectId));
+        self.samples += 1;
+    }
+
+    fn add_object_id(&mut self, offset: f64, id: Vec3) {
+        match self.object_id {
+            Some((closest, _)) if closest <= offset => {}
+            _ => self.object_id = Some((offset, id)),
+        }
+    }
+
+    /// Adds the samples of `other`, for the same pixel and passes.
+    pub fn merge(&mut self, other: &AovPixel<'a>) {
+        for (sum, other) in self.sums.iter_mut().zip(&other.sums) {
+            *sum += *other;
+        }
+        for (matched, other) in self.matched.iter_mut().zip(&other.matched) {
+            *matched += *other;
+        }
+        for ((_, coverage), (_, other)) in self.mattes.iter_mut().zip(&other.mattes) {
+            coverage.merge(other);
+        }
+        if let Some((offset, id)) = other.object_id {
+            self.add_object_id(offset, id);
+        }
+        self.samples += other.samples;
+    }
+
+    /// Value of `aovs[index]` for the pixel.
+    pub fn value(&self, aovs: &[Aov], index: usize) -> Vec3 {
+        match aovs[index] {
+            Aov::ObjectId => self.object_id.map_or(Vec3::origin(), |(_, id)| id),
+            _ if self.samples == 0 => Vec3::origin(),
+            _ => self.sums[index] / self.samples as f64,
+        }
+    }
+
+    /// Light of the paths matching the light path expression `index`, for the pixel.
+    pub fn matched(&self, index: usize) -> Vec3 {
+        if self.samples == 0 {
+            return Vec3::origin();
+        }
+        self.matched[index] / self.samples as f64
+    }
+
+    /// Names seen for the matte `index`, with their coverage of the pixel.
+    pub fn coverage(&self, index: usize) -> &Coverage<'a> {
+        &self.mattes[index].1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aov::{Aov, AovPixel, AovSample};
+    use crate::integrators::path::{Emitter, Event};
+    use crate::lpe::LightPathExpression;
+    use crate::materials::Scattering;
+    use crate::ray::Ray;
+    use crate::scene::Scene;
+    use crate::vec3::Vec3;
+
+    /// Sample of a ray leaving the empty `scene`.
+    fn missed(scene: &Scene) -> AovSample<'_> {
+        let ray = Ray::new(Vec3::origin(), Vec3::new(0.0, 0.0, -1.0));
+        AovSample::at_first_hit(&ray, scene)
+    }
+
+    #[test]
+    fn splits_light_by_first_bounce() {
+        let diffuse = Event {
+            scattering: Scattering::Diffuse,
+            transmitted: false,
+        };
+        let specular = Event {
+            scattering: Scattering::Specular,
+            transmitted: true,
+        };
+        let light = Vec3::new(1.0, 1.0, 1.0);
+        let expressions: Vec<LightPathExpression> = vec!["CT.*L".parse().unwrap()];
+        let scene = Scene::new(Vec::new(), Vec::new());
+        let mut sample = missed(&scene).with_expressions(&expressions);
+        sample.add_light(&[], Emitter::Background, light);
+        sample.add_light(&[diffuse], Emitter::Light, light);
+        sample.add_light(&[specular, diffuse], Emitter::Light, light * 2.0);
+
+        assert_eq!(sample.get(Aov::Emission), light);
+        assert_eq!(sample.get(Aov::DiffuseDirect), light);
+        assert_eq!(sample.get(Aov::SpecularIndirect), light * 2.0);
+        assert_eq!(sample.get(Aov::Alpha), Vec3::origin());
+        assert_eq!(sample.matched(0), light * 2.0);
+    }
+
+    #[test]
+    fn keeps_object_id_closest_to_center() {
+        let aovs = [Aov::Alpha, Aov::ObjectId];
+        let scene = Scene::new(Vec::new(), Vec::new());
+        let mut covered = missed(&scene);
+        covered.set(Aov::Alpha, Vec3::new(1.0, 0.0, 0.0));
+        covered.set(Aov::ObjectId, Vec3::new(3.0, 0.0, 0.0));
+        let mut background = covered.clone();
+        background.set(Aov::Alpha, Vec3::origin());
+        background.set(Aov::ObjectId, Vec3::origin());
+
+        let mut pixel = AovPixel::new(&aovs, 0);
+        pixel.add(&aovs, &covered, 0.4);
+        let mut other = AovPixel::new(&aovs, 0);
+        other.add(&aovs, &background, 0.1);
+        other.add(&aovs, &background, 0.6);
+        pixel.merge(&other);
+
+        assert!((pixel.value(&aovs, 0).x - 1.0 / 3.0).abs() < 1e-12);
+        assert_eq!(pixel.value(&aovs, 1), Vec3::origin());
+    }
+}
@@ -1,46 +1,62 @@
 use indicatif::ProgressBar;
 use rayon::prelude::*;
+use std::collections::BTreeSet;
 use std::f64;
+use std::path::{Path, PathBuf};
 
 use clap::{App, Arg};
 use indicatif::ProgressStyle;
+use rs_raytracer::aov::{Aov, AovPixel, AovSample};
 use rs_raytracer::camera::Camera;
-use rs_raytracer::hitable::{Hitable, HitableList};
+use rs_raytracer::cryptomatte::{self, Matte};
+use rs_raytracer::denoise::Denoiser;
+use rs_raytracer::exr::{self, Channel};
+use rs_raytracer::film::Film;
+use rs_raytracer::hitable::HitableList;
+use rs_raytracer::integrators::bidirectional::BidirectionalPathTracer;
+use rs_raytracer::integrators::debug::{DebugIntegrator, DebugMode};
+use rs_raytracer::integrators::guided::GuidedPathTracer;
+use rs_raytracer::integrators::metropolis::MetropolisTracer;
+use rs_raytracer::integrators::path::PathTracer;
+use rs_raytracer::integrators::photon::PhotonMapper;
+use rs_raytracer::integrators::restir::ResampledDirectLighting;
+use rs_raytracer::integrators::spectral::SpectralPathTracer;
+use rs_raytracer::integrators::{sky, Integrator};
+use rs_raytracer::lpe::LightPathExpression;
 use rs_raytracer::materials::dielectric::Dielectric;
 use rs_raytracer::materials::lambertian::Lambertian;
 use rs_raytracer::materials::metal::Metal;
-use rs_raytracer::ray::Ray;
+use rs_raytracer::materials::named::Named;
+use rs_raytracer::materials::Material;
+use rs_raytracer::object::Object;
+use rs_raytracer::region::Region;
+use rs_raytracer::scene::Scene;
 use rs_raytracer::sphere::Sphere;
+use rs_raytracer::tiles::{self, stitch_exr, stitch_png, with_part};
 use rs_raytracer::vec3::Vec3;
 
-fn calculate_color(r: &Ray, world: &dyn Hitable, depth: i32) -> Vec3 {
-    match world.hit(r, 0.001, f64::MAX) {
-        Some(hit) => match hit.material.scatter(r, &hit) {
-            Some((albedo, scattered)) => {
-                if depth < 50 {
-                    return albedo * calculate_color(&scattered, world, depth + 1);
-                }
-                Vec3::origin()
-            }
-            None => Vec3::origin(),
-        },
-        None => {
-            let unit_vec = r.direction.make_unit_vec();
-            let t = (unit_vec.y + 1.0) / 2.0;
-
-            Vec3::new(1.0, 1.0, 1.0) * (1.0 - t) + Vec3::new(0.5, 0.7, 1.0) * t
-        }
-    }
+/// Sphere named `name`, with its material named `material_name` for ID mattes.
+fn sphere(
+    name: String,
+    center: Vec3,
+    radius: f64,
+    material_name: &str,
+    material: Box<dyn Material + Send + Sync>,
+) -> Box<Object> {
+    let material = Box::new(Named::new(material_name, material));
+    Box::new(Object::new(Box::new(Sphere::new(center, radius, material))).with_name(name))
 }
 
 fn generate_scene() -> HitableList {
     let mut world = HitableList::new();
 
-    world.push(Box::new(Sphere::new(
+    world.push(sphere(
+        "ground".to_string(),
         Vec3::new(0.0, -1000.0, 0.0),
         1000.0,
+        "ground",
         Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
-    )));
+    ));
 
     for a in -11..11 {
         for b in -11..11 {
@@ -49,23 +65,28 @@ fn generate_scene() -> HitableList {
                 0.2,
                 f64::from(b) + 0.9 * rand::random::<f64>(),
             );
+            let name = format!("sphere_{}_{}", a, b);
             let choose_mat = rand::random::<f64>();
             if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 if choose_mat < 0.8 {
-                    world.push(Box::new(Sphere::new(
+                    world.push(sphere(
+                        name,
                         center,
                         0.2,
+                        "diffuse",
                         Box::new(Lambertian::new(Vec3::new(
                             rand::random(),
                             rand::random(),
                             rand::random(),
                         ))),
-                    )))
+                    ))
                 //metal
                 } else if choose_mat < 0.95 {
-                    world.push(Box::new(Sphere::new(
+                    world.push(sphere(
+                        name,
                         center,
                         0.2,
+                        "metal",
                         Box::new(Metal::new(
                             Vec3::new(
                                 0.5 * (1.0 + rand::random::<f64>()),
@@ -74,39 +95,72 @@ fn generate_scene() -> HitableList {
                             ),
                             rand::random(),
                         )),
-                    )));
+                    ));
                 } else {
-                    world.push(Box::new(Sphere::new(
+                    world.push(sphere(
+                        name,
                         center,
                         0.2,
+                        "glass",
                         Box::new(Dielectric::new(1.5)),
-                    )));
+                    ));
                 }
             }
         }
     }
 
-    world.push(Box::new(Sphere::new(
+    world.push(sphere(
+        "glass_sphere".to_string(),
         Vec3::new(0.0, 1.0, 0.0),
         1.0,
+        "glass",
         Box::new(Dielectric::new(1.5)),
-    )));
+    ));
 
-    world.push(Box::new(Sphere::new(
+    world.push(sphere(
+        "diffuse_sphere".to_string(),
         Vec3::new(-4.0, 1.0, 0.0),
         1.0,
+        "diffuse",
         Box::new(Lambertian::new(Vec3::new(0.4, 0.2, 0.1))),
-    )));
+    ));
 
-    world.push(Box::new(Sphere::new(
+    world.push(sphere(
+        "metal_sphere".to_string(),
         Vec3::new(4.0, 1.0, 0.0),
         1.0,
+        "metal",
         Box::new(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.0)),
-    )));
+    ));
 
     world
 }
 
+/// Splits `name=expression` into the name of the pass and the parsed expression.
+fn parse_lpe(lpe: &str) -> Result<(String, LightPathExpression), String> {
+    let mut parts = lpe.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        // Room for the `.R` of the channel names
+        (Some(name), _) if name.len() + 2 > exr::MAX_NAME_LENGTH => Err(format!(
+            "pass names can't be longer than {} bytes, got `{}`",
+            exr::MAX_NAME_LENGTH - 2,
+            name
+        )),
+        (Some(name), Some(expression)) if !name.is_empty() => {
+            let expression = expression
+                .parse()
+                .map_err(|e| format!("invalid light path expression `{}`: {}", expression, e))?;
+            Ok((name.to_string(), expression))
+        }
+        _ => Err(format!("expected `name=expression`, got `{}`", lpe)),
+    }
+}
+
+/// Path of the PNG of the pass `name` written next to the image at `path`.
+fn pass_path(path: &Path, name: &str) -> PathBuf {
+    with_part(path, name).with_extension("png")
+}
+
 fn main() {
     let app = App::new("Raytracer").args(&[
         Arg::with_name("x").required(true),
@@ -122,7 +176,121 @@ fn main() {
             .required(false)
             .default_value("output.png")
             .short("o")
-            .help("Output path, defaults to `output.png`"),
+            .help(
+                "Output path, defaults to `output.png`. Paths ending in `.exr` are written linear",
+            ),
+        Arg::with_name("spectral")
+            .long("spectral")
+            .help("Render spectrally, needed for dispersion"),
+        Arg::with_name("bidirectional")
+            .long("bidirectional")
+            .conflicts_with("spectral")
+            .help("Render with bidirectional path tracing, for caustics from small lights"),
+        Arg::with_name("metropolis")
+            .long("metropolis")
+            .conflicts_with_all(&["spectral", "bidirectional"])
+            .help("Render with Metropolis light transport, the aa rays become mutations per pixel"),
+        Arg::with_name("guided")
+            .long("guided")
+            .conflicts_with_all(&["spectral", "bidirectional", "metropolis"])
+            .help("Render with path guiding, learning where light comes from over the passes"),
+        Arg::with_name("restir")
+            .long("restir")
+            .conflicts_with_all(&["spectral", "bidirectional", "metropolis", "guided"])
+            .help("Light camera hits by reservoir resampling, reusing reservoirs between passes"),
+        Arg::with_name("photons")
+            .long("photons")
+            .takes_value(true)
+            .conflicts_with_all(&[
+                "spectral",
+                "bidirectional",
+                "metropolis",
+                "guided",
+                "restir",
+            ])
+            .help("Render with photon mapping, tracing this many photons per pass"),
+        Arg::with_name("photon-radius")
+            .long("photon-radius")
+            .default_value("0.1")
+            .help("Radius photons are gathered in"),
+        Arg::with_name("passes")
+            .long("passes")
+            .default_value("1")
+            .help(
+                "Passes of photon mapping, path guiding or ReSTIR, the samples are split over them",
+            ),
+        Arg::with_name("mode")
+            .long("mode")
+            .takes_value(true)
+            .possible_values(DebugMode::NAMES)
+            .conflicts_with_all(&[
+                "spectral",
+                "bidirectional",
+                "metropolis",
+                "guided",
+                "restir",
+                "photons",
+            ])
+            .help("Show a property of the scene instead of rendering it"),
+        Arg::with_name("extent")
+            .long("extent")
+            .default_value("20")
+            .help("Distance shown as white by the depth and position modes"),
+        Arg::with_name("ao-radius")
+            .long("ao-radius")
+            .default_value("1")
+            .help("Distance within which occluders darken the ao mode"),
+        Arg::with_name("aov")
+            .long("aov")
+            .takes_value(true)
+            .multiple(true)
+            .use_delimiter(true)
+            .possible_values(Aov::NAMES)
+            .help("Passes to write as `<output>.<aov>.png`, or as layers of an `.exr` output"),
+        Arg::with_name("denoise")
+            .long("denoise")
+            .conflicts_with("mode")
+            .help("Filter the noise out of the image, guided by the albedo and normal passes"),
+        Arg::with_name("transparent")
+            .long("transparent")
+            .conflicts_with("mode")
+            .help("Leave the background transparent, writing RGBA with holdouts cut out"),
+        Arg::with_name("lpe")
+            .long("lpe")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .validator(|lpe| parse_lpe(&lpe).map(|_| ()))
+            .help("Pass of the light of paths matching a light path expression, as `name=C<RS>+L`"),
+        Arg::with_name("cryptomatte")
+            .long("cryptomatte")
+            .takes_value(true)
+            .multiple(true)
+            .use_delimiter(true)
+            .possible_values(Matte::NAMES)
+            .help("Cryptomatte ID mattes of named objects or materials, previewed in PNGs"),
+        Arg::with_name("region")
+            .long("region")
+            .takes_value(true)
+            .validator(|region| region.parse::<Region>().map(|_| ()))
+            // Light paths splatting outside the region would be lost, and Metropolis chains
+            // would wander the whole image
+            .conflicts_with_all(&["bidirectional", "metropolis"])
+            .help(
+                "Only render the pixels from x0,y0 to x1,y1 (excluded), counted from the top left",
+            ),
+        Arg::with_name("tile-size")
+            .long("tile-size")
+            .takes_value(true)
+            .validator(|size| match size.parse::<usize>() {
+                Ok(size) if size > 0 => Ok(()),
+                _ => Err(format!("expected a number of pixels, got `{}`", size)),
+            })
+            .conflicts_with_all(&["bidirectional", "metropolis"])
+            .help(
+                "Render and save tiles of this many pixels one at a time, then put them together, \
+                 for images too large for memory. Tiles are denoised on their own",
+            ),
     ]);
 
     let matches = app.get_matches();
@@ -139,7 +307,7 @@ fn main() {
         .parse()
         .expect("y should be a number");
 
-    let aa_ray_count = matches
+    let aa_ray_count: usize = matches
         .value_of("aa")
         .expect("has a default")
         .parse()
@@ -160,58 +328,422 @@ fn main() {
         dist_to_focus,
     );
 
-    let world = generate_scene();
-    let pbar = ProgressBar::new((ny * nx) as u64);
+    let passes = matches
+        .value_of("passes")
+        .expect("has a default")
+        .parse()
+        .expect("number of passes should be a number");
+
+    let debug_mode: Option<DebugMode> = matches
+        .value_of("mode")
+        .map(|mode| mode.parse().expect("mode is one of the possible values"));
+
+    let mut aovs: Vec<Aov> = matches.values_of("aov").map_or(Vec::new(), |aovs| {
+        aovs.map(|aov| aov.parse().expect("aov is one of the possible values"))
+            .collect()
+    });
+
+    // The denoiser and the alpha channel need passes that may not be written out
+    let written_aovs = aovs.len();
+    let denoise = matches.is_present("denoise");
+    let transparent = matches.is_present("transparent");
+    let mut needed = Vec::new();
+    if denoise {
+        needed.extend_from_slice(&[Aov::Albedo, Aov::Normal]);
+    }
+    if transparent {
+        needed.push(Aov::Alpha);
+    }
+    for aov in needed {
+        if !aovs.contains(&aov) {
+            aovs.push(aov);
+        }
+    }
+
+    let (lpe_names, expressions): (Vec<String>, Vec<LightPathExpression>) = matches
+        .values_of("lpe")
+        .map_or(Vec::new(), |lpes| {
+            lpes.map(|lpe| parse_lpe(lpe).expect("validated by clap"))
+                .collect()
+        })
+        .into_iter()
+        .unzip();
+
+    // Other integrators can't tell how light reached the camera
+    let path_traced = [
+        "mode",
+        "spectral",
+        "bidirectional",
+        "metropolis",
+        "guided",
+        "photons",
+    ]
+    .iter()
+    .all(|integrator| !matches.is_present(integrator));
+    if !path_traced && (aovs.iter().any(|aov| aov.is_lighting()) || !expressions.is_empty()) {
+        clap::Error::value_validation_auto(
+            "lighting passes and light path expressions are only rendered by the path tracer"
+                .to_string(),
+        )
+        .exit();
+    }
+
+    let mattes: Vec<Matte> = matches
+        .values_of("cryptomatte")
+        .map_or(Vec::new(), |mattes| {
+            mattes
+                .map(|matte| matte.parse().expect("matte is one of the possible values"))
+                .collect()
+        });
+
+    let extent = matches
+        .value_of("extent")
+        .expect("has a default")
+        .parse()
+        .expect("extent should be a number");
+
+    let mut integrator: Box<dyn Integrator> = if let Some(mode) = debug_mode {
+        let ao_radius = matches
+            .value_of("ao-radius")
+            .expect("has a default")
+            .parse()
+            .expect("ao radius should be a number");
+        Box::new(
+            DebugIntegrator::new(mode)
+                .with_extent(extent)
+                .with_ao_radius(ao_radius),
+        )
+    } else if matches.is_present("spectral") {
+        Box::new(SpectralPathTracer::new())
+    } else if matches.is_present("bidirectional") {
+        Box::new(BidirectionalPathTracer::new())
+    } else if matches.is_present("metropolis") {
+        Box::new(MetropolisTracer::new())
+    } else if matches.is_present("guided") {
+        Box::new(GuidedPathTracer::new(passes))
+    } else if let Some(photons) = matches.value_of("photons") {
+        let photons = photons
+            .parse()
+            .expect("number of photons should be a number");
+        let radius = matches
+            .value_of("photon-radius")
+            .expect("has a default")
+            .parse()
+            .expect("photon radius should be a number");
+        Box::new(PhotonMapper::new(photons, radius).progressive(passes, 2.0 / 3.0))
+    } else if matches.is_present("restir") {
+        Box::new(
+            PathTracer::new()
+                .with_passes(passes)
+                .with_resampled_direct_lighting(ResampledDirectLighting::new(
+                    nx as usize,
+                    ny as usize,
+                )),
+        )
+    } else {
+        Box::new(PathTracer::new())
+    };
+
+    let scene = Scene::new(generate_scene(), Vec::new()).with_camera(cam);
+    let (width, height) = (nx as usize, ny as usize);
+    let full = Region::full(width, height);
+    let region = matches
+        .value_of("region")
+        .map_or(full, |region| region.parse().expect("validated by clap"));
+    if !region.is_within(&full) {
+        clap::Error::value_validation_auto(format!(
+            "the region doesn't fit in the {}x{} image",
+            width, height
+        ))
+        .exit();
+    }
+    let tiles = match matches.value_of("tile-size") {
+        Some(size) => region.tiles(size.parse().expect("validated by clap")),
+        None => vec![region],
+    };
+
+    let passes = integrator.passes();
+    let samples_per_pass = (aa_ray_count / passes).max(1);
+    let pbar = ProgressBar::new(region.area() as u64 * passes as u64);
 
     pbar.set_style(ProgressStyle::default_bar().template(
         "[{elapsed} elapsed] {wide_bar:.cyan/white} {percent}% [{eta} remaining] [rendering]",
     ));
 
-    let result: Vec<Vec<(i64, i64, i64)>> = (0..ny)
-        .into_par_iter()
-        .map(|j: i32| {
-            (0..nx)
-                .into_par_iter()
-                .map(|i: i32| {
-                    let mut col = Vec3::new(0.0, 0.0, 0.0);
+    let output_path = Path::new(matches.value_of("output").expect("has a default value"));
+    let tile_path = |tile: &Region| {
+        if tiles.len() == 1 {
+            output_path.to_path_buf()
+        } else {
+            tiles::tile_path(output_path, tile)
+        }
+    };
+    let exr_output = output_path
+        .extension()
+        .is_some_and(|extension| extension == "exr");
+    let new_aov_pixel = || AovPixel::new(&aovs, expressions.len()).with_mattes(&mattes);
+    let mut names_seen = vec![BTreeSet::new(); mattes.len()];
+
+    // Only a tile is held in memory at once, the image is put together from the saved tiles
+    for tile in &tiles {
+        let film = Film::cropped(width, height, *tile);
+        integrator.crop(*tile);
+        let mut result = vec![vec![(Vec3::origin(), new_aov_pixel()); tile.width()]; tile.height()];
+        for pass in 0..passes {
+            integrator.prepare_pass(&scene, pass);
+            let integrator = integrator.as_ref();
 
-                    for _ in 0..aa_ray_count {
-                        let u: f64 = (f64::from(i) + rand::random::<f64>()) as f64 / f64::from(nx);
-                        let v: f64 = (f64::from(j) + rand::random::<f64>()) as f64 / f64::from(ny);
+            // Rows from the top, while the camera counts them from the bottom
+            let pass_result: Vec<Vec<(Vec3, AovPixel)>> = (tile.y0..tile.y1)
+                .into_par_iter()
+                .map(|y| {
+                    let j = height - 1 - y;
+                    (tile.x0..tile.x1)
+                        .into_par_iter()
+                        .map(|i| {
+                            let mut col = Vec3::new(0.0, 0.0, 0.0);
+                            let mut aov_pixel = new_aov_pixel();
 
-                        let r = cam.get_ray(u, v);
+                            for _ in 0..samples_per_pass {
+                                let du = rand::random::<f64>();
+                                let dv = rand::random::<f64>();
+                                let u: f64 = (i as f64 + du) / width as f64;
+                                let v: f64 = (j as f64 + dv) / height as f64;
 
-                        col += calculate_color(&r, &world, 0);
-                    }
+                                let r = scene.camera.get_ray(u, v);
 
-                    col /= f64::from(aa_ray_count);
-                    col = Vec3::new(col.x.sqrt(), col.y.sqrt(), col.z.sqrt());
+                                if aovs.is_empty() && expressions.is_empty() && mattes.is_empty() {
+                                    col += integrator.radiance(&r, &scene, &film);
+                                } else {
+                                    let mut sample = AovSample::at_first_hit(&r, &scene)
+                                        .with_expressions(&expressions);
+                                    let mut radiance = integrator.radiance_with_aovs(
+                                        &r,
+                                        &scene,
+                                        &film,
+                                        &mut sample,
+                                    );
+                                    if transparent {
+                                        // Premultiplied by alpha, without the background behind
+                                        let alpha = sample.get(Aov::Alpha).x;
+                                        radiance = radiance - sky(&r) * (1.0 - alpha);
+                                    }
+                                    col += radiance;
+                                    let offset = ((du - 0.5).powi(2) + (dv - 0.5).powi(2)).sqrt();
+                                    aov_pixel.add(&aovs, &sample, offset);
+                                }
+                            }
 
-                    let ir = (255.99 * col.x) as i64;
-                    let ig = (255.99 * col.y) as i64;
-                    let ib = (255.99 * col.z) as i64;
-                    pbar.inc(1);
-                    (ir, ig, ib)
+                            pbar.inc(1);
+                            (col, aov_pixel)
+                        })
+                        .collect()
                 })
+                .collect();
+
+            for (row, pass_row) in result.iter_mut().zip(pass_result) {
+                for ((col, aov_pixel), (pass_col, pass_aov_pixel)) in row.iter_mut().zip(pass_row) {
+                    *col += pass_col;
+                    aov_pixel.merge(&pass_aov_pixel);
+                }
+            }
+        }
+
+        // Light paths and Metropolis chains may have splatted anywhere
+        let (tile_width, tile_height) = (tile.width(), tile.height());
+        let pixels = || (0..tile_height).flat_map(|y| (0..tile_width).map(move |x| (x, y)));
+        let mut beauty: Vec<Vec3> = pixels()
+            .map(|(x, y)| {
+                let splat = film.splat(tile.x0 + x, height - 1 - (tile.y0 + y));
+                (result[y][x].0 + splat) / (samples_per_pass * passes) as f64
+            })
+            .collect();
+        let feature = |aov: Aov| -> Vec<Vec3> {
+            let index = aovs
+                .iter()
+                .position(|&a| a == aov)
+                .expect("added when needed");
+            pixels()
+                .map(|(x, y)| result[y][x].1.value(&aovs, index))
                 .collect()
-        })
-        .collect();
+        };
+        if denoise {
+            beauty = Denoiser::new().denoise(
+                tile_width,
+                tile_height,
+                &beauty,
+                &feature(Aov::Albedo),
+                &feature(Aov::Normal),
+            );
+        }
+        let beauty = |x: usize, y: usize| beauty[y * tile_width + x];
+        let alpha = if transparent {
+            feature(Aov::Alpha).iter().map(|alpha| alpha.x).collect()
+        } else {
+            vec![1.0; tile_width * tile_height]
+        };
+        let alpha = |x: usize, y: usize| alpha[y * tile_width + x];
+        let tile_names: Vec<BTreeSet<&str>> = (0..mattes.len())
+            .map(|index| {
+                result
+                    .iter()
+                    .flatten()
+                    .flat_map(|(_, pixel)| pixel.coverage(index).ranked())
+                    .map(|(name, _)| name)
+                    .collect()
+            })
+            .collect();
+        for (seen, names) in names_seen.iter_mut().zip(&tile_names) {
+            seen.extend(names);
+        }
+        let path = tile_path(tile);
 
-    let mut imgbuf = image::ImageBuffer::new(nx as u32, ny as u32);
+        if exr_output {
+            let components = |c: Vec3| [c.x as f32, c.y as f32, c.z as f32];
+            let mut channels = Vec::new();
+            for (name, component) in &[("R", 0), ("G", 1), ("B", 2)] {
+                let values = pixels()
+                    .map(|(x, y)| components(beauty(x, y))[*component])
+                    .collect();
+                channels.push(Channel::new(*name, values));
+            }
+            if transparent {
+                channels.push(Channel::new(
+                    "A",
+                    pixels().map(|(x, y)| alpha(x, y) as f32).collect(),
+                ));
+            }
+            for (index, aov) in aovs.iter().take(written_aovs).enumerate() {
+                if transparent && *aov == Aov::Alpha {
+                    continue;
+                }
+                for (component, channel) in aov.channels().iter().enumerate() {
+                    let name = match aov {
+                        Aov::Alpha => channel.to_string(),
+                        _ => format!("{}.{}", aov.name(), channel),
+                    };
+                    let values = pixels()
+                        .map(|(x, y)| components(result[y][x].1.value(&aovs, index))[component])
+                        .collect();
+                    channels.push(Channel::new(name, values));
+                }
+            }
+            for (index, name) in lpe_names.iter().enumerate() {
+                for (component, channel) in ["R", "G", "B"].iter().enumerate() {
+                    let values = pixels()
+                        .map(|(x, y)| components(result[y][x].1.matched(index))[component])
+                        .collect();
+                    channels.push(Channel::new(format!("{}.{}", name, channel), values));
+                }
+            }
+            let mut metadata = Vec::new();
+            for (index, (&matte, names)) in mattes.iter().zip(&tile_names).enumerate() {
+                let coverages: Vec<_> = pixels()
+                    .map(|(x, y)| result[y][x].1.coverage(index))
+                    .collect();
+                channels.extend(cryptomatte::channels(matte, &coverages));
+                metadata.extend(cryptomatte::metadata(matte, names.iter().cloned()));
+            }
 
-    for (r, row) in result.iter().rev().zip(imgbuf.rows_mut()) {
-        for (pixel_result, pix) in r.iter().zip(row) {
-            *pix = image::Rgb([
-                pixel_result.0 as u8,
-                pixel_result.1 as u8,
-                pixel_result.2 as u8,
-            ]);
+            exr::write(&path, full, *tile, &channels, &metadata)
+                .expect("failed to write to output path.");
+            continue;
         }
-    }
 
-    let output_path = matches.value_of("output").expect("has a default value");
+        let to_byte = |c: f64| (255.99 * c.clamp(0.0, 1.0)) as u8;
+        let mut imgbuf = image::ImageBuffer::new(tile_width as u32, tile_height as u32);
+        for (x, y, pix) in imgbuf.enumerate_pixels_mut() {
+            let (x, y) = (x as usize, y as usize);
+            // PNGs aren't premultiplied
+            let alpha = alpha(x, y);
+            let col = if alpha > 0.0 {
+                beauty(x, y) / alpha
+            } else {
+                Vec3::origin()
+            };
+
+            // Spectral rendering and emitters can go out of gamut, debug values are written as is
+            let gamma = |c: f64| {
+                to_byte(if debug_mode.is_some() {
+                    c
+                } else {
+                    c.max(0.0).sqrt()
+                })
+            };
+            *pix = image::Rgba([gamma(col.x), gamma(col.y), gamma(col.z), to_byte(alpha)]);
+        }
 
-    imgbuf
-        .save(output_path)
+        if transparent {
+            imgbuf.save(&path)
+        } else {
+            image::DynamicImage::ImageRgba8(imgbuf).to_rgb().save(&path)
+        }
         .expect("failed to write to output path.");
+
+        let save_pass = |name: &str, display: &dyn Fn(&AovPixel) -> Vec3| {
+            let mut imgbuf = image::ImageBuffer::new(tile_width as u32, tile_height as u32);
+            for (x, y, pix) in imgbuf.enumerate_pixels_mut() {
+                let col = display(&result[y as usize][x as usize].1);
+                *pix = image::Rgb([to_byte(col.x), to_byte(col.y), to_byte(col.z)]);
+            }
+            imgbuf
+                .save(pass_path(&path, name))
+                .expect("failed to write to output path.");
+        };
+
+        for (index, aov) in aovs.iter().take(written_aovs).enumerate() {
+            save_pass(aov.name(), &|pixel| {
+                aov.display(pixel.value(&aovs, index), extent)
+            });
+        }
+        for (index, name) in lpe_names.iter().enumerate() {
+            save_pass(name, &|pixel| {
+                let col = pixel.matched(index);
+                Vec3::new(
+                    col.x.max(0.0).sqrt(),
+                    col.y.max(0.0).sqrt(),
+                    col.z.max(0.0).sqrt(),
+                )
+            });
+        }
+        for (index, matte) in mattes.iter().enumerate() {
+            save_pass(&format!("crypto_{}", matte.name()), &|pixel| {
+                pixel.coverage(index).preview()
+            });
+        }
+    }
+
+    if tiles.len() == 1 {
+        return;
+    }
+    if exr_output {
+        let mut metadata = Vec::new();
+        for (&matte, names) in mattes.iter().zip(&names_seen) {
+            metadata.extend(cryptomatte::metadata(matte, names.iter().cloned()));
+        }
+        stitch_exr(&tiles, full, region, &tile_path, output_path, &metadata)
+            .expect("failed to write to output path.");
+    } else {
+        stitch_png(&tiles, region, &tile_path, output_path)
+            .expect("failed to write to output path.");
+        let pass_names = aovs
+            .iter()
+            .take(written_aovs)
+            .map(|aov| aov.name().to_string())
+            .chain(lpe_names.iter().cloned())
+            .chain(
+                mattes
+                    .iter()
+                    .map(|matte| format!("crypto_{}", matte.name())),
+            );
+        for name in pass_names {
+            stitch_png(
+                &tiles,
+                region,
+                &|tile| pass_path(&tile_path(tile), &name),
+                &pass_path(output_path, &name),
+            )
+            .expect("failed to write to output path.");
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use crate::ray::Ray;
+use crate::ray::{Ray, RayKind};
 use crate::vec3::Vec3;
 use std::f64;
 
@@ -40,9 +40,73 @@ impl Camera {
         }
     }
     pub fn get_ray(&self, u: f64, v: f64) -> Ray {
-        let orig = self.origin.clone();
+        let orig = self.origin;
         let result = self.lower_left_corner + (self.horizontal * u) + (self.vertical * v) - orig;
-        Ray::new(orig, result)
+        Ray::new(orig, result).with_kind(RayKind::Camera)
+    }
+
+    /// Viewing direction, through the center of the film.
+    pub fn forward(&self) -> Vec3 {
+        (self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0 - self.origin)
+            .make_unit_vec()
+    }
+
+    /// Distance of `p` from the camera along the viewing direction.
+    pub fn depth(&self, p: Vec3) -> f64 {
+        (p - self.origin).dot(self.forward())
+    }
+
+    /// Area of the film scaled to unit distance from the pinhole.
+    fn film_area(&self) -> f64 {
+        let center = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0;
+        let distance_squared = (center - self.origin).squared_length();
+        self.horizontal.length() * self.vertical.length() / distance_squared
+    }
+
+    /// Film coordinates, as taken by `get_ray`, and importance of a ray leaving the camera along
+    /// the unit vector `direction`. `None` if the direction misses the film.
+    ///
+    /// The importance is normalized so it integrates to one over the film, making light paths
+    /// splatted onto the camera comparable with camera paths.
+    pub fn importance(&self, direction: Vec3) -> Option<(f64, f64, f64)> {
+        let forward = self.forward();
+        let cos_theta = direction.dot(forward);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+
+        // Where the direction crosses the film plane
+        let to_corner = self.lower_left_corner - self.origin;
+        let on_film = direction * (to_corner.dot(forward) / cos_theta) - to_corner;
+        let u = on_film.dot(self.horizontal) / self.horizontal.squared_length();
+        let v = on_film.dot(self.vertical) / self.vertical.squared_length();
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+
+        Some((u, v, 1.0 / (self.film_area() * cos_theta.powi(4))))
+    }
+
+    /// Film coordinates where the point `p` is seen, and the importance the camera sends towards
+    /// it: `importance` times the cosine at the pinhole over the squared distance.
+    pub fn importance_at(&self, p: Vec3) -> Option<(f64, f64, f64)> {
+        let to_point = p - self.origin;
+        let distance_squared = to_point.squared_length();
+        let direction = to_point.make_unit_vec();
+        let (u, v, importance) = self.importance(direction)?;
+        Some((
+            u,
+            v,
+            importance * direction.dot(self.forward()) / distance_squared,
+        ))
+    }
+
+    /// Solid angle density of `get_ray` with uniform film coordinates producing `direction`.
+    pub fn importance_pdf(&self, direction: Vec3) -> f64 {
+        match self.importance(direction) {
+            Some(_) => 1.0 / (self.film_area() * direction.dot(self.forward()).powi(3)),
+            None => 0.0,
+        }
     }
 }
 
@@ -57,3 +121,29 @@ impl Default for Camera {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::camera::Camera;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn importance_finds_film_coordinates() {
+        let camera = Camera::new(
+            Vec3::new(3.0, 2.0, 1.0),
+            Vec3::origin(),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            1.5,
+            0.0,
+            4.0,
+        );
+
+        let ray = camera.get_ray(0.25, 0.75);
+        let (u, v, _) = camera
+            .importance(ray.direction.make_unit_vec())
+            .expect("ray leaves through the film");
+        assert!((u - 0.25).abs() < 1e-9 && (v - 0.75).abs() < 1e-9);
+        assert!(camera.importance(ray.direction * -1.0).is_none());
+    }
+}
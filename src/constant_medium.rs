@@ -0,0 +1,104 @@
+use crate::aabb::Aabb;
+use crate::hitable::HitRecord;
+use crate::hitable::Hitable;
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Fog or smoke of uniform density filling a closed boundary object.
+///
+/// Rays entering the boundary travel an exponentially distributed distance before scattering
+/// off the phase function, or pass through if that distance is past the far side of the volume.
+pub struct ConstantMedium {
+    boundary: Box<dyn Hitable>,
+    density: f64,
+    phase_function: Box<dyn Material + Send + Sync>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Box<dyn Hitable>,
+        density: f64,
+        phase_function: Box<dyn Material + Send + Sync>,
+    ) -> ConstantMedium {
+        ConstantMedium {
+            boundary,
+            density,
+            phase_function,
+        }
+    }
+}
+
+impl Hitable for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        // Find where the ray enters and leaves the boundary, the ray may start inside.
+        let entry = self.boundary.hit(ray, f64::MIN, f64::MAX)?;
+        let exit = self.boundary.hit(ray, entry.t + 0.0001, f64::MAX)?;
+
+        let t_enter = entry.t.max(t_min);
+        let t_exit = exit.t.min(t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+        let t_enter = t_enter.max(0.0);
+
+        let ray_length = ray.direction.length();
+        let distance_inside_boundary = (t_exit - t_enter) * ray_length;
+        let hit_distance = -(1.0 - sampler::random()).ln() / self.density;
+
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+
+        // Normal is meaningless inside a volume, the phase function ignores it.
+        Some(HitRecord::new(
+            t,
+            ray.point_at_parameter(t),
+            Vec3::new(1.0, 0.0, 0.0),
+            0.0,
+            0.0,
+            self.phase_function.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::constant_medium::ConstantMedium;
+    use crate::hitable::Hitable;
+    use crate::materials::isotropic::Isotropic;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn transmittance_follows_beer_lambert() {
+        let density = 0.5;
+        let fog = ConstantMedium::new(
+            Box::new(Sphere::new(
+                Vec3::origin(),
+                1.0,
+                Box::new(Isotropic::new(Vec3::new(1.0, 1.0, 1.0))),
+            )),
+            density,
+            Box::new(Isotropic::new(Vec3::new(1.0, 1.0, 1.0))),
+        );
+        // Through the center, two units of fog
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+
+        let samples = 20_000;
+        let passed = (0..samples)
+            .filter(|_| fog.hit(&ray, 0.001, f64::MAX).is_none())
+            .count();
+        let transmittance = passed as f64 / samples as f64;
+        assert!((transmittance - (-density * 2.0f64).exp()).abs() < 0.015);
+    }
+}
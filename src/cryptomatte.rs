@@ -0,0 +1,297 @@
+use crate::aov::false_colour;
+use crate::exr::Channel;
+use crate::hitable::HitRecord;
+use crate::vec3::Vec3;
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+/// Number of IDs kept per pixel, the most covering first.
+pub const DEPTH: usize = 6;
+
+/// What the IDs of a Cryptomatte pass (Friedman and Jones 2015) stand for. Only named objects
+/// and materials get an ID, see `object::Object::with_name` and `materials::named::Named`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Matte {
+    Object,
+    Material,
+}
+
+impl Matte {
+    pub const ALL: &'static [Matte] = &[Matte::Object, Matte::Material];
+
+    pub const NAMES: &'static [&'static str] = &["object", "material"];
+
+    pub fn name(self) -> &'static str {
+        Matte::NAMES[self as usize]
+    }
+
+    /// Name of the pass in the files, prefixing its layers.
+    pub fn type_name(self) -> &'static str {
+        match self {
+            Matte::Object => "CryptoObject",
+            Matte::Material => "CryptoMaterial",
+        }
+    }
+
+    /// Name of what `hit` hits.
+    pub fn name_of<'a>(self, hit: &HitRecord<'a>) -> Option<&'a str> {
+        match self {
+            Matte::Object => hit.object,
+            Matte::Material => hit.material.name(),
+        }
+    }
+}
+
+impl FromStr for Matte {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Matte, String> {
+        match Matte::NAMES.iter().position(|&known| known == name) {
+            Some(index) => Ok(Matte::ALL[index]),
+            None => Err(format!(
+                "unknown matte `{}`, expected one of {}",
+                name,
+                Matte::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// 32-bit MurmurHash3 with a seed of 0.
+fn murmur3(key: &[u8]) -> u32 {
+    let scramble = |k: u32| {
+        k.wrapping_mul(0xcc9e_2d51)
+            .rotate_left(15)
+            .wrapping_mul(0x1b87_3593)
+    };
+
+    let mut h = 0u32;
+    let chunks = key.chunks_exact(4);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        let k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        h = (h ^ scramble(k))
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe654_6b64);
+    }
+    if !tail.is_empty() {
+        let k = tail
+            .iter()
+            .enumerate()
+            .fold(0, |k, (i, &byte)| k | u32::from(byte) << (8 * i));
+        h ^= scramble(k);
+    }
+
+    h ^= key.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
+
+/// Hash of `name`, with the exponent bits changed when they would make the ID a denormal,
+/// infinite or NaN float.
+pub fn hash(name: &str) -> u32 {
+    let hash = murmur3(name.as_bytes());
+    match (hash >> 23) & 0xFF {
+        0 | 255 => hash ^ (1 << 23),
+        _ => hash,
+    }
+}
+
+/// ID of `name` stored in the passes, the bits of its `hash`.
+pub fn id(name: &str) -> f32 {
+    f32::from_bits(hash(name))
+}
+
+/// Names seen by the samples of a pixel, with how many samples saw each.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage<'a> {
+    names: Vec<(&'a str, usize)>,
+    samples: usize,
+}
+
+impl<'a> Coverage<'a> {
+    pub fn new() -> Coverage<'a> {
+        Coverage::default()
+    }
+
+    /// Adds a sample seeing `name`, or nothing with a name.
+    pub fn add(&mut self, name: Option<&'a str>) {
+        if let Some(name) = name {
+            self.add_samples(name, 1);
+        }
+        self.samples += 1;
+    }
+
+    fn add_samples(&mut self, name: &'a str, samples: usize) {
+        match self.names.iter_mut().find(|(known, _)| *known == name) {
+            Some((_, count)) => *count += samples,
+            None => self.names.push((name, samples)),
+        }
+    }
+
+    /// Adds the samples of `other`, for the same pixel.
+    pub fn merge(&mut self, other: &Coverage<'a>) {
+        for &(name, samples) in &other.names {
+            self.add_samples(name, samples);
+        }
+        self.samples += other.samples;
+    }
+
+    /// Names seen, with the fraction of the pixel they cover, the most covering first.
+    pub fn ranked(&self) -> Vec<(&'a str, f64)> {
+        let mut ranked: Vec<(&'a str, f64)> = self
+            .names
+            .iter()
+            .map(|&(name, count)| (name, count as f64 / self.samples as f64))
+            .collect();
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).expect("no NaN").then(a.0.cmp(b.0)));
+        ranked
+    }
+
+    /// The colours of the names blended by coverage, to look at the pass in 8-bit images.
+    pub fn preview(&self) -> Vec3 {
+        self.ranked()
+            .iter()
+            .fold(Vec3::origin(), |colour, &(name, coverage)| {
+                colour + false_colour(u64::from(hash(name))) * coverage
+            })
+    }
+}
+
+/// Layers `<type>00` to `<type>02` of a pass, each holding the ID and coverage of two ranks in
+/// its RGBA channels, for `pixels` in the order of the image.
+pub fn channels(matte: Matte, pixels: &[&Coverage<'_>]) -> Vec<Channel> {
+    let ranked: Vec<Vec<(&str, f64)>> = pixels.iter().map(|pixel| pixel.ranked()).collect();
+    let mut channels = Vec::new();
+    for rank in 0..DEPTH {
+        let layer = format!("{}{:02}", matte.type_name(), rank / 2);
+        let (id_channel, coverage_channel) = if rank % 2 == 0 {
+            ("R", "G")
+        } else {
+            ("B", "A")
+        };
+        let ids = ranked
+            .iter()
+            .map(|names| names.get(rank).map_or(0.0, |&(name, _)| id(name)))
+            .collect();
+        let coverages = ranked
+            .iter()
+            .map(|names| {
+                names
+                    .get(rank)
+                    .map_or(0.0, |&(_, coverage)| coverage as f32)
+            })
+            .collect();
+        channels.push(Channel::new(format!("{}.{}", layer, id_channel), ids));
+        channels.push(Channel::new(
+            format!("{}.{}", layer, coverage_channel),
+            coverages,
+        ));
+    }
+    channels
+}
+
+/// Quotes `text` as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Header attributes describing a pass to compositing software, with the manifest mapping the
+/// `names` to their hashes.
+pub fn metadata<'a>(
+    matte: Matte,
+    names: impl IntoIterator<Item = &'a str>,
+) -> Vec<(String, String)> {
+    let names: BTreeSet<&str> = names.into_iter().collect();
+    let manifest = names
+        .iter()
+        .map(|name| format!("{}:\"{:08x}\"", json_string(name), hash(name)))
+        .collect::<Vec<String>>()
+        .join(",");
+
+    let key = format!("{:08x}", murmur3(matte.type_name().as_bytes()));
+    let prefix = format!("cryptomatte/{}/", &key[..7]);
+    vec![
+        (prefix.clone() + "name", matte.type_name().to_string()),
+        (prefix.clone() + "hash", "MurmurHash3_32".to_string()),
+        (
+            prefix.clone() + "conversion",
+            "uint32_to_float32".to_string(),
+        ),
+        (prefix + "manifest", format!("{{{}}}", manifest)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cryptomatte::{channels, hash, metadata, murmur3, Coverage, Matte};
+
+    #[test]
+    fn hashes_names_to_valid_floats() {
+        assert_eq!(murmur3(b""), 0);
+        assert_eq!(murmur3(b"hello"), 0x248b_fa47);
+        assert_eq!(
+            murmur3(b"The quick brown fox jumps over the lazy dog"),
+            0x2e4f_f723
+        );
+
+        for name in &["", "floor", "bunny", "glass sphere"] {
+            let id = f32::from_bits(hash(name));
+            assert!(id.is_normal(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn ranks_names_by_coverage() {
+        let mut pixel = Coverage::new();
+        pixel.add(Some("floor"));
+        pixel.add(None);
+        let mut other = Coverage::new();
+        other.add(Some("ball"));
+        other.add(Some("ball"));
+        pixel.merge(&other);
+
+        assert_eq!(pixel.ranked(), vec![("ball", 0.5), ("floor", 0.25)]);
+
+        let channels = channels(Matte::Object, &[&pixel]);
+        let names: Vec<&str> = channels.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            &names[..4],
+            &[
+                "CryptoObject00.R",
+                "CryptoObject00.G",
+                "CryptoObject00.B",
+                "CryptoObject00.A"
+            ]
+        );
+        assert_eq!(channels[0].values[0].to_bits(), hash("ball"));
+        assert_eq!(channels[3].values[0], 0.25);
+        assert_eq!(channels[5].values[0], 0.0);
+
+        let metadata = metadata(Matte::Object, vec!["floor", "ball", "floor"]);
+        let manifest = &metadata[3];
+        assert!(manifest.0.starts_with("cryptomatte/") && manifest.0.ends_with("/manifest"));
+        assert_eq!(
+            manifest.1,
+            format!(
+                "{{\"ball\":\"{:08x}\",\"floor\":\"{:08x}\"}}",
+                hash("ball"),
+                hash("floor")
+            )
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use crate::aabb::Aabb;
+use crate::hitable::HitRecord;
+use crate::hitable::Hitable;
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Axis aligned box made of six faces sharing one material.
+/// Normals point outwards, and every face gets its own [0, 1] UV square.
+pub struct Cuboid {
+    bounds: Aabb,
+    material: Box<dyn Material + Send + Sync>,
+}
+
+impl Cuboid {
+    pub fn new(p0: Vec3, p1: Vec3, material: Box<dyn Material + Send + Sync>) -> Cuboid {
+        Cuboid {
+            bounds: Aabb::from_points(&[p0, p1]),
+            material,
+        }
+    }
+
+    fn record(&self, ray: &Ray, t: f64, axis: usize, sign: f64) -> HitRecord<'_> {
+        let p = ray.point_at_parameter(t);
+        let min = self.bounds.min;
+        let size = self.bounds.max - self.bounds.min;
+        // Flat boxes have a zero extent, their faces all map to 0 along it
+        let ratio = |offset: f64, extent: f64| if extent > 0.0 { offset / extent } else { 0.0 };
+        let relative = Vec3::new(
+            ratio(p.x - min.x, size.x),
+            ratio(p.y - min.y, size.y),
+            ratio(p.z - min.z, size.z),
+        );
+
+        let (normal, u, v) = match axis {
+            0 => (Vec3::new(sign, 0.0, 0.0), relative.z, relative.y),
+            1 => (Vec3::new(0.0, sign, 0.0), relative.x, relative.z),
+            _ => (Vec3::new(0.0, 0.0, sign), relative.x, relative.y),
+        };
+
+        HitRecord::new(t, p, normal, u, v, self.material.as_ref())
+    }
+}
+
+impl Hitable for Cuboid {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (min, max) = (self.bounds.min, self.bounds.max);
+        let slabs = [
+            (min.x, max.x, ray.origin.x, ray.direction.x),
+            (min.y, max.y, ray.origin.y, ray.direction.y),
+            (min.z, max.z, ray.origin.z, ray.direction.z),
+        ];
+
+        // (t, axis, normal sign) of the face where the ray enters and leaves the box.
+        let mut near = (f64::MIN, 0, 0.0);
+        let mut far = (f64::MAX, 0, 0.0);
+
+        for (axis, (min, max, origin, direction)) in slabs.iter().enumerate() {
+            if *direction == 0.0 {
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+
+            let inv_d = 1.0 / direction;
+            let t_at_min = (min - origin) * inv_d;
+            let t_at_max = (max - origin) * inv_d;
+
+            let (entry, exit) = if inv_d < 0.0 {
+                ((t_at_max, axis, 1.0), (t_at_min, axis, -1.0))
+            } else {
+                ((t_at_min, axis, -1.0), (t_at_max, axis, 1.0))
+            };
+
+            if entry.0 > near.0 {
+                near = entry;
+            }
+            if exit.0 < far.0 {
+                far = exit;
+            }
+        }
+
+        if near.0 > far.0 {
+            return None;
+        }
+
+        if near.0 > t_min && near.0 < t_max {
+            return Some(self.record(ray, near.0, near.1, near.2));
+        }
+
+        // Ray starts inside the box
+        if far.0 > t_min && far.0 < t_max {
+            return Some(self.record(ray, far.0, far.1, far.2));
+        }
+
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cuboid::Cuboid;
+    use crate::hitable::Hitable;
+    use crate::materials::lambertian::Lambertian;
+    use crate::ray::Ray;
+    use crate::vec3::Vec3;
+
+    fn unit_cube() -> Cuboid {
+        Cuboid::new(
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(-1.0, -1.0, -1.0),
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn hits_entry_face_with_outward_normal() {
+        let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let cube = unit_cube();
+        let hit = cube
+            .hit(&ray, 0.001, f64::MAX)
+            .expect("ray points at the cube");
+
+        assert_eq!(hit.t, 4.0);
+        assert_eq!(hit.normal, Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!((hit.u, hit.v), (0.5, 0.5));
+    }
+
+    #[test]
+    fn hits_exit_face_from_inside() {
+        let ray = Ray::new(Vec3::origin(), Vec3::new(0.0, 0.0, -1.0));
+        let cube = unit_cube();
+        let hit = cube.hit(&ray, 0.001, f64::MAX).expect("ray starts inside");
+
+        assert_eq!(hit.t, 1.0);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn flat_box_has_finite_uvs() {
+        let flat = Cuboid::new(
+            Vec3::new(-1.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let ray = Ray::new(Vec3::new(0.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = flat
+            .hit(&ray, 0.001, f64::MAX)
+            .expect("ray points at the box");
+
+        assert_eq!(hit.t, 5.0);
+        assert_eq!((hit.u, hit.v), (0.75, 0.5));
+    }
+
+    #[test]
+    fn misses_beside_the_cube() {
+        let ray = Ray::new(Vec3::new(-5.0, 2.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(unit_cube().hit(&ray, 0.001, f64::MAX).is_none());
+    }
+}
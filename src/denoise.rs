@@ -0,0 +1,170 @@
+use crate::vec3::Vec3;
+use rayon::prelude::*;
+
+/// B3 spline taps of the à-trous filter.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Reflectance below which a channel isn't divided out of the image before filtering.
+const MIN_ALBEDO: f64 = 1e-3;
+
+/// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010), smoothing noise away while
+/// keeping the edges found in the albedo and normal passes of the first surface seen.
+///
+/// The image is divided by the albedo before filtering and multiplied back after, so textures
+/// stay sharp and only the lighting gets blurred. Every iteration doubles the spacing of the
+/// 5x5 taps and halves the colour tolerance, removing coarser noise. What's seen in mirrors and
+/// through glass has no features of its own and is only kept sharp by the colour tolerance.
+#[derive(Debug, Clone)]
+pub struct Denoiser {
+    iterations: usize,
+    colour_sigma: f64,
+    normal_sigma: f64,
+    albedo_sigma: f64,
+}
+
+impl Denoiser {
+    pub fn new() -> Denoiser {
+        Denoiser {
+            iterations: 5,
+            colour_sigma: 0.5,
+            normal_sigma: 0.1,
+            albedo_sigma: 0.1,
+        }
+    }
+
+    /// Number of passes, the filter reaches `2^iterations * 2` pixels away.
+    pub fn with_iterations(mut self, iterations: usize) -> Denoiser {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Relative difference in lighting at which neighbours stop being averaged in, smaller keeps
+    /// more detail and more noise.
+    pub fn with_colour_sigma(mut self, sigma: f64) -> Denoiser {
+        self.colour_sigma = sigma;
+        self
+    }
+
+    /// Differences in normal and albedo at which neighbours stop being averaged in.
+    pub fn with_feature_sigmas(mut self, normal: f64, albedo: f64) -> Denoiser {
+        self.normal_sigma = normal;
+        self.albedo_sigma = albedo;
+        self
+    }
+
+    /// Filters the `width` by `height` image `colour`, with its `albedo` and `normal` passes
+    /// stored the same way.
+    pub fn denoise(
+        &self,
+        width: usize,
+        height: usize,
+        colour: &[Vec3],
+        albedo: &[Vec3],
+        normal: &[Vec3],
+    ) -> Vec<Vec3> {
+        let demodulate = |c: f64, a: f64| if a > MIN_ALBEDO { c / a } else { c };
+        let mut lighting: Vec<Vec3> = colour
+            .iter()
+            .zip(albedo)
+            .map(|(c, a)| {
+                Vec3::new(
+                    demodulate(c.x, a.x),
+                    demodulate(c.y, a.y),
+                    demodulate(c.z, a.z),
+                )
+            })
+            .collect();
+
+        let mut colour_sigma = self.colour_sigma;
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            lighting = (0..width * height)
+                .into_par_iter()
+                .map(|p| {
+                    let (x, y) = (p % width, p / width);
+                    let mut sum = Vec3::origin();
+                    let mut total = 0.0;
+                    for (dy, ky) in KERNEL.iter().enumerate() {
+                        for (dx, kx) in KERNEL.iter().enumerate() {
+                            let qx = x as isize + (dx as isize - 2) * step;
+                            let qy = y as isize + (dy as isize - 2) * step;
+                            if qx < 0 || qy < 0 || qx >= width as isize || qy >= height as isize {
+                                continue;
+                            }
+
+                            let q = qy as usize * width + qx as usize;
+                            let distance = |a: Vec3, b: Vec3, sigma: f64| {
+                                (a - b).squared_length() / (sigma * sigma)
+                            };
+                            // Relative, so bright and dark areas are smoothed alike
+                            let (lp, lq) = (lighting[p].luminance(), lighting[q].luminance());
+                            let colour = (lp - lq) / ((lp + lq) / 2.0 + 1e-6) / colour_sigma;
+                            let weight = kx
+                                * ky
+                                * (-colour * colour
+                                    - distance(normal[p], normal[q], self.normal_sigma)
+                                    - distance(albedo[p], albedo[q], self.albedo_sigma))
+                                .exp();
+                            sum += lighting[q] * weight;
+                            total += weight;
+                        }
+                    }
+                    sum / total
+                })
+                .collect();
+            colour_sigma /= 2.0;
+        }
+
+        let remodulate = |c: f64, a: f64| if a > MIN_ALBEDO { c * a } else { c };
+        lighting
+            .iter()
+            .zip(albedo)
+            .map(|(c, a)| {
+                Vec3::new(
+                    remodulate(c.x, a.x),
+                    remodulate(c.y, a.y),
+                    remodulate(c.z, a.z),
+                )
+            })
+            .collect()
+    }
+}
+
+impl Default for Denoiser {
+    fn default() -> Denoiser {
+        Denoiser::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::denoise::Denoiser;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn smooths_noise_but_not_edges() {
+        let (width, height) = (32, 32);
+        // Two materials side by side, under the same noisy light
+        let albedo: Vec<Vec3> = (0..width * height)
+            .map(|p| {
+                if p % width < width / 2 {
+                    Vec3::new(0.2, 0.2, 0.2)
+                } else {
+                    Vec3::new(0.8, 0.8, 0.8)
+                }
+            })
+            .collect();
+        let normal = vec![Vec3::new(0.0, 1.0, 0.0); width * height];
+        let colour: Vec<Vec3> = albedo
+            .iter()
+            .enumerate()
+            .map(|(p, a)| *a * if p % 3 == 0 { 1.3 } else { 0.85 })
+            .collect();
+
+        let denoised = Denoiser::new().denoise(width, height, &colour, &albedo, &normal);
+
+        for (p, (denoised, a)) in denoised.iter().zip(&albedo).enumerate() {
+            assert!((denoised.x - a.x).abs() < 0.05 * a.x, "pixel {}", p);
+        }
+    }
+}
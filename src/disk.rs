@@ -0,0 +1,152 @@
+use crate::aabb::Aabb;
+use crate::hitable::HitRecord;
+use crate::hitable::{Hitable, SurfaceSample};
+use crate::materials::Material;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Flat disk facing `normal`, hits on its back get the same normal.
+/// `u` runs around the disk and `v` goes from the center (0) to the rim (1).
+pub struct Disk {
+    center: Vec3,
+    radius: f64,
+    frame: Onb,
+    material: Box<dyn Material + Send + Sync>,
+}
+
+impl Disk {
+    pub fn new(
+        center: Vec3,
+        normal: Vec3,
+        radius: f64,
+        material: Box<dyn Material + Send + Sync>,
+    ) -> Disk {
+        Disk {
+            center,
+            radius,
+            frame: Onb::from_w(normal),
+            material,
+        }
+    }
+}
+
+impl Hitable for Disk {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let normal = self.frame.w;
+        let denominator = normal.dot(ray.direction);
+
+        // Parallel to the plane
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.center - ray.origin).dot(normal) / denominator;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let p = ray.point_at_parameter(t);
+        let local = self.frame.to_local(p - self.center);
+        let distance = (local.x * local.x + local.y * local.y).sqrt();
+        if distance > self.radius {
+            return None;
+        }
+
+        let phi = local.y.atan2(local.x);
+        let u = (phi + f64::consts::PI) / (2.0 * f64::consts::PI);
+        let v = if self.radius > 0.0 {
+            distance / self.radius
+        } else {
+            0.0
+        };
+
+        Some(HitRecord::new(t, p, normal, u, v, self.material.as_ref()))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // Extent of a disk along each axis is radius * sqrt(1 - n_axis^2).
+        let n = self.frame.w;
+        let extent = Vec3::new(
+            self.radius * (1.0 - n.x * n.x).max(0.0).sqrt(),
+            self.radius * (1.0 - n.y * n.y).max(0.0).sqrt(),
+            self.radius * (1.0 - n.z * n.z).max(0.0).sqrt(),
+        );
+
+        Some(Aabb::new(self.center - extent, self.center + extent).padded(0.0001))
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample<'_>> {
+        let area = f64::consts::PI * self.radius * self.radius;
+        if area <= 0.0 {
+            return None;
+        }
+
+        let (u, v) = (sampler::random(), sampler::random().sqrt());
+        let phi = 2.0 * f64::consts::PI * u - f64::consts::PI;
+        let local = Vec3::new(phi.cos(), phi.sin(), 0.0) * (v * self.radius);
+        Some(SurfaceSample {
+            hit: HitRecord::new(
+                0.0,
+                self.center + self.frame.local(local),
+                self.frame.w,
+                u,
+                v,
+                self.material.as_ref(),
+            ),
+            area,
+        })
+    }
+
+    fn normal_cone(&self) -> (Vec3, f64) {
+        (self.frame.w, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disk::Disk;
+    use crate::hitable::Hitable;
+    use crate::materials::lambertian::Lambertian;
+    use crate::ray::Ray;
+    use crate::vec3::Vec3;
+
+    fn disk(radius: f64) -> Disk {
+        Disk::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            radius,
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn hits_with_v_from_center_to_rim() {
+        let ray = Ray::new(Vec3::new(1.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let disk = disk(2.0);
+        let hit = disk
+            .hit(&ray, 0.001, f64::MAX)
+            .expect("ray points at the disk");
+
+        assert_eq!(hit.t, 2.0);
+        assert_eq!(hit.normal, Vec3::new(0.0, 1.0, 0.0));
+        assert!((hit.v - 0.5).abs() < 1e-12);
+        assert!((0.0..=1.0).contains(&hit.u));
+    }
+
+    #[test]
+    fn misses_past_the_rim() {
+        let ray = Ray::new(Vec3::new(1.5, 3.0, 1.5), Vec3::new(0.0, -1.0, 0.0));
+        assert!(disk(2.0).hit(&ray, 0.001, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn zero_radius_disk_has_finite_uvs() {
+        let ray = Ray::new(Vec3::new(0.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        if let Some(hit) = disk(0.0).hit(&ray, 0.001, f64::MAX) {
+            assert!(hit.u.is_finite() && hit.v.is_finite());
+        }
+    }
+}
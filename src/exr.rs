@@ -0,0 +1,471 @@
+use crate::region::Region;
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Longest channel or attribute name, without the long names flag only 31 bytes are allowed.
+pub const MAX_NAME_LENGTH: usize = 255;
+
+/// Channel of an image, `width * height` values from the top row down.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    /// Full name, layers are separated by dots like in `diffuse_direct.R`.
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+impl Channel {
+    pub fn new(name: impl Into<String>, values: Vec<f32>) -> Channel {
+        Channel {
+            name: name.into(),
+            values,
+        }
+    }
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+fn window(region: Region) -> Vec<u8> {
+    [
+        region.x0 as i32,
+        region.y0 as i32,
+        region.x1 as i32 - 1,
+        region.y1 as i32 - 1,
+    ]
+    .iter()
+    .flat_map(|v| v.to_le_bytes().to_vec())
+    .collect()
+}
+
+/// Writes an uncompressed scanline OpenEXR file of 32-bit floats one row at a time, so images
+/// never have to be held in memory whole.
+pub struct Writer<W: Write> {
+    out: W,
+    /// Position in the rows given of each channel of the file.
+    order: Vec<usize>,
+    data: Region,
+    y: usize,
+}
+
+impl<W: Write> Writer<W> {
+    /// Writes the header of an image with the channels `names`, covering `data` out of the
+    /// `display` window, with the `metadata` as string attributes. Names can be up to
+    /// `MAX_NAME_LENGTH` bytes long, longer ones are an `InvalidInput` error.
+    pub fn new(
+        mut out: W,
+        display: Region,
+        data: Region,
+        names: &[String],
+        metadata: &[(String, String)],
+    ) -> io::Result<Writer<W>> {
+        // The format wants channels in alphabetical order
+        let mut order: Vec<usize> = (0..names.len()).collect();
+        order.sort_by(|&a, &b| names[a].cmp(&names[b]));
+
+        let longest = names
+            .iter()
+            .chain(metadata.iter().map(|(name, _)| name))
+            .map(|name| name.len())
+            .max()
+            .unwrap_or(0);
+        if longest > MAX_NAME_LENGTH {
+            return Err(unusable("name too long for OpenEXR"));
+        }
+
+        // Magic number and version 2, single part scanlines, with the long names flag if needed
+        let flags = if longest > 31 { 0x04 } else { 0 };
+        let mut header = vec![0x76, 0x2f, 0x31, 0x01, 2, flags, 0, 0];
+
+        let mut list = Vec::new();
+        for &index in &order {
+            list.extend_from_slice(names[index].as_bytes());
+            list.push(0);
+            // FLOAT pixels, not perceptually linear, no subsampling
+            list.extend_from_slice(&2i32.to_le_bytes());
+            list.extend_from_slice(&[0, 0, 0, 0]);
+            list.extend_from_slice(&1i32.to_le_bytes());
+            list.extend_from_slice(&1i32.to_le_bytes());
+        }
+        list.push(0);
+        attribute(&mut header, "channels", "chlist", &list);
+        attribute(&mut header, "compression", "compression", &[0]);
+        attribute(&mut header, "dataWindow", "box2i", &window(data));
+        attribute(&mut header, "displayWindow", "box2i", &window(display));
+        attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        attribute(
+            &mut header,
+            "pixelAspectRatio",
+            "float",
+            &1f32.to_le_bytes(),
+        );
+        attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        attribute(
+            &mut header,
+            "screenWindowWidth",
+            "float",
+            &1f32.to_le_bytes(),
+        );
+        for (name, value) in metadata {
+            attribute(&mut header, name, "string", value.as_bytes());
+        }
+        header.push(0);
+
+        // One scanline per block, after the table of their offsets
+        let block_size = 8 + 4 * data.width() * names.len();
+        let first_block = header.len() + 8 * data.height();
+        for y in 0..data.height() {
+            header.extend_from_slice(&((first_block + y * block_size) as u64).to_le_bytes());
+        }
+        out.write_all(&header)?;
+
+        Ok(Writer {
+            out,
+            order,
+            data,
+            y: 0,
+        })
+    }
+
+    /// Writes the next row from the top, with the values of every channel in the order of
+    /// their names. Rows of the wrong width or past the bottom are an `InvalidInput` error.
+    pub fn write_row(&mut self, row: &[&[f32]]) -> io::Result<()> {
+        if self.y >= self.data.height() {
+            return Err(unusable("more rows than the data window"));
+        }
+        if row.len() != self.order.len() || row.iter().any(|r| r.len() != self.data.width()) {
+            return Err(unusable(
+                "row not as wide as the data window for every channel",
+            ));
+        }
+        let mut block = Vec::with_capacity(8 + 4 * self.data.width() * row.len());
+        block.extend_from_slice(&((self.data.y0 + self.y) as i32).to_le_bytes());
+        block.extend_from_slice(&((4 * self.data.width() * row.len()) as i32).to_le_bytes());
+        for &index in &self.order {
+            for value in row[index] {
+                block.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        self.y += 1;
+        self.out.write_all(&block)
+    }
+
+    /// Checks every row was written, and gives the output back.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.y != self.data.height() {
+            return Err(unusable("rows missing"));
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Writes `channels` covering `data` out of the `display` window to `out` through a `Writer`.
+fn write_channels<W: Write>(
+    out: W,
+    display: Region,
+    data: Region,
+    channels: &[Channel],
+    metadata: &[(String, String)],
+) -> io::Result<W> {
+    if channels.iter().any(|c| c.values.len() != data.area()) {
+        return Err(unusable("channel not the size of the data window"));
+    }
+    let names: Vec<String> = channels.iter().map(|c| c.name.clone()).collect();
+
+    let mut writer = Writer::new(out, display, data, &names, metadata)?;
+    for y in 0..data.height() {
+        let row: Vec<&[f32]> = channels
+            .iter()
+            .map(|c| &c.values[y * data.width()..(y + 1) * data.width()])
+            .collect();
+        writer.write_row(&row)?;
+    }
+    writer.finish()
+}
+
+/// Encodes `channels` covering `data` out of the `display` window in memory, see `write`.
+pub fn encode(
+    display: Region,
+    data: Region,
+    channels: &[Channel],
+    metadata: &[(String, String)],
+) -> io::Result<Vec<u8>> {
+    write_channels(Vec::new(), display, data, channels, metadata)
+}
+
+/// Writes `channels` covering `data` out of the `display` window to `path`, a row at a time.
+pub fn write(
+    path: impl AsRef<Path>,
+    display: Region,
+    data: Region,
+    channels: &[Channel],
+    metadata: &[(String, String)],
+) -> io::Result<()> {
+    let out = BufWriter::new(File::create(path)?);
+    write_channels(out, display, data, channels, metadata).map(|_| ())
+}
+
+fn unusable(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads back the channels of a file written by `write`, with its data window. Other files are
+/// only read when stored the same way, uncompressed scanlines of 32-bit floats.
+pub fn decode(file: &[u8]) -> io::Result<(Region, Vec<Channel>)> {
+    let mut at = 8;
+    let bytes = |at: usize, length: usize| {
+        at.checked_add(length)
+            .and_then(|end| file.get(at..end))
+            .ok_or_else(|| invalid("truncated file"))
+    };
+    let string = |at: &mut usize| -> io::Result<String> {
+        let length = file[(*at).min(file.len())..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| invalid("unterminated string"))?;
+        let text = String::from_utf8_lossy(&file[*at..*at + length]).into_owned();
+        *at += length + 1;
+        Ok(text)
+    };
+    let int = |bytes: &[u8]| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+    if file.len() < 8 || file[..4] != [0x76, 0x2f, 0x31, 0x01] {
+        return Err(invalid("not an OpenEXR file"));
+    }
+    let mut names = Vec::new();
+    let mut data = None;
+    loop {
+        let name = string(&mut at)?;
+        if name.is_empty() {
+            break;
+        }
+        let kind = string(&mut at)?;
+        let size =
+            usize::try_from(int(bytes(at, 4)?)).map_err(|_| invalid("negative attribute size"))?;
+        let value = bytes(at + 4, size)?;
+        at += 4 + size;
+        match (name.as_str(), kind.as_str()) {
+            ("channels", "chlist") => {
+                let mut list = 0;
+                while value.get(list).is_some_and(|&b| b != 0) {
+                    let end = list
+                        + value[list..]
+                            .iter()
+                            .position(|&b| b == 0)
+                            .ok_or_else(|| invalid("unterminated channel name"))?;
+                    names.push(String::from_utf8_lossy(&value[list..end]).into_owned());
+                    if value.len() < end + 17 || int(&value[end + 1..]) != 2 {
+                        return Err(invalid("only 32-bit float channels are supported"));
+                    }
+                    list = end + 17;
+                }
+            }
+            ("compression", _) if value != [0] => {
+                return Err(invalid("only uncompressed files are supported"))
+            }
+            ("dataWindow", "box2i") if value.len() == 16 => {
+                let bound = |i: usize| usize::try_from(int(&value[4 * i..]));
+                data = match (bound(0), bound(1), bound(2), bound(3)) {
+                    (Ok(x0), Ok(y0), Ok(x1), Ok(y1)) if x0 <= x1 && y0 <= y1 => {
+                        Some(Region::new(x0, y0, x1 + 1, y1 + 1))
+                    }
+                    _ => return Err(invalid("invalid data window")),
+                };
+            }
+            _ => {}
+        }
+    }
+    let data = data.ok_or_else(|| invalid("no data window"))?;
+
+    // The window is only checked against the size of the file while reading, so nothing is
+    // allocated for it up front
+    let mut channels: Vec<Channel> = names
+        .into_iter()
+        .map(|name| Channel::new(name, Vec::new()))
+        .collect();
+    for y in 0..data.height() {
+        let offset = bytes(at + 8 * y, 8)?;
+        let mut block = usize::try_from(u64::from_le_bytes([
+            offset[0], offset[1], offset[2], offset[3], offset[4], offset[5], offset[6], offset[7],
+        ]))
+        .ok()
+        .and_then(|offset| offset.checked_add(8))
+        .ok_or_else(|| invalid("truncated file"))?;
+        for channel in &mut channels {
+            for _ in 0..data.width() {
+                let value = bytes(block, 4)?;
+                channel
+                    .values
+                    .push(f32::from_le_bytes([value[0], value[1], value[2], value[3]]));
+                block += 4;
+            }
+        }
+    }
+    Ok((data, channels))
+}
+
+/// Reads the file at `path`, see `decode`.
+pub fn read(path: impl AsRef<Path>) -> io::Result<(Region, Vec<Channel>)> {
+    decode(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::exr::{decode, encode, Channel, Writer, MAX_NAME_LENGTH};
+    use crate::region::Region;
+    use std::convert::TryInto;
+    use std::io;
+
+    #[test]
+    fn stores_scanlines_after_offset_table() {
+        let channels = [
+            Channel::new("G", vec![3.0, 4.0]),
+            Channel::new("B", vec![1.0, 2.0]),
+        ];
+        let metadata = [("owner".to_string(), "me".to_string())];
+        let file = encode(Region::full(1, 2), Region::full(1, 2), &channels, &metadata)
+            .expect("encodable");
+
+        assert_eq!(&file[..4], &[0x76, 0x2f, 0x31, 0x01]);
+        let owner = b"owner\0string\0\x02\0\0\0me";
+        assert!(file.windows(owner.len()).any(|w| w == owner));
+        // Two blocks of line number, size and one float per channel
+        let blocks = &file[file.len() - 2 * 16..];
+        let offset = &file[file.len() - 48..file.len() - 40];
+        let first_offset = u64::from_le_bytes(offset.try_into().expect("8 bytes"));
+        assert_eq!(first_offset as usize, file.len() - 32);
+
+        let float = |at: usize| f32::from_le_bytes(blocks[at..at + 4].try_into().expect("4 bytes"));
+        // Blue sorts before green
+        assert_eq!((float(8), float(12)), (1.0, 3.0));
+        assert_eq!((float(24), float(28)), (2.0, 4.0));
+    }
+
+    #[test]
+    fn reads_back_cropped_images() {
+        let channels = [
+            Channel::new("R", vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+            Channel::new("A", vec![0.5; 6]),
+        ];
+        let data = Region::new(2, 1, 5, 3);
+        let file = encode(Region::full(8, 4), data, &channels, &[]).expect("encodable");
+
+        let (window, read) = decode(&file).expect("valid file");
+        assert_eq!(window, data);
+        assert_eq!(read.len(), 2);
+        assert_eq!(
+            (read[0].name.as_str(), &read[0].values),
+            ("A", &channels[1].values)
+        );
+        assert_eq!(
+            (read[1].name.as_str(), &read[1].values),
+            ("R", &channels[0].values)
+        );
+        assert!(decode(&file[..file.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn flags_long_names() {
+        let short = encode(
+            Region::full(1, 1),
+            Region::full(1, 1),
+            &[Channel::new("R", vec![1.0])],
+            &[],
+        )
+        .expect("encodable");
+        assert_eq!(short[5], 0);
+
+        let name = format!("{}.R", "x".repeat(40));
+        let channels = [Channel::new(name.as_str(), vec![1.0])];
+        let file =
+            encode(Region::full(1, 1), Region::full(1, 1), &channels, &[]).expect("encodable");
+        assert_eq!(file[5] & 0x04, 0x04);
+        let (_, read) = decode(&file).expect("valid file");
+        assert_eq!(read[0].name, name);
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let file = encode(
+            Region::full(2, 2),
+            Region::full(2, 2),
+            &[Channel::new("R", vec![1.0; 4])],
+            &[],
+        )
+        .expect("encodable");
+        let window = b"dataWindow\0box2i\0";
+        let at = file
+            .windows(window.len())
+            .position(|w| w == window)
+            .expect("a data window")
+            + window.len();
+        let patched = |at: usize, value: i32| {
+            let mut file = file.clone();
+            file[at..at + 4].copy_from_slice(&value.to_le_bytes());
+            file
+        };
+
+        // Attribute size, then xMin, yMin, xMax and yMax
+        assert!(decode(&patched(at, -1)).is_err());
+        assert!(decode(&patched(at, i32::MAX)).is_err());
+        assert!(decode(&patched(at + 4, -1)).is_err());
+        assert!(decode(&patched(at + 12, -1)).is_err());
+        assert!(decode(&patched(at + 16, -5)).is_err());
+        // A window far larger than the file runs out of offsets
+        assert!(decode(&patched(at + 12, i32::MAX)).is_err());
+        assert!(decode(&patched(at + 16, i32::MAX)).is_err());
+        assert!(decode(&file).is_ok());
+    }
+
+    #[test]
+    fn rejects_misuse_of_the_writer() {
+        let region = Region::full(2, 2);
+        let names = ["R".to_string(), "G".to_string()];
+        let long = ["x".repeat(MAX_NAME_LENGTH + 1)];
+        let error = Writer::new(Vec::new(), region, region, &long, &[]).err();
+        assert_eq!(error.map(|e| e.kind()), Some(io::ErrorKind::InvalidInput));
+
+        let mut writer = Writer::new(Vec::new(), region, region, &names, &[]).expect("header");
+        let narrow = writer.write_row(&[&[1.0], &[1.0]]);
+        assert_eq!(
+            narrow.map_err(|e| e.kind()),
+            Err(io::ErrorKind::InvalidInput)
+        );
+        let missing = writer.write_row(&[&[1.0, 2.0]]);
+        assert_eq!(
+            missing.map_err(|e| e.kind()),
+            Err(io::ErrorKind::InvalidInput)
+        );
+
+        writer.write_row(&[&[1.0, 2.0], &[3.0, 4.0]]).expect("row");
+        let short = Writer::new(Vec::new(), region, region, &names, &[]).expect("header");
+        assert_eq!(
+            short.finish().err().map(|e| e.kind()),
+            Some(io::ErrorKind::InvalidInput)
+        );
+
+        writer.write_row(&[&[1.0, 2.0], &[3.0, 4.0]]).expect("row");
+        let extra = writer.write_row(&[&[1.0, 2.0], &[3.0, 4.0]]);
+        assert_eq!(
+            extra.map_err(|e| e.kind()),
+            Err(io::ErrorKind::InvalidInput)
+        );
+        assert!(decode(&writer.finish().expect("every row")).is_ok());
+
+        let channels = [Channel::new("R", vec![1.0; 3])];
+        let error = encode(region, region, &channels, &[]).err();
+        assert_eq!(error.map(|e| e.kind()), Some(io::ErrorKind::InvalidInput));
+    }
+}
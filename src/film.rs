@@ -0,0 +1,68 @@
+use crate::region::Region;
+use crate::vec3::Vec3;
+use std::sync::Mutex;
+
+/// Collects radiance that can land on any pixel, like light paths connected to the camera, while
+/// the pixels themselves are rendered in parallel.
+pub struct Film {
+    width: usize,
+    height: usize,
+    region: Region,
+    splats: Mutex<Vec<Vec3>>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Film {
+        Film::cropped(width, height, Region::full(width, height))
+    }
+
+    /// Film of a `width` by `height` image only keeping the radiance landing in `region`, when
+    /// only its pixels are rendered.
+    pub fn cropped(width: usize, height: usize, region: Region) -> Film {
+        Film {
+            width,
+            height,
+            region,
+            splats: Mutex::new(vec![Vec3::origin(); region.area()]),
+        }
+    }
+
+    /// Index in `splats` of pixel `i`, `j`, counting rows from the bottom, if it's in the region.
+    fn index(&self, i: usize, j: usize) -> Option<usize> {
+        let y = self.height - 1 - j;
+        if !self.region.contains(i, y) {
+            return None;
+        }
+        Some((y - self.region.y0) * self.region.width() + i - self.region.x0)
+    }
+
+    /// Adds radiance at the film coordinates `u` and `v` taken by `Camera::get_ray`.
+    pub fn add_splat(&self, u: f64, v: f64, radiance: Vec3) {
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return;
+        }
+
+        let i = (u * self.width as f64) as usize;
+        let j = (v * self.height as f64) as usize;
+        if let Some(index) = self.index(i, j) {
+            let mut splats = self
+                .splats
+                .lock()
+                .expect("no thread panics holding the lock");
+            splats[index] += radiance;
+        }
+    }
+
+    /// Radiance splatted onto pixel `i`, `j`, counting rows from the bottom like `Camera::get_ray`.
+    ///
+    /// Splats come from paths started by the rendered pixels, so they're scaled up to what the
+    /// whole image would have sent when only a region is rendered.
+    pub fn splat(&self, i: usize, j: usize) -> Vec3 {
+        let index = self.index(i, j).expect("pixel in the rendered region");
+        let scale = (self.width * self.height) as f64 / self.region.area() as f64;
+        self.splats
+            .lock()
+            .expect("no thread panics holding the lock")[index]
+            * scale
+    }
+}
@@ -0,0 +1,241 @@
+use crate::aabb::Aabb;
+use crate::hitable::HitRecord;
+use crate::hitable::Hitable;
+use crate::materials::henyey_greenstein::HenyeyGreenstein;
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::Vec3;
+use crate::voxel_grid::VoxelGrid;
+
+/// Emission of a volume, eg. fire. Radiance is `color * scale * grid value`.
+pub struct VolumeEmission {
+    pub grid: VoxelGrid,
+    pub color: Vec3,
+    pub scale: f64,
+}
+
+/// Clouds, smoke or fire with density varying according to a voxel grid stretched over `bounds`.
+///
+/// Collisions are found with delta tracking against the grid maximum, so the result is unbiased
+/// regardless of how the density varies. Shadow rays estimate the transmittance by ratio tracking
+/// instead, which is also unbiased but less noisy than the all or nothing of a collision. At a
+/// collision the path always scatters off the
+/// phase function, weighted by `albedo`, and picks up the emission of the absorbed `1 - albedo`.
+pub struct HeterogeneousMedium {
+    bounds: Aabb,
+    density: VoxelGrid,
+    density_scale: f64,
+    phase_function: HenyeyGreenstein,
+    emission: Option<VolumeEmission>,
+}
+
+impl HeterogeneousMedium {
+    pub fn new(
+        bounds: Aabb,
+        density: VoxelGrid,
+        density_scale: f64,
+        phase_function: HenyeyGreenstein,
+    ) -> HeterogeneousMedium {
+        HeterogeneousMedium {
+            bounds,
+            density,
+            density_scale,
+            phase_function,
+            emission: None,
+        }
+    }
+
+    pub fn with_emission(mut self, emission: VolumeEmission) -> HeterogeneousMedium {
+        self.emission = Some(emission);
+        self
+    }
+
+    fn to_grid_space(&self, p: Vec3) -> Vec3 {
+        (p - self.bounds.min) / (self.bounds.max - self.bounds.min)
+    }
+
+    fn majorant(&self) -> f64 {
+        self.density.max_value() * self.density_scale
+    }
+
+    /// Extinction coefficient at a world space position.
+    pub fn density_at(&self, p: Vec3) -> f64 {
+        self.density.lookup(self.to_grid_space(p)) * self.density_scale
+    }
+}
+
+impl Hitable for HeterogeneousMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let majorant = self.majorant();
+        if majorant <= 0.0 {
+            return None;
+        }
+
+        let (t_enter, t_exit) = self.bounds.hit_interval(ray, t_min, t_max)?;
+        let ray_length = ray.direction.length();
+
+        // Delta tracking: take steps against the majorant and accept real collisions
+        // with probability density / majorant.
+        let mut t = t_enter;
+        loop {
+            t -= (1.0 - sampler::random()).ln() / (majorant * ray_length);
+            if t >= t_exit {
+                return None;
+            }
+
+            let p = ray.point_at_parameter(t);
+            if sampler::random() < self.density_at(p) / majorant {
+                // Normal is meaningless inside a volume, the phase function ignores it.
+                return Some(HitRecord::new(
+                    t,
+                    p,
+                    Vec3::new(1.0, 0.0, 0.0),
+                    0.0,
+                    0.0,
+                    self,
+                ));
+            }
+        }
+    }
+
+    /// Ratio tracking: takes the same steps as delta tracking and multiplies by the chance of
+    /// every one of them being a null collision.
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let majorant = self.majorant();
+        let (t_enter, t_exit) = match self.bounds.hit_interval(ray, t_min, t_max) {
+            Some(interval) if majorant > 0.0 => interval,
+            _ => return 1.0,
+        };
+        let ray_length = ray.direction.length();
+
+        let mut transmittance = 1.0;
+        let mut t = t_enter;
+        loop {
+            t -= (1.0 - sampler::random()).ln() / (majorant * ray_length);
+            if t >= t_exit {
+                return transmittance;
+            }
+
+            transmittance *= 1.0 - self.density_at(ray.point_at_parameter(t)) / majorant;
+            // Russian roulette, so dense volumes don't take steps for nothing
+            if transmittance < 0.1 {
+                if sampler::random() < 0.5 {
+                    return 0.0;
+                }
+                transmittance *= 2.0;
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+impl Material for HeterogeneousMedium {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        self.phase_function.scatter(r_in, hit_record)
+    }
+
+    fn bsdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Option<Vec3> {
+        self.phase_function.bsdf(r_in, hit_record, direction)
+    }
+
+    fn pdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        self.phase_function.pdf(r_in, hit_record, direction)
+    }
+
+    fn emitted(&self, _r_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        match &self.emission {
+            Some(emission) => {
+                let absorbed = Vec3::new(1.0, 1.0, 1.0) - self.phase_function.albedo;
+                let radiance = emission
+                    .grid
+                    .lookup(self.to_grid_space(hit_record.position));
+                absorbed * emission.color * (emission.scale * radiance)
+            }
+            None => Vec3::origin(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::Aabb;
+    use crate::heterogeneous_medium::{HeterogeneousMedium, VolumeEmission};
+    use crate::hitable::{HitRecord, Hitable};
+    use crate::materials::henyey_greenstein::HenyeyGreenstein;
+    use crate::materials::Material;
+    use crate::ray::Ray;
+    use crate::vec3::Vec3;
+    use crate::voxel_grid::VoxelGrid;
+
+    /// Two units deep along x, with the density rising along y so the majorant overestimates it
+    /// halfway up.
+    fn slab(density: Vec<f32>) -> HeterogeneousMedium {
+        HeterogeneousMedium::new(
+            Aabb::new(Vec3::origin(), Vec3::new(2.0, 1.0, 1.0)),
+            VoxelGrid::new(1, 2, 1, density),
+            1.0,
+            HenyeyGreenstein::new(Vec3::new(1.0, 1.0, 1.0), 0.0),
+        )
+    }
+
+    #[test]
+    fn ratio_tracking_estimates_transmittance() {
+        let medium = slab(vec![0.25, 1.0]);
+        let ray = Ray::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        let density = medium.density_at(Vec3::new(1.0, 0.5, 0.5));
+        assert!((density - 0.625).abs() < 1e-9);
+
+        let samples = 20_000;
+        let transmittance = (0..samples)
+            .map(|_| medium.transmittance(&ray, 0.001, f64::MAX))
+            .sum::<f64>()
+            / samples as f64;
+        assert!((transmittance - (-density * 2.0).exp()).abs() < 0.01);
+    }
+
+    #[test]
+    fn delta_tracking_matches_beer_lambert() {
+        let medium = slab(vec![0.5, 0.5]);
+        let ray = Ray::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0));
+
+        let samples = 100_000;
+        let passed = (0..samples)
+            .filter(|_| medium.hit(&ray, 0.001, f64::MAX).is_none())
+            .count();
+        let transmittance = passed as f64 / samples as f64;
+        assert!((transmittance - (-0.5 * 2.0_f64).exp()).abs() < 0.01);
+    }
+
+    #[test]
+    fn emits_what_it_absorbs() {
+        let medium = HeterogeneousMedium::new(
+            Aabb::new(Vec3::origin(), Vec3::new(2.0, 1.0, 1.0)),
+            VoxelGrid::new(1, 1, 1, vec![1.0]),
+            1.0,
+            HenyeyGreenstein::new(Vec3::new(0.75, 0.5, 0.25), 0.0),
+        )
+        .with_emission(VolumeEmission {
+            grid: VoxelGrid::new(1, 2, 1, vec![0.25, 1.0]),
+            color: Vec3::new(1.0, 2.0, 4.0),
+            scale: 2.0,
+        });
+        let ray = Ray::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        let hit = HitRecord::new(
+            2.0,
+            Vec3::new(1.0, 0.5, 0.5),
+            Vec3::new(1.0, 0.0, 0.0),
+            0.0,
+            0.0,
+            &medium,
+        );
+
+        // (1 - albedo) * color * scale * grid, the grid is 0.625 halfway up
+        let emitted = medium.emitted(&ray, &hit);
+        let expected = Vec3::new(0.25 * 1.0, 0.5 * 2.0, 0.75 * 4.0) * (2.0 * 0.625);
+        assert!((emitted - expected).length() < 1e-6, "{:?}", emitted);
+    }
+}
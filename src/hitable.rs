@@ -1,29 +1,73 @@
+use crate::aabb::Aabb;
 use crate::materials::Material;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
+use std::f64;
 
 pub struct HitRecord<'a> {
     pub t: f64,
     pub position: Vec3,
+    /// Geometric normal, fixed by the orientation of the shape whichever side the ray hits it
+    /// from, see `facing_normal`.
     pub normal: Vec3,
-    pub material: &'a (dyn Material),
+    pub u: f64,
+    pub v: f64,
+    pub material: &'a dyn Material,
+    /// Indices in `Scene::lights` of the only lights illuminating the hit object, `None` for all
+    /// of them.
+    pub lights: Option<&'a [usize]>,
+    /// Name of the hit object, selecting it in ID mattes.
+    pub object: Option<&'a str>,
 }
 
 impl<'a> HitRecord<'a> {
-    pub fn new(t: f64, position: Vec3, normal: Vec3, material: &'a (dyn Material)) -> HitRecord {
+    pub fn new(
+        t: f64,
+        position: Vec3,
+        normal: Vec3,
+        u: f64,
+        v: f64,
+        material: &'a dyn Material,
+    ) -> HitRecord<'a> {
         HitRecord {
             t,
             position,
             normal,
+            u,
+            v,
             material,
+            lights: None,
+            object: None,
         }
     }
+
+    /// `normal` turned towards the side `r_in` arrives from, for two sided materials.
+    pub fn facing_normal(&self, r_in: &Ray) -> Vec3 {
+        if r_in.direction.dot(self.normal) > 0.0 {
+            self.normal * -1.0
+        } else {
+            self.normal
+        }
+    }
+
+    /// Whether the light at `index` in `Scene::lights` illuminates the hit object.
+    pub fn lit_by(&self, index: usize) -> bool {
+        self.lights.is_none_or(|lights| lights.contains(&index))
+    }
+}
+
+/// Point picked uniformly over the surface of a shape, see `Hitable::sample_surface`.
+pub struct SurfaceSample<'a> {
+    /// The point as if a ray had hit it, `t` is meaningless.
+    pub hit: HitRecord<'a>,
+    /// Area of the whole surface, the density of the sample is its inverse.
+    pub area: f64,
 }
 
 pub type HitableList = Vec<Box<dyn Hitable>>;
 
 impl Hitable for HitableList {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let mut closest_so_far = t_max;
         let mut closest_hit_record: Option<HitRecord> = None;
 
@@ -39,8 +83,57 @@ impl Hitable for HitableList {
 
         closest_hit_record
     }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut transmittance = 1.0;
+        for hitable in self.iter() {
+            transmittance *= hitable.transmittance(ray, t_min, t_max);
+            if transmittance == 0.0 {
+                break;
+            }
+        }
+        transmittance
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut hitables = self.iter();
+        let first = hitables.next()?.bounding_box()?;
+
+        hitables.try_fold(first, |acc, hitable| {
+            hitable
+                .bounding_box()
+                .map(|bbox| Aabb::surrounding_box(acc, bbox))
+        })
+    }
 }
 
 pub trait Hitable: Send + Sync {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>>;
+
+    /// Fraction of the light along `ray` between `t_min` and `t_max` getting through, for shadow
+    /// rays. Surfaces block all of it, volumes can estimate the fraction instead of picking a
+    /// collision.
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        if self.hit(ray, t_min, t_max).is_some() {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    /// Box enclosing the object, `None` for objects without finite extent.
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /// Picks a point uniformly over the surface, so emissive shapes can start light paths.
+    /// `None` for objects that can't be sampled.
+    fn sample_surface(&self) -> Option<SurfaceSample<'_>> {
+        None
+    }
+
+    /// Cone around the normals of the surface, as a unit axis and a half angle in radians, so
+    /// emissive shapes can be skipped by the points they face away from. Defaults to every
+    /// direction.
+    fn normal_cone(&self) -> (Vec3, f64) {
+        (Vec3::new(0.0, 0.0, 1.0), f64::consts::PI)
+    }
 }
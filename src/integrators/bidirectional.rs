@@ -0,0 +1,827 @@
+use crate::camera::Camera;
+use crate::film::Film;
+use crate::hitable::{HitRecord, Hitable};
+use crate::integrators::{sky, Integrator};
+use crate::lights::{Light, LightSample};
+use crate::onb::{random_cosine_direction, Onb};
+use crate::ray::{Ray, RayKind};
+use crate::sampler;
+use crate::scene::{Emitter, Scene};
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Bidirectional path tracer (Veach 1997).
+///
+/// Every sample traces a path from the camera and one from a light picked by power, and connects
+/// every prefix of one to every prefix of the other. All the ways of building the same path are
+/// weighted against each other with the power heuristic. Light prefixes connected straight to the
+/// camera land on arbitrary pixels and are splatted onto the film, that's where caustics come from.
+///
+/// Lights with a position and emissive shapes that can be sampled (`Hitable::sample_surface`)
+/// start paths. Other emissive objects, the sky and infinitely distant lights are only found from
+/// the camera side. Light links apply to the first surface light paths from lights land on, like
+/// direct lighting. Absorbing dielectric interiors are ignored.
+#[derive(Debug)]
+pub struct BidirectionalPathTracer {
+    max_depth: usize,
+}
+
+impl BidirectionalPathTracer {
+    pub fn new() -> BidirectionalPathTracer {
+        BidirectionalPathTracer { max_depth: 10 }
+    }
+
+    /// Maximum number of bounces, the work per sample grows with its square.
+    pub fn with_max_depth(mut self, max_depth: usize) -> BidirectionalPathTracer {
+        self.max_depth = max_depth;
+        self
+    }
+}
+
+impl Default for BidirectionalPathTracer {
+    fn default() -> BidirectionalPathTracer {
+        BidirectionalPathTracer::new()
+    }
+}
+
+enum VertexKind<'a> {
+    Camera,
+    Light(&'a dyn Light),
+    /// Point on an emissive surface starting a light path.
+    Emitter(HitRecord<'a>),
+    Surface(HitRecord<'a>),
+}
+
+struct Vertex<'a> {
+    kind: VertexKind<'a>,
+    position: Vec3,
+    /// Ray the vertex was reached by, materials need its direction.
+    r_in: Ray,
+    /// Throughput of the path up to the vertex, divided by the sampling densities.
+    beta: Vec3,
+    /// Scatters specularly, so it can't be connected to.
+    delta: bool,
+    /// Area density of sampling the vertex from the previous one on its path, and the reverse.
+    pdf_fwd: f64,
+    pdf_rev: f64,
+    /// Area density of a light path starting at the vertex, for camera vertices on emissive
+    /// surfaces that can be sampled.
+    emitter_pdf: f64,
+}
+
+impl<'a> Vertex<'a> {
+    fn endpoint(kind: VertexKind<'a>, position: Vec3, pdf_fwd: f64) -> Vertex<'a> {
+        Vertex {
+            kind,
+            position,
+            r_in: Ray::new(position, Vec3::origin()),
+            beta: Vec3::new(1.0, 1.0, 1.0),
+            delta: false,
+            pdf_fwd,
+            pdf_rev: 0.0,
+            emitter_pdf: 0.0,
+        }
+    }
+
+    /// Whether the light at `index` in `Scene::lights` illuminates the vertex.
+    fn lit_by(&self, index: usize) -> bool {
+        match &self.kind {
+            VertexKind::Surface(hit) => hit.lit_by(index),
+            _ => true,
+        }
+    }
+
+    /// Turns a solid angle density of sampling the direction to `next` into an area density.
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let to_next = next.position - self.position;
+        let distance_squared = to_next.squared_length();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+
+        match &next.kind {
+            VertexKind::Surface(hit) | VertexKind::Emitter(hit) => {
+                pdf * hit.normal.dot(to_next).abs() / (distance_squared * distance_squared.sqrt())
+            }
+            _ => pdf / distance_squared,
+        }
+    }
+
+    /// Area density of light leaving the vertex towards `next`, when a light path starts there.
+    fn emission_pdf(&self, next: &Vertex) -> f64 {
+        let direction = (next.position - self.position).make_unit_vec();
+        let pdf = match &self.kind {
+            VertexKind::Camera => 0.0,
+            VertexKind::Light(light) => light.emission_pdf(direction),
+            // Emissive surfaces send light paths out with a cosine distribution
+            VertexKind::Surface(hit) | VertexKind::Emitter(hit) => {
+                hit.normal.dot(direction).max(0.0) / f64::consts::PI
+            }
+        };
+
+        self.convert_density(pdf, next)
+    }
+
+    /// Area density of this vertex sampling `next`, when it was reached from `previous`.
+    fn pdf(&self, previous: Option<&Vertex>, next: &Vertex, camera: &Camera) -> f64 {
+        let direction = (next.position - self.position).make_unit_vec();
+        let pdf = match &self.kind {
+            VertexKind::Camera => camera.importance_pdf(direction),
+            VertexKind::Light(_) | VertexKind::Emitter(_) => return self.emission_pdf(next),
+            VertexKind::Surface(hit) => match previous {
+                Some(previous) => {
+                    let r_in = Ray::new(previous.position, self.position - previous.position);
+                    hit.material.pdf(&r_in, hit, direction)
+                }
+                None => 0.0,
+            },
+        };
+
+        self.convert_density(pdf, next)
+    }
+
+    /// BSDF times cosine for light leaving towards `next`.
+    fn f(&self, next: Vec3) -> Vec3 {
+        match &self.kind {
+            VertexKind::Surface(hit) => {
+                let direction = (next - self.position).make_unit_vec();
+                hit.material
+                    .bsdf(&self.r_in, hit, direction)
+                    .unwrap_or_else(Vec3::origin)
+            }
+            _ => Vec3::origin(),
+        }
+    }
+}
+
+/// Extends `path` by following scattered rays, starting with `ray` leaving its last vertex in a
+/// direction sampled with solid angle density `pdf`. Returns the emission found along the way,
+/// except from surfaces that can start light paths, which is weighted against them instead.
+fn random_walk<'a>(
+    scene: &'a Scene,
+    mut ray: Ray,
+    mut beta: Vec3,
+    mut pdf: f64,
+    max_vertices: usize,
+    path: &mut Vec<Vertex<'a>>,
+) -> Vec3 {
+    let mut emitted = Vec3::origin();
+
+    while path.len() < max_vertices {
+        let (index, hit) = match scene.hit_object(&ray, 0.001, f64::MAX) {
+            Some(found) => found,
+            None => return emitted + beta * sky(&ray),
+        };
+        let emitter_pdf = scene.surface_emitter_pdf(index);
+        if emitter_pdf == 0.0 {
+            emitted += beta * hit.material.emitted(&ray, &hit);
+        }
+        let scattered = hit.material.scatter(&ray, &hit);
+
+        let mut vertex = Vertex {
+            position: hit.position,
+            kind: VertexKind::Surface(hit),
+            r_in: ray,
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            emitter_pdf,
+        };
+        let previous = path.last().expect("paths start at an endpoint");
+        vertex.pdf_fwd = previous.convert_density(pdf, &vertex);
+        path.push(vertex);
+
+        let (attenuation, scattered) = match scattered {
+            Some(scattered) if path.len() < max_vertices => scattered,
+            _ => break,
+        };
+        let direction = scattered.direction.make_unit_vec();
+
+        let n = path.len();
+        let vertex = &path[n - 1];
+        let (delta, pdf_fwd, pdf_rev) = match &vertex.kind {
+            VertexKind::Surface(hit) if hit.material.bsdf(&ray, hit, direction).is_some() => {
+                let reverse = Ray::new(vertex.position, direction * -1.0);
+                let towards_previous = ray.direction.make_unit_vec() * -1.0;
+                (
+                    false,
+                    hit.material.pdf(&ray, hit, direction),
+                    hit.material.pdf(&reverse, hit, towards_previous),
+                )
+            }
+            _ => (true, 0.0, 0.0),
+        };
+        let pdf_rev = vertex.convert_density(pdf_rev, &path[n - 2]);
+        path[n - 2].pdf_rev = pdf_rev;
+        path[n - 1].delta = delta;
+
+        beta = beta * attenuation;
+        if beta == Vec3::origin() {
+            break;
+        }
+        ray = Ray::new(scattered.origin, direction);
+        pdf = pdf_fwd;
+    }
+
+    emitted
+}
+
+/// Fraction of the light between `from` and `to` getting through.
+fn visibility(scene: &Scene, from: Vec3, to: Vec3) -> f64 {
+    let to = to - from;
+    let distance = to.length();
+    scene.world.transmittance(
+        &Ray::new(from, to / distance).with_kind(RayKind::Shadow),
+        0.001,
+        distance - 0.001,
+    )
+}
+
+/// Fraction of the light of `sample` reaching `from`.
+fn light_visibility(scene: &Scene, from: Vec3, sample: &LightSample) -> f64 {
+    scene.world.transmittance(
+        &Ray::new(from, sample.direction).with_kind(RayKind::Shadow),
+        0.001,
+        sample.distance - 0.001,
+    )
+}
+
+/// Power heuristic weight of connecting the first `s` light vertices to the first `t` camera
+/// vertices, against every other strategy that builds the same path. `sampled_light` replaces the
+/// light vertex when it was sampled for the connection. Without light vertices, the camera path
+/// hit an emissive surface.
+fn mis_weight(
+    light_path: &[Vertex],
+    camera_path: &[Vertex],
+    sampled_light: Option<&Vertex>,
+    s: usize,
+    t: usize,
+    camera: &Camera,
+) -> f64 {
+    // Light vertices are never connected straight to the camera, emitters seen directly are only
+    // found by camera paths
+    if s + t == 2 {
+        return 1.0;
+    }
+
+    let qs = if s >= 1 {
+        Some(sampled_light.unwrap_or(&light_path[s - 1]))
+    } else {
+        None
+    };
+    let pt = &camera_path[t - 1];
+    let qs_minus = if s >= 2 {
+        Some(&light_path[s - 2])
+    } else {
+        None
+    };
+    let pt_minus = if t >= 2 {
+        Some(&camera_path[t - 2])
+    } else {
+        None
+    };
+
+    // The connection changes the reverse densities around it
+    let (pt_rev, pt_minus_rev) = match qs {
+        Some(qs) => (
+            qs.pdf(qs_minus, pt, camera),
+            pt_minus.map_or(0.0, |vertex| pt.pdf(Some(qs), vertex, camera)),
+        ),
+        None => (
+            pt.emitter_pdf,
+            pt_minus.map_or(0.0, |vertex| pt.emission_pdf(vertex)),
+        ),
+    };
+
+    let remap = |pdf: f64| if pdf == 0.0 { 1.0 } else { pdf };
+    let ratio = |pdf_rev: f64, pdf_fwd: f64| (remap(pdf_rev) / remap(pdf_fwd)).powi(2);
+
+    let mut sum = 0.0;
+    let mut r = 1.0;
+    for i in (1..t).rev() {
+        let pdf_rev = if i == t - 1 {
+            pt_rev
+        } else if i == t - 2 {
+            pt_minus_rev
+        } else {
+            camera_path[i].pdf_rev
+        };
+        r *= ratio(pdf_rev, camera_path[i].pdf_fwd);
+
+        let delta = i != t - 1 && camera_path[i].delta;
+        if !delta && !camera_path[i - 1].delta {
+            sum += r;
+        }
+    }
+
+    if let Some(qs) = qs {
+        let qs_rev = pt.pdf(pt_minus, qs, camera);
+        let qs_minus_rev = qs_minus.map_or(0.0, |vertex| qs.pdf(Some(pt), vertex, camera));
+        // Lights that only have a position can't be hit by camera paths
+        let light = if s == 1 { qs } else { &light_path[0] };
+        let delta_light = matches!(light.kind, VertexKind::Light(_));
+
+        r = 1.0;
+        for i in (0..s).rev() {
+            let vertex = if i == s - 1 { qs } else { &light_path[i] };
+            let pdf_rev = if i == s - 1 {
+                qs_rev
+            } else if i == s - 2 {
+                qs_minus_rev
+            } else {
+                vertex.pdf_rev
+            };
+            r *= ratio(pdf_rev, vertex.pdf_fwd);
+
+            let delta = i != s - 1 && vertex.delta;
+            let delta_previous = if i == 0 {
+                delta_light
+            } else {
+                light_path[i - 1].delta
+            };
+            if !delta && !delta_previous {
+                sum += r;
+            }
+        }
+    }
+
+    1.0 / (1.0 + sum)
+}
+
+impl BidirectionalPathTracer {
+    /// Starts `light_path` at a light or emissive surface picked by power, and extends it.
+    fn trace_light_path<'a>(&self, scene: &'a Scene, light_path: &mut Vec<Vertex<'a>>) {
+        let mut linked_light = None;
+        let (endpoint, ray, beta, pdf) = match scene.sample_emitter(sampler::random()) {
+            Some((Emitter::Light(index, light), probability)) => {
+                linked_light = Some(index);
+                let emission = match light.sample_emission().filter(|e| e.pdf > 0.0) {
+                    Some(emission) => emission,
+                    None => return,
+                };
+                (
+                    Vertex::endpoint(VertexKind::Light(light), emission.ray.origin, probability),
+                    emission.ray,
+                    emission.intensity / (probability * emission.pdf),
+                    emission.pdf,
+                )
+            }
+            Some((Emitter::Surface(_, object), probability)) => {
+                let sample = match object.sample_surface() {
+                    Some(sample) => sample,
+                    None => return,
+                };
+                let hit = sample.hit;
+                let direction = Onb::from_w(hit.normal).local(random_cosine_direction());
+                let pdf = hit.normal.dot(direction) / f64::consts::PI;
+                if pdf <= 0.0 {
+                    return;
+                }
+
+                // The cosine of the direction cancels out with its density
+                let position_pdf = probability / sample.area;
+                let towards = Ray::new(hit.position + direction, direction * -1.0);
+                let radiance = hit.material.emitted(&towards, &hit);
+                let position = hit.position;
+                (
+                    Vertex::endpoint(VertexKind::Emitter(hit), position, position_pdf),
+                    Ray::new(position, direction),
+                    radiance * (f64::consts::PI / position_pdf),
+                    pdf,
+                )
+            }
+            None => return,
+        };
+
+        light_path.push(endpoint);
+        random_walk(scene, ray, beta, pdf, self.max_depth + 1, light_path);
+
+        // Light links only decide which objects the light reaches directly
+        if let Some(index) = linked_light {
+            if light_path
+                .get(1)
+                .is_some_and(|vertex| !vertex.lit_by(index))
+            {
+                light_path.truncate(1);
+            }
+        }
+    }
+
+    /// Light from a point on `light` reaching `pt`, with the vertex on the light.
+    fn sample_light<'a>(
+        scene: &Scene,
+        pt: &Vertex,
+        light: &'a dyn Light,
+        probability: f64,
+    ) -> Option<(Vertex<'a>, Vec3)> {
+        let sample = light.sample(pt.position)?;
+        let position = pt.position + sample.direction * sample.distance;
+        let contribution = pt.beta * pt.f(position) * sample.radiance / probability;
+        if contribution == Vec3::origin() {
+            return None;
+        }
+        let contribution = contribution * light_visibility(scene, pt.position, &sample);
+        if contribution == Vec3::origin() {
+            return None;
+        }
+
+        Some((
+            Vertex::endpoint(VertexKind::Light(light), position, probability),
+            contribution,
+        ))
+    }
+
+    /// Light from a point picked on the emissive surface of `object` reaching `pt`, with the
+    /// vertex on the surface.
+    fn sample_surface<'a>(
+        scene: &Scene,
+        pt: &Vertex,
+        object: &'a dyn Hitable,
+        probability: f64,
+    ) -> Option<(Vertex<'a>, Vec3)> {
+        let sample = object.sample_surface()?;
+        let hit = sample.hit;
+        let to_light = hit.position - pt.position;
+        let distance_squared = to_light.squared_length();
+        let direction = to_light / distance_squared.sqrt();
+        let cos_light = -hit.normal.dot(direction);
+        if cos_light <= 0.0 {
+            return None;
+        }
+
+        let pdf = probability / sample.area;
+        let radiance = hit
+            .material
+            .emitted(&Ray::new(pt.position, direction), &hit);
+        let contribution =
+            pt.beta * pt.f(hit.position) * radiance * (cos_light / (distance_squared * pdf));
+        if contribution == Vec3::origin() {
+            return None;
+        }
+        let contribution = contribution * visibility(scene, pt.position, hit.position);
+        if contribution == Vec3::origin() {
+            return None;
+        }
+
+        let position = hit.position;
+        Some((
+            Vertex::endpoint(VertexKind::Emitter(hit), position, pdf),
+            contribution,
+        ))
+    }
+
+    /// Connects the last camera vertex to a newly sampled light or point on an emissive surface.
+    fn connect_to_light(scene: &Scene, light_path: &[Vertex], camera_path: &[Vertex]) -> Vec3 {
+        let t = camera_path.len();
+        let pt = &camera_path[t - 1];
+
+        let sampled = match scene.sample_emitter(sampler::random()) {
+            Some((Emitter::Light(index, light), probability)) if pt.lit_by(index) => {
+                BidirectionalPathTracer::sample_light(scene, pt, light, probability)
+            }
+            Some((Emitter::Surface(_, object), probability)) => {
+                BidirectionalPathTracer::sample_surface(scene, pt, object, probability)
+            }
+            _ => None,
+        };
+
+        match sampled {
+            Some((light_vertex, contribution)) => {
+                let weight = mis_weight(
+                    light_path,
+                    camera_path,
+                    Some(&light_vertex),
+                    1,
+                    t,
+                    &scene.camera,
+                );
+                contribution * weight
+            }
+            None => Vec3::origin(),
+        }
+    }
+
+    /// Emission of the surface the last camera vertex is on, when light paths can start there.
+    fn hit_emitter(light_path: &[Vertex], camera_path: &[Vertex], camera: &Camera) -> Vec3 {
+        let pt = &camera_path[camera_path.len() - 1];
+        let emitted = match &pt.kind {
+            VertexKind::Surface(hit) if pt.emitter_pdf > 0.0 => hit.material.emitted(&pt.r_in, hit),
+            _ => return Vec3::origin(),
+        };
+        if emitted == Vec3::origin() {
+            return Vec3::origin();
+        }
+
+        pt.beta * emitted * mis_weight(light_path, camera_path, None, 0, camera_path.len(), camera)
+    }
+
+    /// Connects the last light vertex to the camera, splatting the result onto the film.
+    fn connect_to_camera(
+        scene: &Scene,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        film: &Film,
+    ) {
+        let qs = &light_path[light_path.len() - 1];
+        let (u, v, importance) = match scene.camera.importance_at(qs.position) {
+            Some(importance) => importance,
+            None => return,
+        };
+
+        let contribution = qs.beta * qs.f(scene.camera.origin) * importance;
+        if contribution == Vec3::origin() {
+            return;
+        }
+        let contribution = contribution * visibility(scene, qs.position, scene.camera.origin);
+        if contribution == Vec3::origin() {
+            return;
+        }
+
+        let weight = mis_weight(
+            light_path,
+            &camera_path[..1],
+            None,
+            light_path.len(),
+            1,
+            &scene.camera,
+        );
+        film.add_splat(u, v, contribution * weight);
+    }
+
+    /// Connects the last vertices of two subpaths that are both on surfaces or in media.
+    fn connect(scene: &Scene, light_path: &[Vertex], camera_path: &[Vertex]) -> Vec3 {
+        let qs = &light_path[light_path.len() - 1];
+        let pt = &camera_path[camera_path.len() - 1];
+
+        let distance_squared = (qs.position - pt.position).squared_length();
+        let contribution =
+            qs.beta * qs.f(pt.position) * pt.f(qs.position) * pt.beta / distance_squared;
+        if contribution == Vec3::origin() {
+            return Vec3::origin();
+        }
+        let contribution = contribution * visibility(scene, pt.position, qs.position);
+        if contribution == Vec3::origin() {
+            return Vec3::origin();
+        }
+
+        let weight = mis_weight(
+            light_path,
+            camera_path,
+            None,
+            light_path.len(),
+            camera_path.len(),
+            &scene.camera,
+        );
+        contribution * weight
+    }
+
+    /// Light from infinitely distant lights, which can only be sampled from the camera side.
+    fn infinite_lights(scene: &Scene, pt: &Vertex) -> Vec3 {
+        let mut total = Vec3::origin();
+        for light in scene.infinite_lights() {
+            if let Some(sample) = light.sample(pt.position) {
+                let contribution = pt.beta * pt.f(pt.position + sample.direction) * sample.radiance;
+                if contribution != Vec3::origin() {
+                    total += contribution * light_visibility(scene, pt.position, &sample);
+                }
+            }
+        }
+        total
+    }
+}
+
+impl Integrator for BidirectionalPathTracer {
+    fn radiance(&self, ray: &Ray, scene: &Scene, film: &Film) -> Vec3 {
+        let direction = ray.direction.make_unit_vec();
+        let mut camera_path = vec![Vertex::endpoint(VertexKind::Camera, ray.origin, 1.0)];
+        let mut radiance = random_walk(
+            scene,
+            Ray::new(ray.origin, direction),
+            Vec3::new(1.0, 1.0, 1.0),
+            scene.camera.importance_pdf(direction),
+            self.max_depth + 2,
+            &mut camera_path,
+        );
+
+        let mut light_path = Vec::new();
+        self.trace_light_path(scene, &mut light_path);
+
+        for t in 2..=camera_path.len() {
+            radiance +=
+                BidirectionalPathTracer::hit_emitter(&light_path, &camera_path[..t], &scene.camera);
+        }
+
+        for t in 1..=camera_path.len() {
+            for s in 1..=light_path.len() {
+                if s + t < 3 || s + t - 2 > self.max_depth {
+                    continue;
+                }
+                let (light_prefix, camera_prefix) = (&light_path[..s], &camera_path[..t]);
+                if light_prefix[s - 1].delta || camera_prefix[t - 1].delta {
+                    continue;
+                }
+
+                if t == 1 {
+                    BidirectionalPathTracer::connect_to_camera(
+                        scene,
+                        light_prefix,
+                        camera_prefix,
+                        film,
+                    );
+                } else if s == 1 {
+                    radiance += BidirectionalPathTracer::connect_to_light(
+                        scene,
+                        light_prefix,
+                        camera_prefix,
+                    );
+                } else {
+                    radiance +=
+                        BidirectionalPathTracer::connect(scene, light_prefix, camera_prefix);
+                }
+            }
+        }
+
+        for pt in camera_path.iter().skip(1).filter(|pt| !pt.delta) {
+            radiance += BidirectionalPathTracer::infinite_lights(scene, pt);
+        }
+
+        radiance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::camera::Camera;
+    use crate::film::Film;
+    use crate::hitable::Hitable;
+    use crate::integrators::bidirectional::{
+        mis_weight, BidirectionalPathTracer, Vertex, VertexKind,
+    };
+    use crate::integrators::path::PathTracer;
+    use crate::integrators::Integrator;
+    use crate::lights::point::PointLight;
+    use crate::materials::diffuse_light::DiffuseLight;
+    use crate::materials::lambertian::Lambertian;
+    use crate::object::Object;
+    use crate::quad::Quad;
+    use crate::ray::Ray;
+    use crate::rect::AxisAlignedRect;
+    use crate::scene::Scene;
+    use crate::vec3::Vec3;
+    use std::f64;
+
+    #[test]
+    fn strategies_for_a_path_to_an_emissive_surface_sum_to_one() {
+        let floor = AxisAlignedRect::xz(
+            (-5.0, 5.0),
+            (-5.0, 5.0),
+            0.0,
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        // Facing down, u x v = -y
+        let lamp = Quad::new(
+            Vec3::new(-1.0, 2.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            Box::new(DiffuseLight::new(Vec3::new(4.0, 4.0, 4.0))),
+        );
+        let world: Vec<Box<dyn Hitable>> = vec![Box::new(floor), Box::new(lamp)];
+        let camera = Camera::new(
+            Vec3::new(0.0, 1.0, 3.0),
+            Vec3::origin(),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let scene = Scene::new(world, Vec::new()).with_camera(camera);
+        let camera = &scene.camera;
+
+        let (x0, x1, x2) = (
+            camera.origin,
+            Vec3::new(0.1, 0.0, 0.2),
+            Vec3::new(0.3, 2.0, -0.4),
+        );
+        let surface = |from: Vec3, to: Vec3, pdf_fwd: f64| {
+            let r_in = Ray::new(from, to - from);
+            let (index, hit) = scene
+                .hit_object(&r_in, 0.001, f64::MAX)
+                .expect("hits the scene");
+            assert!((hit.position - to).length() < 1e-9);
+            Vertex {
+                position: to,
+                kind: VertexKind::Surface(hit),
+                r_in,
+                beta: Vec3::new(1.0, 1.0, 1.0),
+                delta: false,
+                pdf_fwd,
+                pdf_rev: 0.0,
+                emitter_pdf: scene.surface_emitter_pdf(index),
+            }
+        };
+
+        // x0 -> x1 -> x2 sampled from the camera
+        let c0 = Vertex::endpoint(VertexKind::Camera, x0, 1.0);
+        let mut c1 = surface(x0, x1, 0.0);
+        c1.pdf_fwd = c0.convert_density(camera.importance_pdf((x1 - x0).make_unit_vec()), &c1);
+        let mut c2 = surface(x1, x2, 0.0);
+        assert!(c2.emitter_pdf > 0.0);
+        c2.pdf_fwd = c1.pdf(Some(&c0), &c2, camera);
+        let camera_path = [c0, c1, c2];
+
+        // x2 -> x1 sampled from the lamp
+        let (_, lamp_hit) = scene
+            .hit_object(&Ray::new(x1, x2 - x1), 0.001, f64::MAX)
+            .expect("hits the lamp");
+        let l0 = Vertex::endpoint(
+            VertexKind::Emitter(lamp_hit),
+            x2,
+            camera_path[2].emitter_pdf,
+        );
+        let mut l1 = surface(x2, x1, 0.0);
+        l1.pdf_fwd = l0.emission_pdf(&l1);
+        let light_path = [l0, l1];
+
+        let weights = [
+            mis_weight(&light_path, &camera_path, None, 0, 3, camera),
+            mis_weight(
+                &light_path[..1],
+                &camera_path[..2],
+                Some(&light_path[0]),
+                1,
+                2,
+                camera,
+            ),
+            mis_weight(&light_path, &camera_path[..1], None, 2, 1, camera),
+        ];
+        assert!(weights.iter().all(|&w| w > 0.0 && w < 1.0), "{:?}", weights);
+        let sum: f64 = weights.iter().sum();
+        assert!((sum - 1.0).abs() < 1e-9, "{:?}", weights);
+    }
+
+    /// Mean of a small image rendered like the renderer does, splats included.
+    fn mean_brightness(integrator: &dyn Integrator, scene: &Scene) -> f64 {
+        let size = 16;
+        let samples = 16;
+        let film = Film::new(size, size);
+        let mut total = Vec3::origin();
+        for j in 0..size {
+            for i in 0..size {
+                for _ in 0..samples {
+                    let u = (i as f64 + 0.5) / size as f64;
+                    let v = (j as f64 + 0.5) / size as f64;
+                    total += integrator.radiance(&scene.camera.get_ray(u, v), scene, &film);
+                }
+            }
+        }
+        for j in 0..size {
+            for i in 0..size {
+                total += film.splat(i, j);
+            }
+        }
+        (total / (size * size * samples) as f64).luminance()
+    }
+
+    #[test]
+    fn respects_light_links() {
+        // The light only reaches the floor after bouncing off the ceiling
+        let floor = AxisAlignedRect::xz(
+            (-5.0, 5.0),
+            (-5.0, 5.0),
+            0.0,
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let ceiling = AxisAlignedRect::xz(
+            (-5.0, 5.0),
+            (-5.0, 5.0),
+            2.0,
+            Box::new(Lambertian::new(Vec3::new(0.8, 0.8, 0.8))),
+        );
+        let world: Vec<Box<dyn Hitable>> = vec![
+            Box::new(Object::new(Box::new(floor)).with_lights(Vec::new())),
+            Box::new(ceiling),
+        ];
+        let light = PointLight::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(4.0, 4.0, 4.0));
+        let camera = Camera::new(
+            Vec3::new(0.0, 1.0, 4.0),
+            Vec3::new(0.0, 0.5, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let scene = Scene::new(world, vec![Box::new(light)]).with_camera(camera);
+
+        let expected = mean_brightness(&PathTracer::new(), &scene);
+        let actual = mean_brightness(&BidirectionalPathTracer::new(), &scene);
+        assert!(
+            (actual - expected).abs() < 0.05 * expected,
+            "{} {}",
+            actual,
+            expected
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use crate::aov::false_colour;
+use crate::film::Film;
+use crate::hitable::{HitRecord, Hitable};
+use crate::integrators::Integrator;
+use crate::materials::Material;
+use crate::medium_stack::MediumStack;
+use crate::onb::{random_cosine_direction, Onb};
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+use std::f64;
+use std::str::FromStr;
+
+/// What `DebugIntegrator` shows.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DebugMode {
+    /// Shading normal, each component mapped from [-1, 1] to [0, 1].
+    Normals,
+    /// World position within `extent` of the origin.
+    Position,
+    /// Distance along the viewing direction, white at `extent`.
+    Depth,
+    /// Reflectance of the material, or the emitted light of lights.
+    Albedo,
+    /// Texture coordinates in red and green.
+    Uv,
+    /// A colour per object of the scene.
+    ObjectId,
+    /// A colour per material, which can change between runs.
+    MaterialId,
+    /// Fraction of the hemisphere around the normal that's unoccluded within `ao_radius`.
+    AmbientOcclusion,
+    /// Number of bounces before the path leaves the scene or is absorbed, from blue to red at
+    /// `max_bounces`.
+    Bounces,
+}
+
+impl DebugMode {
+    pub const NAMES: &'static [&'static str] = &[
+        "normals",
+        "position",
+        "depth",
+        "albedo",
+        "uv",
+        "object-id",
+        "material-id",
+        "ao",
+        "bounces",
+    ];
+}
+
+impl FromStr for DebugMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<DebugMode, String> {
+        match name {
+            "normals" => Ok(DebugMode::Normals),
+            "position" => Ok(DebugMode::Position),
+            "depth" => Ok(DebugMode::Depth),
+            "albedo" => Ok(DebugMode::Albedo),
+            "uv" => Ok(DebugMode::Uv),
+            "object-id" => Ok(DebugMode::ObjectId),
+            "material-id" => Ok(DebugMode::MaterialId),
+            "ao" => Ok(DebugMode::AmbientOcclusion),
+            "bounces" => Ok(DebugMode::Bounces),
+            _ => Err(format!(
+                "unknown mode `{}`, expected one of {}",
+                name,
+                DebugMode::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Shows properties of the first surface seen by the camera instead of rendering it, to find out
+/// why a scene looks wrong. Values are meant to be written to the image without gamma correction.
+#[derive(Debug)]
+pub struct DebugIntegrator {
+    mode: DebugMode,
+    extent: f64,
+    ao_radius: f64,
+    max_bounces: usize,
+}
+
+/// Blue to green to red as `t` goes from 0 to 1.
+fn heat(t: f64) -> Vec3 {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        Vec3::new(0.0, 2.0 * t, 1.0 - 2.0 * t)
+    } else {
+        Vec3::new(2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0)
+    }
+}
+
+impl DebugIntegrator {
+    pub fn new(mode: DebugMode) -> DebugIntegrator {
+        DebugIntegrator {
+            mode,
+            extent: 20.0,
+            ao_radius: 1.0,
+            max_bounces: 16,
+        }
+    }
+
+    /// Distance mapped to the ends of the colour range by the position and depth modes.
+    pub fn with_extent(mut self, extent: f64) -> DebugIntegrator {
+        self.extent = extent;
+        self
+    }
+
+    /// Distance within which occluders darken ambient occlusion.
+    pub fn with_ao_radius(mut self, ao_radius: f64) -> DebugIntegrator {
+        self.ao_radius = ao_radius;
+        self
+    }
+
+    /// Bounce count shown in red by the bounces mode, longer paths are cut there.
+    pub fn with_max_bounces(mut self, max_bounces: usize) -> DebugIntegrator {
+        self.max_bounces = max_bounces.max(1);
+        self
+    }
+
+    fn ambient_occlusion(&self, ray: &Ray, scene: &Scene, hit: &HitRecord) -> Vec3 {
+        // Occlusion is measured on the side the camera sees
+        let normal = hit.facing_normal(ray);
+        let direction = Onb::from_w(normal).local(random_cosine_direction());
+
+        match scene
+            .world
+            .hit(&Ray::new(hit.position, direction), 0.001, self.ao_radius)
+        {
+            Some(_) => Vec3::origin(),
+            None => Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    fn bounces(&self, ray: &Ray, scene: &Scene) -> Vec3 {
+        let mut ray = *ray;
+        let mut media = MediumStack::new();
+        let mut bounces = 0;
+        while bounces < self.max_bounces {
+            let hit = match scene.world.hit(&ray, 0.001, f64::MAX) {
+                Some(hit) => hit,
+                None => break,
+            };
+            match media.scatter(&ray, &hit) {
+                Some((_, scattered, scattered_media)) => {
+                    ray = scattered;
+                    media = scattered_media;
+                    bounces += 1;
+                }
+                None => break,
+            }
+        }
+
+        heat(bounces as f64 / self.max_bounces as f64)
+    }
+}
+
+impl Integrator for DebugIntegrator {
+    fn radiance(&self, ray: &Ray, scene: &Scene, _film: &Film) -> Vec3 {
+        if self.mode == DebugMode::Bounces {
+            return self.bounces(ray, scene);
+        }
+
+        let hit = match scene.world.hit(ray, 0.001, f64::MAX) {
+            Some(hit) => hit,
+            None => return Vec3::origin(),
+        };
+
+        match self.mode {
+            DebugMode::Normals => (hit.normal.make_unit_vec() + Vec3::new(1.0, 1.0, 1.0)) / 2.0,
+            DebugMode::Position => (hit.position / self.extent + Vec3::new(1.0, 1.0, 1.0)) / 2.0,
+            DebugMode::Depth => {
+                let depth = scene.camera.depth(hit.position) / self.extent;
+                Vec3::new(depth, depth, depth)
+            }
+            DebugMode::Albedo => hit.material.albedo(ray, &hit),
+            DebugMode::Uv => Vec3::new(hit.u, hit.v, 0.0),
+            DebugMode::ObjectId => scene
+                .hit_object(ray, 0.001, f64::MAX)
+                .map_or(Vec3::origin(), |(id, _)| false_colour(id as u64)),
+            DebugMode::MaterialId => {
+                false_colour(hit.material as *const dyn Material as *const () as usize as u64)
+            }
+            DebugMode::AmbientOcclusion => self.ambient_occlusion(ray, scene, &hit),
+            DebugMode::Bounces => unreachable!("handled without a hit"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::camera::Camera;
+    use crate::film::Film;
+    use crate::hitable::Hitable;
+    use crate::integrators::debug::{DebugIntegrator, DebugMode};
+    use crate::integrators::Integrator;
+    use crate::materials::conductor::Conductor;
+    use crate::materials::principled::Principled;
+    use crate::materials::Material;
+    use crate::scene::Scene;
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+
+    /// What `mode` shows in the middle of the image, looking at a unit sphere from 5 away.
+    fn centre(mode: DebugMode, material: Box<dyn Material + Send + Sync>) -> Vec3 {
+        let sphere = Sphere::new(Vec3::origin(), 1.0, material);
+        let world: Vec<Box<dyn Hitable>> = vec![Box::new(sphere)];
+        let camera = Camera::new(
+            Vec3::new(0.0, 0.0, 5.0),
+            Vec3::origin(),
+            Vec3::new(0.0, 1.0, 0.0),
+            20.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let scene = Scene::new(world, Vec::new()).with_camera(camera);
+        let ray = scene.camera.get_ray(0.5, 0.5);
+        DebugIntegrator::new(mode)
+            .with_extent(10.0)
+            .radiance(&ray, &scene, &Film::new(1, 1))
+    }
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).length() < 1e-6,
+            "{:?} {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn shows_depth_normals_and_albedo() {
+        let gold = || Box::new(Conductor::gold(0.3));
+        assert_close(centre(DebugMode::Depth, gold()), Vec3::new(0.4, 0.4, 0.4));
+        assert_close(centre(DebugMode::Normals, gold()), Vec3::new(0.5, 0.5, 1.0));
+
+        // The same colour for every sample, not the weight of a random reflection
+        let albedo = centre(DebugMode::Albedo, gold());
+        for _ in 0..10 {
+            assert_eq!(centre(DebugMode::Albedo, gold()), albedo);
+        }
+        assert!(albedo.x > albedo.z);
+
+        let colour = Vec3::new(0.8, 0.6, 0.2);
+        let metal = Box::new(Principled::metal(colour, 0.5));
+        assert_close(centre(DebugMode::Albedo, metal), colour);
+    }
+
+    #[test]
+    fn parses_every_mode_name() {
+        for name in DebugMode::NAMES {
+            assert!(name.parse::<DebugMode>().is_ok(), "{}", name);
+        }
+        assert!("shaded".parse::<DebugMode>().is_err());
+    }
+}
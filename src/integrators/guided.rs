@@ -0,0 +1,254 @@
+use crate::film::Film;
+use crate::hitable::{HitRecord, Hitable};
+use crate::integrators::{
+    direct_lighting, emission_weight, scattering_pdf, sky, Integrator, MAX_DEPTH,
+};
+use crate::materials::microfacet::reflect;
+use crate::materials::Scattering;
+use crate::medium_stack::MediumStack;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::scene::Scene;
+use crate::sd_tree::SdTree;
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Path tracer learning where indirect light comes from (practical path guiding, Müller et al.
+/// 2017).
+///
+/// Every pass records the radiance its paths find into an `SdTree`, and the next pass samples
+/// directions from it, blended with the material's own sampling so glossy lobes are still
+/// followed. Passes hold the same number of samples, so the first, unguided one still adds
+/// noise to the final image.
+///
+/// Guiding only applies where the material doesn't scatter specularly, and only converges to the
+/// path traced image where `Material::bsdf` agrees with the weights `Material::scatter` returns.
+/// Directions picked by the guide are wasted on glossy materials without a BSDF.
+pub struct GuidedPathTracer {
+    passes: usize,
+    bsdf_fraction: f64,
+    spatial_threshold: usize,
+    directional_threshold: f64,
+    tree: Option<SdTree>,
+}
+
+impl GuidedPathTracer {
+    /// Learns over `passes` passes, the first one only records.
+    pub fn new(passes: usize) -> GuidedPathTracer {
+        GuidedPathTracer {
+            passes: passes.max(1),
+            bsdf_fraction: 0.5,
+            spatial_threshold: 4000,
+            directional_threshold: 0.01,
+            tree: None,
+        }
+    }
+
+    /// Fraction of directions sampled from the material rather than the learned distribution.
+    pub fn with_bsdf_fraction(mut self, bsdf_fraction: f64) -> GuidedPathTracer {
+        self.bsdf_fraction = bsdf_fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Records a region of space needs in a pass before it's split in two.
+    pub fn with_spatial_threshold(mut self, records: usize) -> GuidedPathTracer {
+        self.spatial_threshold = records.max(1);
+        self
+    }
+
+    /// Fraction of a region's energy a direction bin needs before it's subdivided.
+    pub fn with_directional_threshold(mut self, fraction: f64) -> GuidedPathTracer {
+        self.directional_threshold = fraction;
+        self
+    }
+
+    /// Samples the next direction at a hit with a BSDF, returning the weight of the light coming
+    /// back along it, the ray, the media it travels in and the density it was sampled with.
+    fn sample_direction(
+        &self,
+        tree: &SdTree,
+        r: &Ray,
+        hit: &HitRecord,
+        media: &MediumStack,
+    ) -> Option<(Vec3, Ray, MediumStack, f64)> {
+        let guided = tree.can_sample(hit.position);
+        let bsdf_fraction = if guided { self.bsdf_fraction } else { 1.0 };
+
+        if sampler::random() < bsdf_fraction {
+            let (albedo, scattered, media) = media.scatter(r, hit)?;
+            let direction = scattered.direction.make_unit_vec();
+            let bsdf_pdf = hit.material.pdf(r, hit, direction);
+            if bsdf_pdf <= 0.0 {
+                // Specular lobes can't be picked by the guide
+                return Some((albedo / bsdf_fraction, scattered, media, 0.0));
+            }
+
+            let pdf = bsdf_fraction * bsdf_pdf
+                + (1.0 - bsdf_fraction) * tree.pdf(hit.position, direction);
+            Some((albedo * (bsdf_pdf / pdf), scattered, media, pdf))
+        } else {
+            let direction = tree.sample(hit.position)?;
+            let pdf = bsdf_fraction * hit.material.pdf(r, hit, direction)
+                + (1.0 - bsdf_fraction) * tree.pdf(hit.position, direction);
+            let bsdf = hit.material.bsdf(r, hit, direction)?;
+            if pdf <= 0.0 {
+                return None;
+            }
+
+            let scattered = Ray::new(hit.position, direction).with_wavelength(r.wavelength);
+            Some((bsdf / pdf, scattered, media.clone(), pdf))
+        }
+    }
+
+    /// Light arriving along `r`, which was scattered with the density `pdf` as for
+    /// `emission_weight`.
+    fn calculate_color(
+        &self,
+        r: &Ray,
+        pdf: Option<f64>,
+        scene: &Scene,
+        depth: i32,
+        media: &MediumStack,
+    ) -> Vec3 {
+        let (index, hit) = match scene.hit_object(r, 0.001, f64::MAX) {
+            Some(found) => found,
+            None => return sky(r),
+        };
+
+        let transmittance = media.transmittance(hit.t * r.direction.length());
+        let emitted = hit.material.emitted(r, &hit) * emission_weight(scene, r, index, &hit, pdf)
+            + direct_lighting(scene, r, &hit, media);
+        if depth >= MAX_DEPTH {
+            return transmittance * emitted;
+        }
+
+        let tree = match &self.tree {
+            Some(tree) if guided(r, &hit) => tree,
+            _ => {
+                return match media.scatter(r, &hit) {
+                    Some((albedo, scattered, media)) => {
+                        let direction = scattered.direction.make_unit_vec();
+                        let pdf = scattering_pdf(r, &hit, direction);
+                        let incident =
+                            self.calculate_color(&scattered, pdf, scene, depth + 1, &media);
+                        transmittance * (emitted + albedo * incident)
+                    }
+                    None => transmittance * emitted,
+                };
+            }
+        };
+
+        match self.sample_direction(tree, r, &hit, media) {
+            Some((weight, scattered, media, pdf)) => {
+                // Specular lobes picked by the material have no density
+                let scattered_pdf = Some(pdf).filter(|&pdf| pdf > 0.0);
+                let incident =
+                    self.calculate_color(&scattered, scattered_pdf, scene, depth + 1, &media);
+                if pdf > 0.0 {
+                    tree.record(
+                        hit.position,
+                        scattered.direction.make_unit_vec(),
+                        incident.luminance() / pdf,
+                    );
+                }
+                transmittance * (emitted + weight * incident)
+            }
+            None => transmittance * emitted,
+        }
+    }
+}
+
+/// Whether directions at the hit can be picked by the guide, judged by the scattering around the
+/// mirror direction, where every lobe of the material has some of its density.
+fn guided(r_in: &Ray, hit: &HitRecord) -> bool {
+    let mirror = reflect(r_in.direction.make_unit_vec() * -1.0, hit.normal);
+    hit.material.scattering(r_in, hit, mirror) != Scattering::Specular
+}
+
+impl Integrator for GuidedPathTracer {
+    fn radiance(&self, ray: &Ray, scene: &Scene, _film: &Film) -> Vec3 {
+        self.calculate_color(ray, None, scene, 0, &MediumStack::new())
+    }
+
+    fn passes(&self) -> usize {
+        self.passes
+    }
+
+    fn prepare_pass(&mut self, scene: &Scene, pass: usize) {
+        if pass == 0 {
+            self.tree = scene.world.bounding_box().map(SdTree::new);
+        } else if let Some(tree) = &mut self.tree {
+            tree.refine(self.spatial_threshold, self.directional_threshold);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::camera::Camera;
+    use crate::film::Film;
+    use crate::hitable::Hitable;
+    use crate::integrators::guided::GuidedPathTracer;
+    use crate::integrators::path::PathTracer;
+    use crate::integrators::Integrator;
+    use crate::materials::lambertian::Lambertian;
+    use crate::scene::Scene;
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+
+    /// Mean of a small image, with the samples of every pixel split over the passes.
+    fn mean_brightness(integrator: &mut dyn Integrator, scene: &Scene) -> f64 {
+        let size = 16;
+        let samples = 16;
+        let film = Film::new(size, size);
+        let mut total = Vec3::origin();
+        for pass in 0..integrator.passes() {
+            integrator.prepare_pass(scene, pass);
+            for j in 0..size {
+                for i in 0..size {
+                    for _ in 0..samples / integrator.passes() {
+                        let u = (i as f64 + 0.5) / size as f64;
+                        let v = (j as f64 + 0.5) / size as f64;
+                        total += integrator.radiance(&scene.camera.get_ray(u, v), scene, &film);
+                    }
+                }
+            }
+        }
+        (total / (size * size * samples) as f64).luminance()
+    }
+
+    #[test]
+    fn matches_the_path_tracer() {
+        let floor = Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let ball = Sphere::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            Box::new(Lambertian::new(Vec3::new(0.8, 0.3, 0.3))),
+        );
+        let world: Vec<Box<dyn Hitable>> = vec![Box::new(floor), Box::new(ball)];
+        let camera = Camera::new(
+            Vec3::new(0.0, 2.0, 5.0),
+            Vec3::new(0.0, 0.5, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let scene = Scene::new(world, Vec::new()).with_camera(camera);
+
+        let expected = mean_brightness(&mut PathTracer::new(), &scene);
+        let mut guided = GuidedPathTracer::new(4).with_spatial_threshold(500);
+        let actual = mean_brightness(&mut guided, &scene);
+        assert!(
+            (actual - expected).abs() < 0.05 * expected,
+            "{} {}",
+            actual,
+            expected
+        );
+    }
+}
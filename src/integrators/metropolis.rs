@@ -0,0 +1,432 @@
+use crate::film::Film;
+use crate::integrators::{path, Integrator};
+use crate::medium_stack::MediumStack;
+use crate::ray::Ray;
+use crate::sampler::{self, Sampler};
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use std::any::Any;
+use std::f64;
+use std::sync::Mutex;
+
+/// One coordinate of primary sample space, with the value to go back to if a mutation is rejected.
+#[derive(Debug, Copy, Clone)]
+struct PrimarySample {
+    value: f64,
+    /// Iteration the value was last changed in.
+    modified: u64,
+    backup: f64,
+    backup_modified: u64,
+}
+
+/// Sampler handing out the coordinates of a point in primary sample space, mutated a little or
+/// replaced entirely at every iteration of a Markov chain.
+///
+/// Coordinates are only mutated when the path asks for them, catching up on the small steps they
+/// missed in one go, so paths of any length can be explored.
+#[derive(Debug)]
+struct PrimarySampleSpace {
+    rng: StdRng,
+    samples: Vec<PrimarySample>,
+    index: usize,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64,
+    large_step_probability: f64,
+    sigma: f64,
+}
+
+impl PrimarySampleSpace {
+    fn new(seed: u64, large_step_probability: f64, sigma: f64) -> PrimarySampleSpace {
+        PrimarySampleSpace {
+            rng: StdRng::seed_from_u64(seed),
+            samples: Vec::new(),
+            index: 0,
+            iteration: 0,
+            large_step: true,
+            last_large_step: 0,
+            large_step_probability,
+            sigma,
+        }
+    }
+
+    /// Proposes a new point, its coordinates change as they are handed out.
+    fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step =
+            self.iteration == 1 || self.rng.gen::<f64>() < self.large_step_probability;
+        self.index = 0;
+    }
+
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    fn reject(&mut self) {
+        let iteration = self.iteration;
+        for sample in self.samples.iter_mut().filter(|s| s.modified == iteration) {
+            sample.value = sample.backup;
+            sample.modified = sample.backup_modified;
+        }
+        self.iteration -= 1;
+    }
+
+    fn gaussian(&mut self) -> f64 {
+        // Box-Muller
+        let u1 = 1.0 - self.rng.gen::<f64>();
+        let u2 = self.rng.gen::<f64>();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * f64::consts::PI * u2).cos()
+    }
+}
+
+impl Sampler for PrimarySampleSpace {
+    fn next(&mut self) -> f64 {
+        if self.index == self.samples.len() {
+            let value = self.rng.gen();
+            self.samples.push(PrimarySample {
+                value,
+                modified: self.iteration,
+                backup: value,
+                backup_modified: self.iteration,
+            });
+        }
+
+        let mut sample = self.samples[self.index];
+        self.index += 1;
+
+        // Large steps since the last change replace the value
+        if sample.modified < self.last_large_step {
+            sample.value = self.rng.gen();
+            sample.modified = self.last_large_step;
+        }
+
+        sample.backup = sample.value;
+        sample.backup_modified = sample.modified;
+        if self.large_step {
+            sample.value = self.rng.gen();
+        } else {
+            // The small steps this coordinate missed add up to a single wider one
+            let steps = (self.iteration - sample.modified) as f64;
+            let value = sample.value + self.gaussian() * self.sigma * steps.sqrt();
+            sample.value = value - value.floor();
+        }
+        sample.modified = self.iteration;
+
+        self.samples[self.index - 1] = sample;
+        sample.value
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+/// Path through primary sample space and the film position and radiance it maps to.
+#[derive(Debug, Copy, Clone)]
+struct PathSample {
+    u: f64,
+    v: f64,
+    radiance: Vec3,
+}
+
+impl PathSample {
+    /// Scalar the chains are distributed proportionally to.
+    fn contribution(&self) -> f64 {
+        0.2126 * self.radiance.x + 0.7152 * self.radiance.y + 0.0722 * self.radiance.z
+    }
+}
+
+#[derive(Debug)]
+struct Chain {
+    /// Only taken out while it is installed for a path.
+    sampler: Option<PrimarySampleSpace>,
+    current: PathSample,
+}
+
+/// Primary sample space Metropolis light transport (Kelemen et al. 2002).
+///
+/// Paths are built by the path tracer from the random numbers of a `PrimarySampleSpace`, the first
+/// two of which pick the film position. Markov chains mutate these numbers, with small
+/// perturbations to explore the neighbourhood of bright paths and occasional large steps
+/// replacing all of them so no part of the image is missed, and splat every proposal onto the film
+/// weighted by its acceptance probability.
+///
+/// The brightness of the image comes from a bootstrap phase estimating the average contribution
+/// of a path from independent samples, which also picks the starting point of the chains. It only
+/// runs before the first pass, later passes carry on with the same chains.
+///
+/// Every camera sample of the renderer advances one chain by one mutation and returns black, the
+/// film gets the whole image. There is one chain per rayon thread.
+pub struct MetropolisTracer {
+    bootstrap_samples: usize,
+    large_step_probability: f64,
+    sigma: f64,
+    /// Average contribution over primary sample space.
+    normalization: f64,
+    bootstrapped: bool,
+    chains: Vec<Mutex<Chain>>,
+}
+
+impl MetropolisTracer {
+    pub fn new() -> MetropolisTracer {
+        MetropolisTracer {
+            bootstrap_samples: 100_000,
+            large_step_probability: 0.3,
+            sigma: 0.01,
+            normalization: 0.0,
+            bootstrapped: false,
+            chains: Vec::new(),
+        }
+    }
+
+    /// Number of independent paths used to estimate the image brightness and seed the chains.
+    pub fn with_bootstrap_samples(mut self, bootstrap_samples: usize) -> MetropolisTracer {
+        self.bootstrap_samples = bootstrap_samples.max(1);
+        self
+    }
+
+    /// Fraction of mutations replacing the whole path.
+    pub fn with_large_step_probability(mut self, probability: f64) -> MetropolisTracer {
+        self.large_step_probability = probability.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Standard deviation of the small step perturbation of each random number.
+    pub fn with_sigma(mut self, sigma: f64) -> MetropolisTracer {
+        self.sigma = sigma;
+        self
+    }
+
+    fn sampler(&self, seed: u64) -> PrimarySampleSpace {
+        PrimarySampleSpace::new(seed, self.large_step_probability, self.sigma)
+    }
+
+    fn evaluate(scene: &Scene) -> PathSample {
+        let u = sampler::random();
+        let v = sampler::random();
+        let ray = scene.camera.get_ray(u, v);
+        let radiance = path::calculate_color(&ray, None, scene, 0, &MediumStack::new());
+
+        // Dropping the odd NaN keeps it from poisoning the chain
+        let radiance = if radiance.x.is_finite() && radiance.y.is_finite() && radiance.z.is_finite()
+        {
+            radiance
+        } else {
+            Vec3::origin()
+        };
+        PathSample { u, v, radiance }
+    }
+
+    fn splat(&self, film: &Film, sample: &PathSample, weight: f64) {
+        let contribution = sample.contribution();
+        if weight > 0.0 && contribution > 0.0 {
+            film.add_splat(
+                sample.u,
+                sample.v,
+                sample.radiance * (weight * self.normalization / contribution),
+            );
+        }
+    }
+}
+
+impl Default for MetropolisTracer {
+    fn default() -> MetropolisTracer {
+        MetropolisTracer::new()
+    }
+}
+
+impl Integrator for MetropolisTracer {
+    fn radiance(&self, _ray: &Ray, scene: &Scene, film: &Film) -> Vec3 {
+        if self.chains.is_empty() {
+            return Vec3::origin();
+        }
+        let index = rayon::current_thread_index().unwrap_or(0) % self.chains.len();
+        let mut chain = self.chains[index]
+            .lock()
+            .expect("a chain is only used by its thread");
+
+        let mut sampler = chain.sampler.take().expect("sampler is put back");
+        sampler.start_iteration();
+        let (mut sampler, proposed) =
+            sampler::with_sampler(sampler, || MetropolisTracer::evaluate(scene));
+
+        let current = chain.current;
+        let accept = if current.contribution() > 0.0 {
+            (proposed.contribution() / current.contribution()).min(1.0)
+        } else {
+            1.0
+        };
+
+        // Both states contribute in expectation, whichever is kept
+        self.splat(film, &proposed, accept);
+        self.splat(film, &current, 1.0 - accept);
+
+        if sampler.rng.gen::<f64>() < accept {
+            sampler.accept();
+            chain.current = proposed;
+        } else {
+            sampler.reject();
+        }
+        chain.sampler = Some(sampler);
+
+        Vec3::origin()
+    }
+
+    fn prepare_pass(&mut self, scene: &Scene, _pass: usize) {
+        if self.bootstrapped {
+            return;
+        }
+        self.bootstrapped = true;
+
+        let contributions: Vec<f64> = (0..self.bootstrap_samples as u64)
+            .into_par_iter()
+            .map(|seed| {
+                let mut sampler = self.sampler(seed);
+                sampler.start_iteration();
+                sampler::with_sampler(sampler, || MetropolisTracer::evaluate(scene))
+                    .1
+                    .contribution()
+            })
+            .collect();
+
+        let total: f64 = contributions.iter().sum();
+        self.normalization = total / self.bootstrap_samples as f64;
+        if total <= 0.0 {
+            self.chains = Vec::new();
+            return;
+        }
+
+        // Start the chains from bootstrap paths picked proportionally to their contribution, and
+        // replay them to recover their state
+        let mut rng = rand::thread_rng();
+        self.chains = (0..rayon::current_num_threads())
+            .map(|_| {
+                let target = rng.gen::<f64>() * total;
+                let mut seed = 0;
+                let mut sum = 0.0;
+                for (i, contribution) in contributions.iter().enumerate() {
+                    sum += contribution;
+                    if *contribution > 0.0 {
+                        seed = i as u64;
+                    }
+                    if sum > target {
+                        break;
+                    }
+                }
+
+                let mut sampler = self.sampler(seed);
+                sampler.start_iteration();
+                let (mut sampler, current) =
+                    sampler::with_sampler(sampler, || MetropolisTracer::evaluate(scene));
+                sampler.accept();
+                Mutex::new(Chain {
+                    sampler: Some(sampler),
+                    current,
+                })
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::camera::Camera;
+    use crate::film::Film;
+    use crate::hitable::Hitable;
+    use crate::integrators::metropolis::{MetropolisTracer, PrimarySampleSpace};
+    use crate::integrators::path::PathTracer;
+    use crate::integrators::Integrator;
+    use crate::materials::lambertian::Lambertian;
+    use crate::sampler::Sampler;
+    use crate::scene::Scene;
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn rejected_mutations_restore_the_path() {
+        let mut sampler = PrimarySampleSpace::new(7, 0.5, 0.01);
+        sampler.start_iteration();
+        let original: Vec<f64> = (0..5).map(|_| sampler.next()).collect();
+        sampler.accept();
+
+        for _ in 0..10 {
+            sampler.start_iteration();
+            let proposed: Vec<f64> = (0..8).map(|_| sampler.next()).collect();
+            assert!(proposed.iter().all(|&x| (0.0..1.0).contains(&x)));
+            sampler.reject();
+        }
+
+        sampler.start_iteration();
+        sampler.large_step = false;
+        let replayed: Vec<f64> = (0..5).map(|_| sampler.next()).collect();
+        for (a, b) in original.iter().zip(&replayed) {
+            assert!((a - b).abs() < 0.1);
+        }
+    }
+
+    /// Mean of a `size` by `size` image rendered like the renderer does, over `passes` passes.
+    fn mean_brightness(integrator: &mut dyn Integrator, scene: &Scene, passes: usize) -> f64 {
+        let size = 16;
+        let samples = 16;
+        let film = Film::new(size, size);
+        let mut total = Vec3::origin();
+        for pass in 0..passes {
+            integrator.prepare_pass(scene, pass);
+            for j in 0..size {
+                for i in 0..size {
+                    for _ in 0..samples {
+                        let u = (i as f64 + 0.5) / size as f64;
+                        let v = (j as f64 + 0.5) / size as f64;
+                        total += integrator.radiance(&scene.camera.get_ray(u, v), scene, &film);
+                    }
+                }
+            }
+        }
+        for j in 0..size {
+            for i in 0..size {
+                total += film.splat(i, j);
+            }
+        }
+        (total / (size * size * samples * passes) as f64).luminance()
+    }
+
+    #[test]
+    fn matches_the_path_tracer() {
+        let floor = Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let ball = Sphere::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            Box::new(Lambertian::new(Vec3::new(0.8, 0.3, 0.3))),
+        );
+        let world: Vec<Box<dyn Hitable>> = vec![Box::new(floor), Box::new(ball)];
+        let camera = Camera::new(
+            Vec3::new(0.0, 2.0, 5.0),
+            Vec3::new(0.0, 0.5, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let scene = Scene::new(world, Vec::new()).with_camera(camera);
+
+        let expected = mean_brightness(&mut PathTracer::new(), &scene, 1);
+        let mut metropolis = MetropolisTracer::new().with_bootstrap_samples(10_000);
+        let actual = mean_brightness(&mut metropolis, &scene, 4);
+        assert!(
+            (actual - expected).abs() < 0.05 * expected,
+            "{} {}",
+            actual,
+            expected
+        );
+    }
+}
@@ -0,0 +1,332 @@
+pub mod bidirectional;
+pub mod debug;
+pub mod guided;
+pub mod metropolis;
+pub mod path;
+pub mod photon;
+pub mod restir;
+pub mod spectral;
+
+use crate::aov::AovSample;
+use crate::film::Film;
+use crate::hitable::{HitRecord, Hitable};
+use crate::lights::LightSample;
+use crate::medium_stack::MediumStack;
+use crate::ray::{Ray, RayKind};
+use crate::region::Region;
+use crate::scene::{Emitter, Scene};
+use crate::vec3::Vec3;
+
+/// Maximum number of bounces before a path is cut off.
+pub const MAX_DEPTH: i32 = 50;
+
+pub trait Integrator: Send + Sync {
+    /// Linear RGB radiance arriving at the camera along `ray`. Contributions to other pixels, eg.
+    /// from light paths hitting the camera, are splatted onto `film` instead.
+    fn radiance(&self, ray: &Ray, scene: &Scene, film: &Film) -> Vec3;
+
+    /// `radiance`, also adding it to the lighting passes and light path expressions of `aovs`.
+    /// Integrators that can't tell how light reached the camera leave them black.
+    fn radiance_with_aovs(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        film: &Film,
+        _aovs: &mut AovSample<'_>,
+    ) -> Vec3 {
+        self.radiance(ray, scene, film)
+    }
+
+    /// Number of passes the samples of every pixel are split over.
+    fn passes(&self) -> usize {
+        1
+    }
+
+    /// Called before rendering each pass, eg. to trace photons for it.
+    fn prepare_pass(&mut self, _scene: &Scene, _pass: usize) {}
+
+    /// Called before rendering only the pixels of `region`, eg. a tile, so per-pixel state can be
+    /// limited to them.
+    fn crop(&mut self, _region: Region) {}
+}
+
+/// Gradient sky seen by rays escaping the scene.
+pub fn sky(ray: &Ray) -> Vec3 {
+    let unit_vec = ray.direction.make_unit_vec();
+    let t = (unit_vec.y + 1.0) / 2.0;
+
+    Vec3::new(1.0, 1.0, 1.0) * (1.0 - t) + Vec3::new(0.5, 0.7, 1.0) * t
+}
+
+/// Light reflected towards `r_in` from the lights and emissive surfaces in the scene, by tracing
+/// shadow rays to the ones picked by `Scene::sample_lights`. Zero for materials without a BSDF to
+/// evaluate.
+///
+/// Emissive surfaces can also be found by following scattered rays, the light of both is weighted
+/// by multiple importance sampling, see `emission_weight`.
+pub fn direct_lighting(scene: &Scene, r_in: &Ray, hit: &HitRecord, media: &MediumStack) -> Vec3 {
+    let mut total = Vec3::origin();
+    for_each_direct_light(scene, r_in, hit, media, |_, contribution| {
+        total += contribution
+    });
+    total
+}
+
+/// `direct_lighting` split by light: calls `f` with the unit vector towards every unoccluded
+/// light and the light it reflects towards `r_in`.
+pub fn for_each_direct_light(
+    scene: &Scene,
+    r_in: &Ray,
+    hit: &HitRecord,
+    media: &MediumStack,
+    mut f: impl FnMut(Vec3, Vec3),
+) {
+    for_each_light_sample(scene, r_in, hit, media, |direction, light, visibility| {
+        if visibility > 0.0 {
+            f(direction, light * visibility)
+        }
+    });
+}
+
+/// `direct_lighting` with the whole light of emissive surfaces, for integrators that don't find
+/// them by following scattered rays from surfaces they light directly.
+pub fn unweighted_direct_lighting(
+    scene: &Scene,
+    r_in: &Ray,
+    hit: &HitRecord,
+    media: &MediumStack,
+) -> Vec3 {
+    let mut total = Vec3::origin();
+    light_samples(scene, r_in, hit, media, false, |_, light, visibility| {
+        total += light * visibility
+    });
+    total
+}
+
+/// Point on an emitter picked by `Scene::sample_lights`, see `Light::sample`. Points on emissive
+/// surfaces are weighted against scattered rays finding them if `weighted`.
+fn sample_emitter(
+    emitter: &Emitter,
+    probability: f64,
+    r_in: &Ray,
+    hit: &HitRecord,
+    weighted: bool,
+) -> Option<LightSample> {
+    match *emitter {
+        Emitter::Light(index, light) => {
+            if !hit.lit_by(index) {
+                return None;
+            }
+            let sample = light.sample(hit.position)?;
+            Some(LightSample {
+                radiance: sample.radiance / probability,
+                ..sample
+            })
+        }
+        Emitter::Surface(_, object) => {
+            let sample = object.sample_surface()?;
+            let to_light = sample.hit.position - hit.position;
+            let distance = to_light.length();
+            let direction = to_light / distance;
+            let cosine = -direction.dot(sample.hit.normal);
+            if cosine <= 0.0 {
+                return None;
+            }
+
+            let towards = Ray::new(hit.position, direction).with_wavelength(r_in.wavelength);
+            let radiance = sample.hit.material.emitted(&towards, &sample.hit);
+            let pdf = probability / sample.area * distance * distance / cosine;
+            let weight = if weighted {
+                power_heuristic(pdf, hit.material.pdf(r_in, hit, direction))
+            } else {
+                1.0
+            };
+            Some(LightSample {
+                direction,
+                distance,
+                radiance: radiance * weight / pdf,
+            })
+        }
+    }
+}
+
+/// `for_each_direct_light` including the occluded lights, with the light they would reflect if
+/// nothing was in the way and the fraction of it getting through.
+pub fn for_each_light_sample(
+    scene: &Scene,
+    r_in: &Ray,
+    hit: &HitRecord,
+    media: &MediumStack,
+    f: impl FnMut(Vec3, Vec3, f64),
+) {
+    light_samples(scene, r_in, hit, media, true, f)
+}
+
+fn light_samples(
+    scene: &Scene,
+    r_in: &Ray,
+    hit: &HitRecord,
+    media: &MediumStack,
+    weighted: bool,
+    mut f: impl FnMut(Vec3, Vec3, f64),
+) {
+    for (emitter, probability) in scene.sample_lights(hit.position) {
+        let sample = match sample_emitter(&emitter, probability, r_in, hit, weighted) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        let bsdf = match hit.material.bsdf(r_in, hit, sample.direction) {
+            Some(bsdf) => bsdf,
+            // Specular materials can't be lit directly
+            None => return,
+        };
+        if bsdf == Vec3::origin() {
+            continue;
+        }
+
+        let shadow_ray = Ray::new(hit.position, sample.direction)
+            .with_wavelength(r_in.wavelength)
+            .with_kind(RayKind::Shadow);
+        let visibility = scene
+            .world
+            .transmittance(&shadow_ray, 0.001, sample.distance - 0.001);
+
+        let transmittance = if sample.distance.is_finite() {
+            media.transmittance(sample.distance)
+        } else {
+            Vec3::new(1.0, 1.0, 1.0)
+        };
+        f(
+            sample.direction,
+            transmittance * bsdf * sample.radiance,
+            visibility,
+        );
+    }
+}
+
+/// Solid angle density of the material at `r_in`'s hit scattering into the unit vector
+/// `direction`, for `emission_weight`. `None` for directions `direct_lighting` can't sample,
+/// where the material is specular.
+pub fn scattering_pdf(r_in: &Ray, hit: &HitRecord, direction: Vec3) -> Option<f64> {
+    hit.material.bsdf(r_in, hit, direction)?;
+    Some(hit.material.pdf(r_in, hit, direction)).filter(|&pdf| pdf > 0.0)
+}
+
+/// Multiple importance sampling weight of the light emitted by `world[index]` at `hit`, found by
+/// `ray` after scattering off a point lit by `direct_lighting` with the density `pdf`, as given
+/// by `scattering_pdf`. `None` for rays `direct_lighting` didn't light the origin of, like camera
+/// rays.
+pub fn emission_weight(
+    scene: &Scene,
+    ray: &Ray,
+    index: usize,
+    hit: &HitRecord,
+    pdf: Option<f64>,
+) -> f64 {
+    let pdf = match pdf {
+        Some(pdf) => pdf,
+        None => return 1.0,
+    };
+    let to_light = hit.position - ray.origin;
+    let cosine = to_light.make_unit_vec().dot(hit.normal).abs();
+    if cosine <= 0.0 {
+        return 1.0;
+    }
+    let light_pdf = scene.surface_light_pdf(ray.origin, index);
+    if light_pdf <= 0.0 {
+        return 1.0;
+    }
+    power_heuristic(pdf, light_pdf * to_light.squared_length() / cosine)
+}
+
+/// Weight of a sample taken with the density `pdf` when another strategy could have taken it
+/// with the positive density `other`.
+fn power_heuristic(pdf: f64, other: f64) -> f64 {
+    pdf * pdf / (pdf * pdf + other * other)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hitable::Hitable;
+    use crate::integrators::direct_lighting;
+    use crate::lights::point::PointLight;
+    use crate::materials::lambertian::Lambertian;
+    use crate::medium_stack::MediumStack;
+    use crate::object::{Object, Visibility};
+    use crate::ray::Ray;
+    use crate::scene::Scene;
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+    use std::f64;
+
+    fn lit_floor(blocker: bool) -> Scene {
+        let mut world: Vec<Box<dyn Hitable>> = vec![Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        ))];
+        if blocker {
+            world.push(Box::new(Sphere::new(
+                Vec3::new(0.0, 1.0, 0.0),
+                0.5,
+                Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+            )));
+        }
+
+        let light = PointLight::new(Vec3::new(0.0, 2.0, 0.0), Vec3::new(4.0, 4.0, 4.0));
+        Scene::new(world, vec![Box::new(light)])
+    }
+
+    fn shade(scene: &Scene) -> Vec3 {
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 1.0), Vec3::new(0.0, -1.0, -1.0));
+        let hit = scene
+            .world
+            .hit(&ray, 0.001, f64::MAX)
+            .expect("ray points at the floor");
+        direct_lighting(scene, &ray, &hit, &MediumStack::new())
+    }
+
+    #[test]
+    fn point_light_follows_inverse_square_law() {
+        let radiance = shade(&lit_floor(false));
+        // albedo / pi * intensity / distance^2
+        let expected = 0.5 / f64::consts::PI * 4.0 / 4.0;
+        assert!((radiance.x - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn occluded_light_casts_shadow() {
+        assert_eq!(shade(&lit_floor(true)), Vec3::origin());
+    }
+
+    #[test]
+    fn respects_shadow_visibility_and_light_links() {
+        let floor = |lights| {
+            Object::new(Box::new(Sphere::new(
+                Vec3::new(0.0, -1000.0, 0.0),
+                1000.0,
+                Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+            )))
+            .with_lights(lights)
+        };
+        let blocker = Object::new(Box::new(Sphere::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            0.5,
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )))
+        .with_visibility(Visibility {
+            shadow: false,
+            ..Visibility::ALL
+        });
+        let light = || PointLight::new(Vec3::new(0.0, 2.0, 0.0), Vec3::new(4.0, 4.0, 4.0));
+
+        let linked = Scene::new(
+            vec![Box::new(floor(vec![0])), Box::new(blocker)],
+            vec![Box::new(light())],
+        );
+        assert_eq!(shade(&linked), shade(&lit_floor(false)));
+
+        let unlinked = Scene::new(vec![Box::new(floor(vec![]))], vec![Box::new(light())]);
+        assert_eq!(shade(&unlinked), Vec3::origin());
+    }
+}
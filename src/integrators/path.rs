@@ -0,0 +1,416 @@
+use crate::aov::{Aov, AovSample};
+use crate::film::Film;
+use crate::hitable::{HitRecord, Hitable};
+use crate::integrators::restir::ResampledDirectLighting;
+use crate::integrators::{
+    emission_weight, for_each_direct_light, for_each_light_sample, scattering_pdf, sky, Integrator,
+    MAX_DEPTH,
+};
+use crate::materials::{Compositing, Scattering};
+use crate::medium_stack::MediumStack;
+use crate::ray::{Ray, RayKind};
+use crate::region::Region;
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Unidirectional path tracer, following the scattered ray of every material.
+#[derive(Debug)]
+pub struct PathTracer {
+    passes: usize,
+    resampling: Option<ResampledDirectLighting>,
+}
+
+impl PathTracer {
+    pub fn new() -> PathTracer {
+        PathTracer {
+            passes: 1,
+            resampling: None,
+        }
+    }
+
+    /// Splits the samples of every pixel over passes, so resampled direct lighting can reuse the
+    /// reservoirs of earlier ones.
+    pub fn with_passes(mut self, passes: usize) -> PathTracer {
+        self.passes = passes.max(1);
+        self
+    }
+
+    /// Lights the first hit of camera rays with reservoir resampling, for scenes with many lights.
+    pub fn with_resampled_direct_lighting(
+        mut self,
+        resampling: ResampledDirectLighting,
+    ) -> PathTracer {
+        self.resampling = Some(resampling);
+        self
+    }
+}
+
+impl Default for PathTracer {
+    fn default() -> PathTracer {
+        PathTracer::new()
+    }
+}
+
+/// How a path scattered off a surface on its way from the camera.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub scattering: Scattering,
+    /// Whether the path went through the surface rather than being reflected.
+    pub transmitted: bool,
+}
+
+impl Event {
+    fn new(r_in: &Ray, hit: &HitRecord, direction: Vec3) -> Event {
+        Event {
+            scattering: hit.material.scattering(r_in, hit, direction),
+            transmitted: r_in.direction.dot(hit.normal) * direction.dot(hit.normal) > 0.0,
+        }
+    }
+}
+
+/// Where the light found by a path comes from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Emitter {
+    /// One of `Scene::lights` or an emissive surface, reached by a shadow ray.
+    Light,
+    /// An emissive surface hit by the path.
+    Surface,
+    /// The sky, for paths leaving the scene.
+    Background,
+}
+
+/// Light the objects of the scene add to a shadow catcher seen along `r`, and the fraction of
+/// the light reaching it without them they block.
+///
+/// Unoccluded light and bounces leaving the scene count towards both, while bounces hitting
+/// objects compare the light coming back from them with the background they hide.
+fn catch_shadows(r: &Ray, scene: &Scene, hit: &HitRecord) -> (Vec3, f64) {
+    let media = MediumStack::new();
+    let mut lit = Vec3::origin();
+    let mut unshadowed = Vec3::origin();
+    for_each_light_sample(scene, r, hit, &media, |_, light, visibility| {
+        unshadowed += light;
+        lit += light * visibility;
+    });
+
+    if let Some((albedo, scattered)) = hit.material.scatter(r, hit) {
+        let scattered = scattered.with_kind(RayKind::Diffuse);
+        let background = albedo * sky(&scattered);
+        unshadowed += background;
+        lit += match scene.world.hit(&scattered, 0.001, f64::MAX) {
+            Some(next) if next.material.compositing() != Compositing::ShadowCatcher => {
+                let pdf = scattering_pdf(r, hit, scattered.direction.make_unit_vec());
+                albedo * calculate_color(&scattered, pdf, scene, 1, &media)
+            }
+            _ => background,
+        };
+    }
+
+    let shadow = if unshadowed.luminance() > 0.0 {
+        (1.0 - lit.luminance() / unshadowed.luminance()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let reflected = Vec3::new(
+        (lit.x - unshadowed.x).max(0.0),
+        (lit.y - unshadowed.y).max(0.0),
+        (lit.z - unshadowed.z).max(0.0),
+    );
+    (reflected, shadow)
+}
+
+/// Follows a path from `r` and calls `contribute` with the events between the camera and every
+/// emitter it finds, the emitter and the light it sends back along `r`. The contributions add up
+/// to `calculate_color`. `resampling` lights the first hit in place of `direct_lighting`.
+///
+/// `pdf` is the density `r` was scattered with from its origin, see `emission_weight`.
+///
+/// Paths starting at the camera (`depth` 0) and hitting a shadow catcher first see the background
+/// with the catcher composited over it, and return its alpha.
+pub fn trace(
+    r: &Ray,
+    pdf: Option<f64>,
+    scene: &Scene,
+    depth: i32,
+    media: &MediumStack,
+    resampling: Option<&ResampledDirectLighting>,
+    mut contribute: impl FnMut(&[Event], Emitter, Vec3),
+) -> Option<f64> {
+    let mut ray = *r;
+    let mut media = media.clone();
+    let mut depth = depth;
+    let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+    let mut events = Vec::new();
+    let mut pdf = pdf;
+    loop {
+        let (index, hit) = match scene.hit_object(&ray, 0.001, f64::MAX) {
+            Some(found) => found,
+            None => {
+                contribute(&events, Emitter::Background, throughput * sky(&ray));
+                return None;
+            }
+        };
+
+        // Absorption by the medium the ray travelled through to get here
+        throughput = throughput * media.transmittance(hit.t * ray.direction.length());
+        if depth == 0 && hit.material.compositing() == Compositing::ShadowCatcher {
+            let (reflected, shadow) = catch_shadows(&ray, scene, &hit);
+            let background = sky(&ray) * (1.0 - shadow);
+            contribute(
+                &events,
+                Emitter::Background,
+                throughput * (reflected + background),
+            );
+            return Some(shadow);
+        }
+        let weight = emission_weight(scene, &ray, index, &hit, pdf);
+        contribute(
+            &events,
+            Emitter::Surface,
+            throughput * hit.material.emitted(&ray, &hit) * weight,
+        );
+
+        // The reservoirs only hold lights, emissive surfaces are left to the scattered ray
+        let first_hit = events.is_empty();
+        let resampled = resampling.is_some() && first_hit;
+        let mut lit = |direction: Vec3, light: Vec3| {
+            events.push(Event::new(&ray, &hit, direction));
+            contribute(&events, Emitter::Light, throughput * light);
+            events.pop();
+        };
+        match resampling {
+            Some(resampling) if first_hit => {
+                if let Some((direction, light)) = resampling.sample_direct_light(scene, &ray, &hit)
+                {
+                    lit(direction, light);
+                }
+            }
+            _ => for_each_direct_light(scene, &ray, &hit, &media, lit),
+        }
+
+        if depth >= MAX_DEPTH {
+            return None;
+        }
+        let (albedo, scattered, scattered_media) = media.scatter(&ray, &hit)?;
+        pdf = if resampled {
+            None
+        } else {
+            scattering_pdf(&ray, &hit, scattered.direction.make_unit_vec())
+        };
+
+        // Passing through surfaces hidden by overlapping media isn't an event
+        ray = if scattered.direction != ray.direction {
+            let event = Event::new(&ray, &hit, scattered.direction);
+            events.push(event);
+            scattered.with_kind(match event.scattering {
+                Scattering::Diffuse => RayKind::Diffuse,
+                Scattering::Glossy | Scattering::Specular => RayKind::Specular,
+            })
+        } else {
+            scattered
+        };
+        throughput = throughput * albedo;
+        media = scattered_media;
+        depth += 1;
+    }
+}
+
+pub(crate) fn calculate_color(
+    r: &Ray,
+    pdf: Option<f64>,
+    scene: &Scene,
+    depth: i32,
+    media: &MediumStack,
+) -> Vec3 {
+    let mut color = Vec3::origin();
+    trace(r, pdf, scene, depth, media, None, |_, _, light| {
+        color += light
+    });
+    color
+}
+
+impl Integrator for PathTracer {
+    fn radiance(&self, ray: &Ray, scene: &Scene, _film: &Film) -> Vec3 {
+        let mut color = Vec3::origin();
+        let resampling = self.resampling.as_ref();
+        trace(
+            ray,
+            None,
+            scene,
+            0,
+            &MediumStack::new(),
+            resampling,
+            |_, _, light| color += light,
+        );
+        color
+    }
+
+    fn radiance_with_aovs(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        _film: &Film,
+        aovs: &mut AovSample<'_>,
+    ) -> Vec3 {
+        let mut color = Vec3::origin();
+        let resampling = self.resampling.as_ref();
+        let catcher_alpha = trace(
+            ray,
+            None,
+            scene,
+            0,
+            &MediumStack::new(),
+            resampling,
+            |events, emitter, light| {
+                aovs.add_light(events, emitter, light);
+                color += light
+            },
+        );
+        if let Some(alpha) = catcher_alpha {
+            aovs.set(Aov::Alpha, Vec3::new(alpha, 0.0, 0.0));
+        }
+        color
+    }
+
+    fn passes(&self) -> usize {
+        self.passes
+    }
+
+    fn prepare_pass(&mut self, _scene: &Scene, _pass: usize) {
+        if let Some(resampling) = &mut self.resampling {
+            resampling.prepare_pass();
+        }
+    }
+
+    fn crop(&mut self, region: Region) {
+        if let Some(resampling) = &mut self.resampling {
+            resampling.crop(region);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aov::{Aov, AovSample};
+    use crate::disk::Disk;
+    use crate::film::Film;
+    use crate::hitable::Hitable;
+    use crate::integrators::path::{catch_shadows, trace, Emitter, PathTracer};
+    use crate::integrators::{sky, Integrator};
+    use crate::lights::point::PointLight;
+    use crate::materials::diffuse_light::DiffuseLight;
+    use crate::materials::holdout::Holdout;
+    use crate::materials::lambertian::Lambertian;
+    use crate::materials::shadow_catcher::ShadowCatcher;
+    use crate::medium_stack::MediumStack;
+    use crate::ray::Ray;
+    use crate::scene::Scene;
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+    use std::f64;
+
+    fn shadow_under(blocker: bool) -> (Vec3, f64) {
+        let mut world: Vec<Box<dyn Hitable>> = vec![Box::new(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Box::new(ShadowCatcher::new(Vec3::new(0.5, 0.5, 0.5))),
+        ))];
+        if blocker {
+            world.push(Box::new(Sphere::new(
+                Vec3::new(0.0, 1.0, 0.0),
+                0.5,
+                Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+            )));
+        }
+        let light = PointLight::new(Vec3::new(0.0, 2.0, 0.0), Vec3::new(40.0, 40.0, 40.0));
+        let scene = Scene::new(world, vec![Box::new(light)]);
+
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 1.0), Vec3::new(0.0, -1.0, -1.0));
+        let hit = scene
+            .world
+            .hit(&ray, 0.001, f64::MAX)
+            .expect("ray points at the floor");
+        catch_shadows(&ray, &scene, &hit)
+    }
+
+    #[test]
+    fn shadow_catcher_only_keeps_shadows() {
+        assert_eq!(shadow_under(false), (Vec3::origin(), 0.0));
+
+        let (_, shadow) = shadow_under(true);
+        assert!(shadow > 0.5, "{}", shadow);
+    }
+
+    #[test]
+    fn holdout_cuts_out_the_background() {
+        let world: Vec<Box<dyn Hitable>> = vec![
+            Box::new(Sphere::new(
+                Vec3::new(0.0, -1000.0, 0.0),
+                1000.0,
+                Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+            )),
+            Box::new(Sphere::new(
+                Vec3::new(0.0, 1.0, 0.0),
+                0.5,
+                Box::new(Holdout::new()),
+            )),
+        ];
+        let light = PointLight::new(Vec3::new(0.0, 3.0, 0.0), Vec3::new(40.0, 40.0, 40.0));
+        let scene = Scene::new(world, vec![Box::new(light)]);
+
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut aovs = AovSample::at_first_hit(&ray, &scene);
+        let radiance =
+            PathTracer::new().radiance_with_aovs(&ray, &scene, &Film::new(1, 1), &mut aovs);
+
+        // Premultiplied like transparent renders, without the background behind
+        let alpha = aovs.get(Aov::Alpha).x;
+        assert_eq!(alpha, 0.0);
+        assert_eq!(radiance - sky(&ray) * (1.0 - alpha), Vec3::origin());
+    }
+
+    #[test]
+    fn emissive_surfaces_are_counted_once() {
+        // Disk light of radius 1 facing the floor from a height of 1
+        let world: Vec<Box<dyn Hitable>> = vec![
+            Box::new(Sphere::new(
+                Vec3::new(0.0, -1000.0, 0.0),
+                1000.0,
+                Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+            )),
+            Box::new(Disk::new(
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, -1.0, 0.0),
+                1.0,
+                Box::new(DiffuseLight::new(Vec3::new(4.0, 4.0, 4.0))),
+            )),
+        ];
+        let scene = Scene::new(world, vec![]);
+
+        // Found both by shadow rays and by rays scattered off the floor
+        let ray = Ray::new(Vec3::new(0.0, 0.5, 0.5), Vec3::new(0.0, -1.0, -1.0));
+        let samples = 20_000;
+        let mut total = Vec3::origin();
+        for _ in 0..samples {
+            trace(
+                &ray,
+                None,
+                &scene,
+                0,
+                &MediumStack::new(),
+                None,
+                |_, emitter, light| {
+                    if emitter != Emitter::Background {
+                        total += light
+                    }
+                },
+            );
+        }
+
+        // albedo * radiance * sin^2 of the half angle the disk subtends
+        let expected = 0.5 * 4.0 * 0.5;
+        let radiance = total.x / f64::from(samples);
+        assert!((radiance - expected).abs() < 0.03, "{}", radiance);
+    }
+}
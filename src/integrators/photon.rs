@@ -0,0 +1,333 @@
+use crate::film::Film;
+use crate::hitable::{HitRecord, Hitable};
+use crate::integrators::{sky, unweighted_direct_lighting, Integrator, MAX_DEPTH};
+use crate::kd_tree::KdTree;
+use crate::materials::Scattering;
+use crate::medium_stack::MediumStack;
+use crate::onb::{random_cosine_direction, Onb};
+use crate::ray::Ray;
+use crate::sampler;
+use crate::scene::{Emitter, Scene};
+use crate::vec3::Vec3;
+use rayon::prelude::*;
+use std::f64;
+
+/// Light carried by a photon when it landed on a diffuse surface.
+#[derive(Debug, Copy, Clone)]
+struct Photon {
+    /// Unit vector the photon was travelling along.
+    direction: Vec3,
+    power: Vec3,
+}
+
+/// Photon mapper (Jensen 1996).
+///
+/// Before rendering, photons are traced from the lights and emissive surfaces and stored on every
+/// diffuse surface they land on, except the first one, where they're sampled directly.
+/// Camera rays follow glossy and specular bounces to the first diffuse surface, where light
+/// sampling gives the direct lighting and the photons within `radius` give the rest, caustics
+/// included.
+///
+/// With `progressive`, the samples of every pixel are split over passes with fresh photons and a
+/// radius shrinking like stochastic progressive photon mapping (Knaus and Zwicker 2011), so the
+/// blur of the density estimate vanishes as passes are added.
+///
+/// Light links stop photons from lights at the first surface they land on when it isn't linked,
+/// like direct lighting. The sky has nowhere to emit photons from, so its light is gathered by
+/// carrying on the camera paths past the diffuse surface like a path tracer. Photons ignore
+/// absorbing dielectric interiors.
+pub struct PhotonMapper {
+    photon_count: usize,
+    initial_radius: f64,
+    passes: usize,
+    alpha: f64,
+    radius: f64,
+    photons: KdTree<Photon>,
+}
+
+impl PhotonMapper {
+    /// `photon_count` photons per pass, gathered within `radius` of the shaded points.
+    pub fn new(photon_count: usize, radius: f64) -> PhotonMapper {
+        PhotonMapper {
+            photon_count,
+            initial_radius: radius,
+            passes: 1,
+            alpha: 2.0 / 3.0,
+            radius,
+            photons: KdTree::new(Vec::new()),
+        }
+    }
+
+    /// Renders in `passes` passes, keeping a fraction `alpha` of the photons each time the radius
+    /// shrinks. 2/3 is a good trade off between noise and blur.
+    pub fn progressive(mut self, passes: usize, alpha: f64) -> PhotonMapper {
+        self.passes = passes.max(1);
+        self.alpha = alpha.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Radius of pass `pass` counted from zero, every pass shrinks it by r'^2 = r^2 (i + alpha) / (i + 1).
+    fn pass_radius(&self, pass: usize) -> f64 {
+        let mut radius_squared = self.initial_radius * self.initial_radius;
+        for i in 1..=pass {
+            radius_squared *= (i as f64 + self.alpha) / (i as f64 + 1.0);
+        }
+        radius_squared.sqrt()
+    }
+
+    fn trace_photon(scene: &Scene, photon_count: usize) -> Vec<(Vec3, Photon)> {
+        let mut photons = Vec::new();
+
+        // Direct lighting is sampled from the camera side, only lights have light links
+        let (mut ray, power, light) = match scene.sample_emitter(sampler::random()) {
+            Some((Emitter::Light(index, light), probability)) => {
+                let emission = match light.sample_emission() {
+                    Some(emission) if emission.pdf > 0.0 => emission,
+                    _ => return photons,
+                };
+                let power = emission.intensity / (probability * emission.pdf);
+                (emission.ray, power, Some(index))
+            }
+            Some((Emitter::Surface(_, object), probability)) => {
+                let sample = match object.sample_surface() {
+                    Some(sample) => sample,
+                    None => return photons,
+                };
+                let hit = sample.hit;
+                let area_pdf = probability / sample.area;
+
+                // Cosine weighted directions, the cosine cancels out with their density
+                let direction = Onb::from_w(hit.normal).local(random_cosine_direction());
+                let towards = Ray::new(hit.position + direction, direction * -1.0);
+                let power = hit.material.emitted(&towards, &hit) * (f64::consts::PI / area_pdf);
+                (Ray::new(hit.position, direction), power, None)
+            }
+            None => return photons,
+        };
+
+        let mut power = power / photon_count as f64;
+        for depth in 0..MAX_DEPTH {
+            let hit = match scene.world.hit(&ray, 0.001, f64::MAX) {
+                Some(hit) => hit,
+                None => break,
+            };
+
+            if depth == 0 && light.is_some_and(|index| !hit.lit_by(index)) {
+                break;
+            }
+
+            let direction = ray.direction.make_unit_vec();
+            if depth > 0 && is_diffuse(&ray, &hit) {
+                photons.push((hit.position, Photon { direction, power }));
+            }
+
+            let (attenuation, scattered) = match hit.material.scatter(&ray, &hit) {
+                Some(scattered) => scattered,
+                None => break,
+            };
+
+            // Russian roulette keeps the power of surviving photons about constant
+            let survival = attenuation.x.max(attenuation.y).max(attenuation.z).min(1.0);
+            if survival <= 0.0 || sampler::random() >= survival {
+                break;
+            }
+            power = power * attenuation / survival;
+            ray = scattered;
+        }
+
+        photons
+    }
+
+    /// Radiance reflected towards `r_in` by the photons around the hit point.
+    fn estimate(&self, r_in: &Ray, hit: &HitRecord) -> Vec3 {
+        let mut total = Vec3::origin();
+        self.photons
+            .for_each_within(hit.position, self.radius, |photon, _| {
+                let towards_light = photon.direction * -1.0;
+                let cosine = hit.normal.dot(towards_light).abs();
+                if cosine < 1e-4 {
+                    return;
+                }
+
+                // The BSDF includes the cosine, which the photon density already accounts for
+                if let Some(bsdf) = hit.material.bsdf(r_in, hit, towards_light) {
+                    total += bsdf * photon.power / cosine;
+                }
+            });
+
+        total / (f64::consts::PI * self.radius * self.radius)
+    }
+
+    /// Light of the sky arriving along `r`, directly or after any number of bounces.
+    fn sky_light(r: &Ray, scene: &Scene, depth: i32, media: &MediumStack) -> Vec3 {
+        let hit = match scene.world.hit(r, 0.001, f64::MAX) {
+            Some(hit) => hit,
+            None => return sky(r),
+        };
+
+        let transmittance = media.transmittance(hit.t * r.direction.length());
+        match media.scatter(r, &hit) {
+            Some((albedo, scattered, media)) if depth < MAX_DEPTH => {
+                let survival = albedo.x.max(albedo.y).max(albedo.z).min(1.0);
+                if survival <= 0.0 || sampler::random() >= survival {
+                    return Vec3::origin();
+                }
+                transmittance
+                    * albedo
+                    * PhotonMapper::sky_light(&scattered, scene, depth + 1, &media)
+                    / survival
+            }
+            _ => Vec3::origin(),
+        }
+    }
+
+    fn calculate_color(&self, r: &Ray, scene: &Scene, depth: i32, media: &MediumStack) -> Vec3 {
+        let hit = match scene.world.hit(r, 0.001, f64::MAX) {
+            Some(hit) => hit,
+            None => return sky(r),
+        };
+
+        let transmittance = media.transmittance(hit.t * r.direction.length());
+        let emitted = hit.material.emitted(r, &hit);
+        if is_diffuse(r, &hit) {
+            let sky_light = match media.scatter(r, &hit) {
+                Some((albedo, scattered, media)) if depth < MAX_DEPTH => {
+                    albedo * PhotonMapper::sky_light(&scattered, scene, depth + 1, &media)
+                }
+                _ => Vec3::origin(),
+            };
+            return transmittance
+                * (emitted
+                    + unweighted_direct_lighting(scene, r, &hit, media)
+                    + self.estimate(r, &hit)
+                    + sky_light);
+        }
+
+        match media.scatter(r, &hit) {
+            Some((albedo, scattered, media)) if depth < MAX_DEPTH => {
+                transmittance
+                    * (emitted
+                        + albedo * self.calculate_color(&scattered, scene, depth + 1, &media))
+            }
+            _ => transmittance * emitted,
+        }
+    }
+}
+
+/// Whether photons are stored and looked up at the hit, rather than followed through it. Glossy
+/// surfaces blur the density estimate too much to use it.
+fn is_diffuse(r_in: &Ray, hit: &HitRecord) -> bool {
+    let normal = hit.facing_normal(r_in);
+    hit.material.scattering(r_in, hit, normal) == Scattering::Diffuse
+}
+
+impl Integrator for PhotonMapper {
+    fn radiance(&self, ray: &Ray, scene: &Scene, _film: &Film) -> Vec3 {
+        self.calculate_color(ray, scene, 0, &MediumStack::new())
+    }
+
+    fn passes(&self) -> usize {
+        self.passes
+    }
+
+    fn prepare_pass(&mut self, scene: &Scene, pass: usize) {
+        let photon_count = self.photon_count;
+        let photons = (0..photon_count)
+            .into_par_iter()
+            .flat_map(|_| PhotonMapper::trace_photon(scene, photon_count))
+            .collect();
+
+        self.photons = KdTree::new(photons);
+        self.radius = self.pass_radius(pass);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::film::Film;
+    use crate::hitable::Hitable;
+    use crate::integrators::path::PathTracer;
+    use crate::integrators::photon::PhotonMapper;
+    use crate::integrators::Integrator;
+    use crate::lights::point::PointLight;
+    use crate::materials::diffuse_light::DiffuseLight;
+    use crate::materials::lambertian::Lambertian;
+    use crate::object::Object;
+    use crate::quad::Quad;
+    use crate::ray::Ray;
+    use crate::rect::AxisAlignedRect;
+    use crate::scene::Scene;
+    use crate::vec3::Vec3;
+
+    fn floor() -> Box<dyn Hitable> {
+        Box::new(AxisAlignedRect::xz(
+            (-5.0, 5.0),
+            (-5.0, 5.0),
+            0.0,
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        ))
+    }
+
+    /// Mean radiance of `samples` rays looking down at the middle of the floor.
+    fn mean(integrator: &dyn Integrator, scene: &Scene, samples: usize) -> Vec3 {
+        let film = Film::new(1, 1);
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 1.0), Vec3::new(0.0, -1.0, -1.0));
+        let total = (0..samples).fold(Vec3::origin(), |total, _| {
+            total + integrator.radiance(&ray, scene, &film)
+        });
+        total / samples as f64
+    }
+
+    fn assert_matches_path_tracer(scene: &Scene) {
+        // The photons are the same for every sample of a pass, so their noise only averages out
+        // over the passes
+        let passes = 8;
+        let mut photons = PhotonMapper::new(50_000, 1.0).progressive(passes, 2.0 / 3.0);
+        let mut actual = Vec3::origin();
+        for pass in 0..passes {
+            photons.prepare_pass(scene, pass);
+            actual += mean(&photons, scene, 1_000) / passes as f64;
+        }
+
+        let expected = mean(&PathTracer::new(), scene, 20_000);
+        assert!(expected.x > 0.05, "{:?}", expected);
+        assert!(
+            (actual - expected).length() < 0.05 * expected.length(),
+            "{:?} {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn sky_lights_diffuse_surfaces() {
+        assert_matches_path_tracer(&Scene::new(vec![floor()], Vec::new()));
+    }
+
+    #[test]
+    fn emissive_surfaces_emit_photons() {
+        // Facing down, u x v = -y, and hiding the sky from most of the floor
+        let lamp = Quad::new(
+            Vec3::new(-5.0, 2.0, -5.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 10.0),
+            Box::new(DiffuseLight::new(Vec3::new(4.0, 4.0, 4.0))),
+        );
+        assert_matches_path_tracer(&Scene::new(vec![floor(), Box::new(lamp)], Vec::new()));
+    }
+
+    #[test]
+    fn light_links_stop_photons() {
+        // The light only reaches the floor after bouncing off the ceiling
+        let ceiling = AxisAlignedRect::xz(
+            (-5.0, 5.0),
+            (-5.0, 5.0),
+            2.0,
+            Box::new(Lambertian::new(Vec3::new(0.8, 0.8, 0.8))),
+        );
+        let floor = Object::new(floor()).with_lights(Vec::new());
+        let light = PointLight::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(4.0, 4.0, 4.0));
+        let world: Vec<Box<dyn Hitable>> = vec![Box::new(floor), Box::new(ceiling)];
+        assert_matches_path_tracer(&Scene::new(world, vec![Box::new(light)]));
+    }
+}
@@ -0,0 +1,327 @@
+use crate::hitable::{HitRecord, Hitable};
+use crate::lights::LightSample;
+use crate::ray::{Ray, RayKind};
+use crate::region::Region;
+use crate::sampler;
+use crate::scene::Scene;
+use crate::vec3::Vec3;
+use std::sync::Mutex;
+
+/// Light picked by weighted reservoir sampling out of a stream of candidates.
+#[derive(Debug, Copy, Clone, Default)]
+struct Reservoir {
+    light: Option<usize>,
+    /// Target function of the picked light at the point the reservoir was last resampled for.
+    target: f64,
+    weight_sum: f64,
+    /// Number of candidates seen, including those of merged reservoirs.
+    count: f64,
+    /// Unbiased contribution weight of the picked light, like the inverse of a pdf.
+    weight: f64,
+    /// Normal and distance from the camera of the point the reservoir was built for.
+    surface: Option<(Vec3, f64)>,
+}
+
+impl Reservoir {
+    fn update(&mut self, light: usize, target: f64, weight: f64, count: f64) {
+        self.weight_sum += weight;
+        self.count += count;
+        if weight > 0.0 && sampler::random() * self.weight_sum < weight {
+            self.light = Some(light);
+            self.target = target;
+        }
+    }
+
+    /// Adds the light picked by `other`, with `target` its target function at the current point.
+    fn merge(&mut self, other: &Reservoir, target: f64, max_count: f64) {
+        if let Some(light) = other.light {
+            let count = other.count.min(max_count);
+            self.update(light, target, target * other.weight * count, count);
+        } else {
+            self.count += other.count.min(max_count);
+        }
+    }
+
+    fn finish(&mut self) {
+        self.weight = if self.target > 0.0 && self.count > 0.0 {
+            self.weight_sum / (self.count * self.target)
+        } else {
+            0.0
+        };
+    }
+}
+
+/// Direct lighting at the first hit of camera rays by resampled importance sampling with
+/// per-pixel reservoirs (ReSTIR, Bitterli et al. 2020).
+///
+/// Many lights are picked uniformly as candidates and only the one chosen proportionally to its
+/// unshadowed contribution gets a shadow ray. The reservoir of every pixel is kept for the next
+/// pass, where it's merged into the reservoirs of the same pixel and its neighbours so good
+/// lights found anywhere spread over the image. Keeping the same instance across the frames of
+/// an animation reuses the last frame the same way.
+///
+/// Like the biased variant of the paper, reused reservoirs count all their candidates even where
+/// the light can't reach the current point, which slightly darkens areas lit by lights close to
+/// their horizon. Neighbours are only reused on surfaces with similar normals and depths to keep
+/// this small.
+///
+/// Reservoirs are only kept for the pixels being rendered, see `crop` for rendering in tiles.
+#[derive(Debug)]
+pub struct ResampledDirectLighting {
+    width: usize,
+    height: usize,
+    /// Pixels with reservoirs, rows counted from the top.
+    region: Region,
+    candidates: usize,
+    neighbours: usize,
+    radius: f64,
+    /// Cap on the candidates a reused reservoir counts for, relative to `candidates`.
+    history: f64,
+    current: Vec<Mutex<Reservoir>>,
+    previous: Vec<Reservoir>,
+}
+
+impl ResampledDirectLighting {
+    /// Reservoirs for an image of `width` by `height` pixels.
+    pub fn new(width: usize, height: usize) -> ResampledDirectLighting {
+        let region = Region::full(width, height);
+        ResampledDirectLighting {
+            width,
+            height,
+            region,
+            candidates: 32,
+            neighbours: 4,
+            radius: 10.0,
+            history: 20.0,
+            current: ResampledDirectLighting::reservoirs(region),
+            previous: vec![Reservoir::default(); region.area()],
+        }
+    }
+
+    fn reservoirs(region: Region) -> Vec<Mutex<Reservoir>> {
+        (0..region.area())
+            .map(|_| Mutex::new(Reservoir::default()))
+            .collect()
+    }
+
+    /// Only keeps reservoirs for the pixels of `region` from now on, when the image is rendered a
+    /// region at a time. Reservoirs of another region are dropped, so nothing is reused across
+    /// its border.
+    pub fn crop(&mut self, region: Region) {
+        if region != self.region {
+            self.region = region;
+            self.current = ResampledDirectLighting::reservoirs(region);
+            self.previous = vec![Reservoir::default(); region.area()];
+        }
+    }
+
+    /// Number of lights considered for every shading point before reuse.
+    pub fn with_candidates(mut self, candidates: usize) -> ResampledDirectLighting {
+        self.candidates = candidates.max(1);
+        self
+    }
+
+    /// Reuses the reservoirs of `neighbours` random pixels within `radius` pixels, 0 disables
+    /// spatial reuse.
+    pub fn with_spatial_reuse(mut self, neighbours: usize, radius: f64) -> ResampledDirectLighting {
+        self.neighbours = neighbours;
+        self.radius = radius;
+        self
+    }
+
+    /// Limits the weight of reservoirs from earlier passes to `history` times the candidates of a
+    /// new one, so stale lighting fades out. Neighbours come from the last pass too, so 0 disables
+    /// all reuse.
+    pub fn with_history(mut self, history: f64) -> ResampledDirectLighting {
+        self.history = history.max(0.0);
+        self
+    }
+
+    /// Starts a new pass or frame, the reservoirs of the last one become available for reuse.
+    pub fn prepare_pass(&mut self) {
+        self.previous = self
+            .current
+            .iter_mut()
+            .map(|reservoir| {
+                std::mem::take(
+                    reservoir
+                        .get_mut()
+                        .expect("no thread panics holding the lock"),
+                )
+            })
+            .collect();
+    }
+
+    /// Pixel of `region` the camera ray `ray` goes through, relative to its top left corner.
+    fn pixel(&self, scene: &Scene, ray: &Ray) -> Option<(usize, usize)> {
+        let (u, v, _) = scene.camera.importance(ray.direction)?;
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = self.height - 1 - ((v * self.height as f64) as usize).min(self.height - 1);
+        let x = i.clamp(self.region.x0, self.region.x1 - 1) - self.region.x0;
+        let y = y.clamp(self.region.y0, self.region.y1 - 1) - self.region.y0;
+        Some((x, y))
+    }
+
+    /// Unshadowed light reflected towards `r_in` from `light`, and the sample it comes from.
+    fn unshadowed(
+        scene: &Scene,
+        r_in: &Ray,
+        hit: &HitRecord,
+        light: usize,
+    ) -> Option<(Vec3, LightSample)> {
+        if !hit.lit_by(light) {
+            return None;
+        }
+        let sample = scene.lights()[light].sample(hit.position)?;
+        let bsdf = hit.material.bsdf(r_in, hit, sample.direction)?;
+        Some((bsdf * sample.radiance, sample))
+    }
+
+    fn target(scene: &Scene, r_in: &Ray, hit: &HitRecord, light: usize) -> f64 {
+        ResampledDirectLighting::unshadowed(scene, r_in, hit, light)
+            .map_or(0.0, |(contribution, _)| contribution.luminance())
+    }
+
+    /// Light reflected towards the camera ray `r_in` from the lights in the scene, in place of
+    /// `direct_lighting` at its first hit.
+    pub fn direct_lighting(&self, scene: &Scene, r_in: &Ray, hit: &HitRecord) -> Vec3 {
+        self.sample_direct_light(scene, r_in, hit)
+            .map_or(Vec3::origin(), |(_, light)| light)
+    }
+
+    /// `direct_lighting` along with the unit vector towards the light it was resampled from,
+    /// `None` where no light reaches the hit.
+    pub fn sample_direct_light(
+        &self,
+        scene: &Scene,
+        r_in: &Ray,
+        hit: &HitRecord,
+    ) -> Option<(Vec3, Vec3)> {
+        let light_count = scene.lights().len();
+        let (i, j) = match self.pixel(scene, r_in) {
+            Some(pixel) if light_count > 0 && self.region.area() > 0 => pixel,
+            _ => return None,
+        };
+
+        // Candidates picked uniformly, weighted by target over source pdf
+        let mut reservoir = Reservoir::default();
+        for _ in 0..self.candidates {
+            let light = ((sampler::random() * light_count as f64) as usize).min(light_count - 1);
+            let target = ResampledDirectLighting::target(scene, r_in, hit, light);
+            reservoir.update(light, target, target * light_count as f64, 1.0);
+        }
+        reservoir.finish();
+
+        let max_count = self.history * self.candidates as f64;
+        let retarget = |other: &Reservoir| {
+            other.light.map_or(0.0, |light| {
+                ResampledDirectLighting::target(scene, r_in, hit, light)
+            })
+        };
+
+        // Reuse over time, the result is what later passes and neighbours see
+        let width = self.region.width();
+        let previous = &self.previous[j * width + i];
+        reservoir.merge(previous, retarget(previous), max_count);
+        reservoir.finish();
+        let depth = hit.t * r_in.direction.length();
+        reservoir.surface = Some((hit.normal, depth));
+        *self.current[j * width + i]
+            .lock()
+            .expect("no thread panics holding the lock") = reservoir;
+
+        // Reuse over space, from the last pass so the result doesn't depend on rendering order
+        for _ in 0..self.neighbours {
+            let angle = 2.0 * std::f64::consts::PI * sampler::random();
+            let distance = self.radius * sampler::random().sqrt();
+            let x = (i as f64 + distance * angle.cos()).round();
+            let y = (j as f64 + distance * angle.sin()).round();
+            if x < 0.0 || y < 0.0 || x >= width as f64 || y >= self.region.height() as f64 {
+                continue;
+            }
+
+            // Lights picked for other surfaces are likely useless here
+            let neighbour = &self.previous[y as usize * width + x as usize];
+            match neighbour.surface {
+                Some((normal, neighbour_depth))
+                    if normal.dot(hit.normal) > 0.9
+                        && (neighbour_depth - depth).abs() < 0.1 * depth => {}
+                _ => continue,
+            }
+            reservoir.merge(neighbour, retarget(neighbour), max_count);
+        }
+        reservoir.finish();
+
+        let light = match reservoir.light {
+            Some(light) if reservoir.weight > 0.0 => light,
+            _ => return None,
+        };
+        let (contribution, sample) = ResampledDirectLighting::unshadowed(scene, r_in, hit, light)?;
+
+        let shadow_ray = Ray::new(hit.position, sample.direction)
+            .with_wavelength(r_in.wavelength)
+            .with_kind(RayKind::Shadow);
+        let visibility = scene
+            .world
+            .transmittance(&shadow_ray, 0.001, sample.distance - 0.001);
+        if visibility == 0.0 {
+            return None;
+        }
+
+        Some((
+            sample.direction,
+            contribution * (reservoir.weight * visibility),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::camera::Camera;
+    use crate::integrators::restir::{ResampledDirectLighting, Reservoir};
+    use crate::region::Region;
+    use crate::scene::Scene;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn picks_candidates_proportionally_to_weight() {
+        let mut picked_heavy = 0;
+        for _ in 0..10_000 {
+            let mut reservoir = Reservoir::default();
+            reservoir.update(0, 1.0, 1.0, 1.0);
+            reservoir.update(1, 3.0, 3.0, 1.0);
+            reservoir.update(2, 0.0, 0.0, 1.0);
+            reservoir.finish();
+
+            assert_eq!(reservoir.count, 3.0);
+            if reservoir.light == Some(1) {
+                picked_heavy += 1;
+                // Average weight over target, per candidate
+                assert!((reservoir.weight - 4.0 / 9.0).abs() < 1e-12);
+            }
+        }
+        assert!((7000..8000).contains(&picked_heavy));
+    }
+
+    #[test]
+    fn keeps_reservoirs_for_the_cropped_region() {
+        let camera = Camera::new(
+            Vec3::origin(),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            2.0,
+            0.0,
+            1.0,
+        );
+        let scene = Scene::new(Vec::new(), Vec::new()).with_camera(camera);
+        let mut resampling = ResampledDirectLighting::new(200, 100);
+        resampling.crop(Region::new(40, 20, 50, 28));
+        assert_eq!(resampling.current.len(), 80);
+        assert_eq!(resampling.previous.len(), 80);
+
+        // Pixel 43, 25 from the top is row 74 from the bottom
+        let ray = scene.camera.get_ray(43.5 / 200.0, 74.5 / 100.0);
+        assert_eq!(resampling.pixel(&scene, &ray), Some((3, 5)));
+    }
+}
@@ -0,0 +1,83 @@
+use crate::film::Film;
+use crate::integrators::{
+    direct_lighting, emission_weight, scattering_pdf, sky, Integrator, MAX_DEPTH,
+};
+use crate::medium_stack::MediumStack;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::scene::Scene;
+use crate::spectrum::{xyz_to_rgb, SampledSpectrum, SampledWavelengths};
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Path tracer carrying a handful of wavelengths along every path instead of RGB.
+///
+/// Material colors are upsampled to spectra at every hit, and materials see the hero wavelength
+/// on the ray, so dispersive dielectrics split white light into its colors.
+#[derive(Debug, Default)]
+pub struct SpectralPathTracer;
+
+impl SpectralPathTracer {
+    pub fn new() -> SpectralPathTracer {
+        SpectralPathTracer
+    }
+}
+
+/// Light arriving along `r`, which was scattered with the density `pdf` as for
+/// `emission_weight`.
+fn calculate_spectrum(
+    r: &Ray,
+    pdf: Option<f64>,
+    scene: &Scene,
+    depth: i32,
+    media: &MediumStack,
+    wavelengths: &mut SampledWavelengths,
+) -> SampledSpectrum {
+    match scene.hit_object(r, 0.001, f64::MAX) {
+        Some((index, hit)) => {
+            let transmittance =
+                wavelengths.spectrum_from_rgb(media.transmittance(hit.t * r.direction.length()));
+            let weight = emission_weight(scene, r, index, &hit, pdf);
+            let emitted = SampledSpectrum::constant(weight)
+                * hit.material.emitted_spectrum(r, &hit, wavelengths)
+                + wavelengths.spectrum_from_rgb(direct_lighting(scene, r, &hit, media));
+
+            if hit.material.dispersive() {
+                wavelengths.terminate_secondary();
+            }
+
+            match media.scatter(r, &hit) {
+                Some((albedo, scattered, media)) => {
+                    if depth < MAX_DEPTH {
+                        let scattered = scattered.with_wavelength(r.wavelength);
+                        let pdf = scattering_pdf(r, &hit, scattered.direction.make_unit_vec());
+                        let albedo = wavelengths.spectrum_from_rgb(albedo);
+                        let incoming = calculate_spectrum(
+                            &scattered,
+                            pdf,
+                            scene,
+                            depth + 1,
+                            &media,
+                            wavelengths,
+                        );
+                        return transmittance * (emitted + albedo * incoming);
+                    }
+                    transmittance * emitted
+                }
+                None => transmittance * emitted,
+            }
+        }
+        None => wavelengths.spectrum_from_rgb(sky(r)),
+    }
+}
+
+impl Integrator for SpectralPathTracer {
+    fn radiance(&self, ray: &Ray, scene: &Scene, _film: &Film) -> Vec3 {
+        let mut wavelengths = SampledWavelengths::sample(sampler::random());
+        let ray = ray.with_wavelength(Some(wavelengths.hero()));
+
+        let spectrum =
+            calculate_spectrum(&ray, None, scene, 0, &MediumStack::new(), &mut wavelengths);
+        xyz_to_rgb(wavelengths.to_xyz(&spectrum))
+    }
+}
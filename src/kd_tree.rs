@@ -0,0 +1,138 @@
+use crate::vec3::Vec3;
+
+/// Balanced kd-tree over points with attached data, for finding everything near a position.
+///
+/// The tree is implicit: every subrange of `items` stores its splitting point in the middle, with
+/// smaller coordinates along the split axis before it.
+#[derive(Debug)]
+pub struct KdTree<T> {
+    items: Vec<(Vec3, T)>,
+    axes: Vec<usize>,
+}
+
+fn component(v: Vec3, axis: usize) -> f64 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+impl<T> KdTree<T> {
+    pub fn new(mut items: Vec<(Vec3, T)>) -> KdTree<T> {
+        let mut axes = vec![0; items.len()];
+        KdTree::build(&mut items, &mut axes);
+        KdTree { items, axes }
+    }
+
+    fn build(items: &mut [(Vec3, T)], axes: &mut [usize]) {
+        if items.len() <= 1 {
+            return;
+        }
+
+        // Split the widest extent
+        let (mut min, mut max) = (items[0].0, items[0].0);
+        for (p, _) in items.iter() {
+            min = Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        let extent = max - min;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+
+        let middle = items.len() / 2;
+        items.select_nth_unstable_by(middle, |(a, _), (b, _)| {
+            component(*a, axis)
+                .partial_cmp(&component(*b, axis))
+                .expect("positions should not be NaN")
+        });
+        axes[middle] = axis;
+
+        let (before, after) = items.split_at_mut(middle);
+        let (axes_before, axes_after) = axes.split_at_mut(middle);
+        KdTree::build(before, axes_before);
+        KdTree::build(&mut after[1..], &mut axes_after[1..]);
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Calls `f` with every item within `radius` of `p`, and its squared distance.
+    pub fn for_each_within(&self, p: Vec3, radius: f64, mut f: impl FnMut(&T, f64)) {
+        self.visit(0, self.items.len(), p, radius * radius, &mut f);
+    }
+
+    fn visit(
+        &self,
+        start: usize,
+        end: usize,
+        p: Vec3,
+        radius_squared: f64,
+        f: &mut impl FnMut(&T, f64),
+    ) {
+        if start >= end {
+            return;
+        }
+
+        let middle = start + (end - start) / 2;
+        let (position, item) = &self.items[middle];
+        let distance_squared = (*position - p).squared_length();
+        if distance_squared <= radius_squared {
+            f(item, distance_squared);
+        }
+
+        let axis = self.axes[middle];
+        let offset = component(p, axis) - component(*position, axis);
+        let (near, far) = if offset < 0.0 {
+            ((start, middle), (middle + 1, end))
+        } else {
+            ((middle + 1, end), (start, middle))
+        };
+
+        self.visit(near.0, near.1, p, radius_squared, f);
+        if offset * offset <= radius_squared {
+            self.visit(far.0, far.1, p, radius_squared, f);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kd_tree::KdTree;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn finds_the_same_points_as_brute_force() {
+        let points: Vec<Vec3> = (0..500)
+            .map(|_| Vec3::new(rand::random(), rand::random(), rand::random()))
+            .collect();
+        let tree = KdTree::new(
+            points
+                .iter()
+                .cloned()
+                .enumerate()
+                .map(|(i, p)| (p, i))
+                .collect(),
+        );
+
+        let center = Vec3::new(0.5, 0.4, 0.6);
+        let mut found = Vec::new();
+        tree.for_each_within(center, 0.2, |&i, _| found.push(i));
+        found.sort();
+
+        let expected: Vec<usize> = (0..points.len())
+            .filter(|&i| (points[i] - center).length() <= 0.2)
+            .collect();
+        assert_eq!(found, expected);
+    }
+}
@@ -1,6 +1,34 @@
+pub mod aabb;
+pub mod aov;
 pub mod camera;
+pub mod constant_medium;
+pub mod cryptomatte;
+pub mod cuboid;
+pub mod denoise;
+pub mod disk;
+pub mod exr;
+pub mod film;
+pub mod heterogeneous_medium;
 pub mod hitable;
+pub mod integrators;
+pub mod kd_tree;
+pub mod lights;
+pub mod lpe;
 pub mod materials;
+pub mod medium_stack;
+pub mod object;
+pub mod onb;
+pub mod photometry;
+pub mod quad;
 pub mod ray;
+pub mod rect;
+pub mod region;
+pub mod sampler;
+pub mod scene;
+pub mod sd_tree;
+pub mod spectrum;
 pub mod sphere;
+pub mod textures;
+pub mod tiles;
 pub mod vec3;
+pub mod voxel_grid;
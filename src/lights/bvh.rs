@@ -0,0 +1,327 @@
+use crate::lights::{Light, LightBounds};
+use crate::vec3::Vec3;
+
+/// Node of the hierarchy, the first child of an interior node directly follows it.
+#[derive(Debug)]
+enum Node {
+    Leaf {
+        bounds: LightBounds,
+        light: usize,
+    },
+    Interior {
+        bounds: LightBounds,
+        second_child: usize,
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> &LightBounds {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Hierarchy over the bounds of the lights, for picking one light proportionally to its estimated
+/// contribution to a point in time logarithmic in the number of lights.
+///
+/// Lights without bounds can't be placed in the hierarchy, they're kept in a separate list.
+#[derive(Debug, Default)]
+pub struct LightBvh {
+    nodes: Vec<Node>,
+    infinite: Vec<usize>,
+    /// Node of every light in the hierarchy.
+    leaves: Vec<Option<usize>>,
+}
+
+fn component(v: Vec3, axis: usize) -> f64 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+impl LightBvh {
+    /// Light indices refer to the position in `lights`.
+    pub fn new(lights: &[Box<dyn Light>]) -> LightBvh {
+        LightBvh::from_bounds(lights.iter().map(|light| light.bounds()))
+    }
+
+    /// Hierarchy over anything emitting light, with the bounds as given by `Light::bounds`.
+    /// Light indices refer to the position in `bounds`.
+    pub fn from_bounds(bounds: impl IntoIterator<Item = Option<LightBounds>>) -> LightBvh {
+        let mut bounded = Vec::new();
+        let mut infinite = Vec::new();
+        let mut count = 0;
+
+        for (index, bounds) in bounds.into_iter().enumerate() {
+            match bounds {
+                Some(bounds) if bounds.power > 0.0 => bounded.push((index, bounds)),
+                Some(_) => {}
+                None => infinite.push(index),
+            }
+            count = index + 1;
+        }
+
+        let mut nodes = Vec::with_capacity(2 * bounded.len());
+        if !bounded.is_empty() {
+            LightBvh::build(&mut nodes, &mut bounded);
+        }
+
+        let mut leaves = vec![None; count];
+        for (index, node) in nodes.iter().enumerate() {
+            if let Node::Leaf { light, .. } = *node {
+                leaves[light] = Some(index);
+            }
+        }
+
+        LightBvh {
+            nodes,
+            infinite,
+            leaves,
+        }
+    }
+
+    /// Appends the subtree over `lights` and returns its bounds.
+    fn build(nodes: &mut Vec<Node>, lights: &mut [(usize, LightBounds)]) -> LightBounds {
+        if let [(light, bounds)] = *lights {
+            nodes.push(Node::Leaf { bounds, light });
+            return bounds;
+        }
+
+        // Split at the median centroid along the widest axis
+        let centroid = |bounds: &LightBounds| (bounds.bounds.min + bounds.bounds.max) / 2.0;
+        let mut min = centroid(&lights[0].1);
+        let mut max = min;
+        for (_, bounds) in lights.iter() {
+            let c = centroid(bounds);
+            min = Vec3::new(min.x.min(c.x), min.y.min(c.y), min.z.min(c.z));
+            max = Vec3::new(max.x.max(c.x), max.y.max(c.y), max.z.max(c.z));
+        }
+        let extent = max - min;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+
+        lights.sort_by(|(_, a), (_, b)| {
+            component(centroid(a), axis)
+                .partial_cmp(&component(centroid(b), axis))
+                .expect("light positions should not be NaN")
+        });
+
+        let index = nodes.len();
+        nodes.push(Node::Leaf {
+            bounds: lights[0].1,
+            light: lights[0].0,
+        });
+
+        let (first, second) = lights.split_at_mut(lights.len() / 2);
+        let first_bounds = LightBvh::build(nodes, first);
+        let second_child = nodes.len();
+        let second_bounds = LightBvh::build(nodes, second);
+
+        let bounds = LightBounds::union(first_bounds, second_bounds);
+        nodes[index] = Node::Interior {
+            bounds,
+            second_child,
+        };
+        bounds
+    }
+
+    /// Lights without bounds, every one of them should be sampled.
+    pub fn infinite(&self) -> &[usize] {
+        &self.infinite
+    }
+
+    /// Power of all the bounded lights.
+    pub fn power(&self) -> f64 {
+        self.nodes.first().map_or(0.0, |node| node.bounds().power)
+    }
+
+    /// Picks one of the bounded lights proportionally to its power, regardless of position.
+    pub fn sample_power(&self, mut u: f64) -> Option<(usize, f64)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut index = 0;
+        let mut probability = 1.0;
+        loop {
+            match self.nodes[index] {
+                Node::Leaf { light, .. } => return Some((light, probability)),
+                Node::Interior { second_child, .. } => {
+                    let first = self.nodes[index + 1].bounds().power;
+                    let p_first = first / self.nodes[index].bounds().power;
+                    if u < p_first {
+                        u /= p_first;
+                        probability *= p_first;
+                        index += 1;
+                    } else {
+                        u = ((u - p_first) / (1.0 - p_first)).min(1.0 - f64::EPSILON);
+                        probability *= 1.0 - p_first;
+                        index = second_child;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Picks one of the bounded lights for `p` using the random number `u` in [0, 1), returning
+    /// its index and the probability it was picked with.
+    pub fn sample(&self, p: Vec3, mut u: f64) -> Option<(usize, f64)> {
+        if self.nodes.is_empty() || self.nodes[0].bounds().importance(p) <= 0.0 {
+            return None;
+        }
+
+        let mut index = 0;
+        let mut probability = 1.0;
+        loop {
+            match self.nodes[index] {
+                Node::Leaf { light, .. } => return Some((light, probability)),
+                Node::Interior { second_child, .. } => {
+                    let first = self.nodes[index + 1].bounds().importance(p);
+                    let second = self.nodes[second_child].bounds().importance(p);
+                    if first + second <= 0.0 {
+                        return None;
+                    }
+
+                    // Reuse the random number for the next level
+                    let p_first = first / (first + second);
+                    if u < p_first {
+                        u /= p_first;
+                        probability *= p_first;
+                        index += 1;
+                    } else {
+                        u = ((u - p_first) / (1.0 - p_first)).min(1.0 - f64::EPSILON);
+                        probability *= 1.0 - p_first;
+                        index = second_child;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Probability of `sample` picking the bounded light `light` for `p`.
+    pub fn pdf(&self, p: Vec3, light: usize) -> f64 {
+        let leaf = match self.leaves.get(light) {
+            Some(&Some(leaf)) => leaf,
+            _ => return 0.0,
+        };
+        if self.nodes[0].bounds().importance(p) <= 0.0 {
+            return 0.0;
+        }
+
+        // Descend the same way `sample` does, the subtree of the first child ends at the second
+        let mut index = 0;
+        let mut probability = 1.0;
+        while let Node::Interior { second_child, .. } = self.nodes[index] {
+            let first = self.nodes[index + 1].bounds().importance(p);
+            let second = self.nodes[second_child].bounds().importance(p);
+            if first + second <= 0.0 {
+                return 0.0;
+            }
+
+            let p_first = first / (first + second);
+            if leaf < second_child {
+                probability *= p_first;
+                index += 1;
+            } else {
+                probability *= 1.0 - p_first;
+                index = second_child;
+            }
+        }
+        probability
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lights::bvh::LightBvh;
+    use crate::lights::directional::DirectionalLight;
+    use crate::lights::point::PointLight;
+    use crate::lights::spot::SpotLight;
+    use crate::lights::Light;
+    use crate::vec3::Vec3;
+
+    fn point(x: f64) -> Box<dyn Light> {
+        Box::new(PointLight::new(
+            Vec3::new(x, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+        ))
+    }
+
+    #[test]
+    fn prefers_nearby_lights() {
+        let lights: Vec<Box<dyn Light>> = (0..64).map(|i| point(f64::from(i) * 10.0)).collect();
+        let bvh = LightBvh::new(&lights);
+
+        let (light, probability) = bvh
+            .sample(Vec3::new(0.0, 1.0, 0.0), 0.0)
+            .expect("lights reach the point");
+        assert_eq!(light, 0);
+        // Uniform light picking would give 1 / 64
+        assert!(probability > 0.5);
+    }
+
+    #[test]
+    fn probabilities_sum_to_one() {
+        let lights: Vec<Box<dyn Light>> = (0..5).map(|i| point(f64::from(i))).collect();
+        let bvh = LightBvh::new(&lights);
+        let p = Vec3::new(1.5, 2.0, 0.0);
+
+        // Every light covers an interval of u as wide as its probability
+        let mut total = 0.0;
+        let mut previous = None;
+        for i in 0..10_000 {
+            let (light, probability) = bvh.sample(p, f64::from(i) / 10_000.0).expect("lit");
+            if previous != Some(light) {
+                total += probability;
+                previous = Some(light);
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pdf_matches_sampled_probability() {
+        let lights: Vec<Box<dyn Light>> = (0..7).map(|i| point(f64::from(i) * 3.0)).collect();
+        let bvh = LightBvh::new(&lights);
+        let p = Vec3::new(4.0, 1.0, 0.0);
+
+        for i in 0..100 {
+            let (light, probability) = bvh.sample(p, f64::from(i) / 100.0).expect("lit");
+            assert!((bvh.pdf(p, light) - probability).abs() < 1e-12);
+        }
+        let total: f64 = (0..7).map(|light| bvh.pdf(p, light)).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+        assert_eq!(bvh.pdf(p, 7), 0.0);
+    }
+
+    #[test]
+    fn skips_lights_facing_away() {
+        let lights: Vec<Box<dyn Light>> = vec![
+            Box::new(SpotLight::new(
+                Vec3::origin(),
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::new(1.0, 1.0, 1.0),
+                20.0,
+                30.0,
+            )),
+            Box::new(DirectionalLight::new(
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::new(1.0, 1.0, 1.0),
+                0.5,
+            )),
+        ];
+        let bvh = LightBvh::new(&lights);
+
+        assert_eq!(bvh.infinite(), &[1]);
+        assert_eq!(bvh.sample(Vec3::new(0.0, -5.0, 0.0), 0.5), Some((0, 1.0)));
+        assert!(bvh.sample(Vec3::new(0.0, 5.0, 0.0), 0.5).is_none());
+    }
+}
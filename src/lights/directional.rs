@@ -0,0 +1,43 @@
+use crate::lights::{sample_cone, Light, LightBounds, LightSample};
+use crate::onb::Onb;
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Distant light like the sun, shining along `direction`.
+///
+/// A non zero angular diameter samples directions over the disk of the light, giving soft shadows.
+#[derive(Debug)]
+pub struct DirectionalLight {
+    frame: Onb,
+    /// Irradiance (or illuminance, in lux) on a surface facing the light.
+    irradiance: Vec3,
+    cos_half_angle: f64,
+}
+
+impl DirectionalLight {
+    /// `angular_diameter` is in degrees, the sun is about 0.53.
+    pub fn new(direction: Vec3, irradiance: Vec3, angular_diameter: f64) -> DirectionalLight {
+        DirectionalLight {
+            frame: Onb::from_w(direction * -1.0),
+            irradiance,
+            cos_half_angle: (angular_diameter / 2.0).to_radians().cos(),
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _p: Vec3) -> Option<LightSample> {
+        // Uniformly sample the cone subtended by the light, irradiance is radiance times solid angle.
+        let direction = self.frame.local(sample_cone(self.cos_half_angle));
+
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+}
@@ -0,0 +1,308 @@
+use crate::lights::{sample_cone, EmissionSample, Light, LightBounds, LightSample};
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use std::f64;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+/// Candela distribution of a real luminaire, read from an IES LM-63 photometric file.
+///
+/// Only type C photometry without tilt data is supported, which covers almost all files
+/// published by manufacturers. Vertical angles start at 0 pointing straight down.
+#[derive(Debug, Clone)]
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,
+    horizontal_angles: Vec<f64>,
+    /// Indexed by horizontal angle then vertical angle.
+    candela: Vec<Vec<f64>>,
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+impl IesProfile {
+    pub fn from_reader<R: Read>(reader: R) -> io::Result<IesProfile> {
+        let mut lines = BufReader::new(reader).lines();
+
+        // Keywords and free form header until the tilt line
+        loop {
+            let line = lines
+                .next()
+                .ok_or_else(|| invalid_data("missing TILT line"))??;
+            let line = line.trim();
+            if line.starts_with("TILT=") {
+                if line != "TILT=NONE" {
+                    return Err(invalid_data("only TILT=NONE is supported"));
+                }
+                break;
+            }
+        }
+
+        let mut numbers = Vec::new();
+        for line in lines {
+            for token in line?.split(|c: char| c.is_whitespace() || c == ',') {
+                if token.is_empty() {
+                    continue;
+                }
+                numbers.push(
+                    token
+                        .parse::<f64>()
+                        .map_err(|e| invalid_data(format!("bad number `{}`: {}", token, e)))?,
+                );
+            }
+        }
+
+        // Counts can't exceed the numbers in the file, which bounds what they allocate
+        let available = numbers.len() as f64;
+        let count = |value: f64, what: &str| {
+            if value.fract() != 0.0 || value < 1.0 || value > available {
+                return Err(invalid_data(format!("invalid {} {}", what, value)));
+            }
+            Ok(value as usize)
+        };
+
+        let mut numbers = numbers.into_iter();
+        let mut next = |what: &str| {
+            numbers
+                .next()
+                .ok_or_else(|| invalid_data(format!("file ends before {}", what)))
+        };
+
+        let _lamp_count = next("lamp count")?;
+        let _lumens_per_lamp = next("lumens per lamp")?;
+        let multiplier = next("candela multiplier")?;
+        let vertical_count = next("vertical angle count")?;
+        let horizontal_count = next("horizontal angle count")?;
+        let photometric_type = next("photometric type")?;
+        for what in &["units", "width", "length", "height"] {
+            next(what)?;
+        }
+        let ballast_factor = next("ballast factor")?;
+        for what in &["future use", "input watts"] {
+            next(what)?;
+        }
+
+        if photometric_type != 1.0 {
+            return Err(invalid_data("only type C photometry is supported"));
+        }
+        let vertical_count = count(vertical_count, "vertical angle count")?;
+        let horizontal_count = count(horizontal_count, "horizontal angle count")?;
+
+        let vertical_angles = (0..vertical_count)
+            .map(|_| next("vertical angles"))
+            .collect::<io::Result<Vec<_>>>()?;
+        let horizontal_angles = (0..horizontal_count)
+            .map(|_| next("horizontal angles"))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let scale = multiplier * ballast_factor;
+        let mut candela = Vec::new();
+        for _ in 0..horizontal_count {
+            let row = (0..vertical_count)
+                .map(|_| next("candela values").map(|c| c * scale))
+                .collect::<io::Result<Vec<_>>>()?;
+            candela.push(row);
+        }
+
+        Ok(IesProfile {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<IesProfile> {
+        IesProfile::from_reader(File::open(path)?)
+    }
+
+    /// Finds the segment containing `x`, clamping outside the range.
+    fn locate(angles: &[f64], x: f64) -> (usize, usize, f64) {
+        if angles.len() == 1 || x <= angles[0] {
+            return (0, 0, 0.0);
+        }
+
+        match angles.iter().position(|&a| a >= x) {
+            Some(i) => {
+                let t = (x - angles[i - 1]) / (angles[i] - angles[i - 1]);
+                (i - 1, i, t)
+            }
+            None => (angles.len() - 1, angles.len() - 1, 0.0),
+        }
+    }
+
+    /// Maps a full circle horizontal angle into the range covered by the file,
+    /// using the symmetry implied by the last horizontal angle.
+    fn fold_horizontal(&self, phi: f64) -> f64 {
+        let last = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        let phi = phi.rem_euclid(360.0);
+
+        if last <= 0.0 {
+            0.0
+        } else if last <= 90.0 {
+            let phi = phi % 180.0;
+            if phi > 90.0 {
+                180.0 - phi
+            } else {
+                phi
+            }
+        } else if last <= 180.0 {
+            if phi > 180.0 {
+                360.0 - phi
+            } else {
+                phi
+            }
+        } else {
+            phi
+        }
+    }
+
+    /// Brightest direction, a bound on the candela in any direction.
+    pub fn max_candela(&self) -> f64 {
+        self.candela
+            .iter()
+            .flatten()
+            .fold(0.0, |max, &candela| max.max(candela))
+    }
+
+    /// Candela at vertical angle `theta` and horizontal angle `phi`, both in degrees.
+    pub fn candela(&self, theta: f64, phi: f64) -> f64 {
+        let (v0, v1, tv) = IesProfile::locate(&self.vertical_angles, theta);
+        let (h0, h1, th) = IesProfile::locate(&self.horizontal_angles, self.fold_horizontal(phi));
+
+        let at = |h: usize| self.candela[h][v0] * (1.0 - tv) + self.candela[h][v1] * tv;
+        let value = at(h0) * (1.0 - th) + at(h1) * th;
+
+        // Beyond the last vertical angle the luminaire doesn't emit
+        if theta > self.vertical_angles[self.vertical_angles.len() - 1] {
+            0.0
+        } else {
+            value
+        }
+    }
+}
+
+/// Point light with the intensity distribution of an IES profile.
+#[derive(Debug)]
+pub struct IesLight {
+    position: Vec3,
+    /// `w` points down the luminaire (0 degrees vertical), `u` is 0 degrees horizontal.
+    frame: Onb,
+    profile: IesProfile,
+    /// Multiplies the candela values, eg. a color or an exposure adjustment.
+    color: Vec3,
+}
+
+impl IesLight {
+    pub fn new(position: Vec3, down: Vec3, profile: IesProfile, color: Vec3) -> IesLight {
+        IesLight {
+            position,
+            frame: Onb::from_w(down),
+            profile,
+            color,
+        }
+    }
+
+    /// Candela emitted along the unit vector `direction`.
+    fn candela(&self, direction: Vec3) -> f64 {
+        let emitted = self.frame.to_local(direction);
+        let theta = emitted.z.clamp(-1.0, 1.0).acos().to_degrees();
+        let phi = emitted.y.atan2(emitted.x).to_degrees();
+        self.profile.candela(theta, phi)
+    }
+}
+
+impl Light for IesLight {
+    fn sample(&self, p: Vec3) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.squared_length();
+        if distance_squared == 0.0 {
+            return None;
+        }
+
+        let distance = distance_squared.sqrt();
+        let direction = to_light / distance;
+
+        let candela = self.candela(direction * -1.0);
+        if candela <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.color * (candela / distance_squared),
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds::point(
+            self.position,
+            self.frame.w,
+            f64::consts::PI,
+            f64::consts::FRAC_PI_2,
+            self.color * self.profile.max_candela(),
+        ))
+    }
+
+    fn sample_emission(&self) -> Option<EmissionSample> {
+        let direction = sample_cone(-1.0);
+        Some(EmissionSample {
+            ray: Ray::new(self.position, direction),
+            intensity: self.color * self.candela(direction),
+            pdf: 1.0 / (4.0 * f64::consts::PI),
+        })
+    }
+
+    fn emission_pdf(&self, _direction: Vec3) -> f64 {
+        1.0 / (4.0 * f64::consts::PI)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lights::ies::IesProfile;
+
+    const DOWNLIGHT: &str = "IESNA:LM-63-2002
+[MANUFAC] Example
+TILT=NONE
+1 1000 2.0 3 2 1 2 0.1 0.1 0.0
+1.0 1.0 100
+0 45 90
+0 90
+100 50 0
+80 40 0
+";
+
+    #[test]
+    fn interpolates_candela() {
+        let profile = IesProfile::from_reader(DOWNLIGHT.as_bytes()).expect("valid profile");
+
+        assert_eq!(profile.candela(0.0, 0.0), 200.0);
+        assert_eq!(profile.candela(22.5, 0.0), 150.0);
+        assert_eq!(profile.candela(0.0, 45.0), 180.0);
+        // Quadrant symmetry
+        assert_eq!(profile.candela(45.0, 270.0), 80.0);
+        assert_eq!(profile.candela(120.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn rejects_tilt_data() {
+        let tilted = DOWNLIGHT.replace("TILT=NONE", "TILT=INCLUDE");
+        assert!(IesProfile::from_reader(tilted.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_angle_counts() {
+        for counts in &["0 2", "3 -2", "2.5 2", "3 1e18", "3 NaN"] {
+            let profile = DOWNLIGHT.replace("2.0 3 2 1", &format!("2.0 {} 1", counts));
+            assert!(
+                IesProfile::from_reader(profile.as_bytes()).is_err(),
+                "{}",
+                counts
+            );
+        }
+    }
+}
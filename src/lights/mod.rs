@@ -0,0 +1,162 @@
+pub mod bvh;
+pub mod directional;
+pub mod ies;
+pub mod point;
+pub mod spot;
+
+use crate::aabb::Aabb;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Light arriving at a point from a sampled position on a light.
+#[derive(Debug, Copy, Clone)]
+pub struct LightSample {
+    /// Unit vector from the shaded point towards the light.
+    pub direction: Vec3,
+    /// Distance to the light, shadow rays only need to check for occluders up to it.
+    pub distance: f64,
+    /// Incident radiance divided by the probability of the sample.
+    pub radiance: Vec3,
+}
+
+/// Ray leaving a light, to start a path from it.
+#[derive(Debug, Copy, Clone)]
+pub struct EmissionSample {
+    pub ray: Ray,
+    /// Intensity along the ray.
+    pub intensity: Vec3,
+    /// Solid angle density of the ray direction.
+    pub pdf: f64,
+}
+
+/// Conservative bounds on where a light is, where it emits and how much, so its contribution to a
+/// point can be estimated without sampling it.
+#[derive(Debug, Copy, Clone)]
+pub struct LightBounds {
+    pub bounds: Aabb,
+    /// Central emission direction.
+    pub axis: Vec3,
+    /// Spread of the emission directions around `axis`, in radians.
+    pub theta_o: f64,
+    /// Angle beyond `theta_o` that light still falls off over, at most a right angle.
+    pub theta_e: f64,
+    /// Emitted power, weighted by luminance.
+    pub power: f64,
+}
+
+impl LightBounds {
+    /// Bounds of a point emitting `intensity` into a cone of directions.
+    pub fn point(
+        position: Vec3,
+        axis: Vec3,
+        theta_o: f64,
+        theta_e: f64,
+        intensity: Vec3,
+    ) -> LightBounds {
+        // Power is intensity integrated over the solid angle of the cone
+        let solid_angle =
+            2.0 * f64::consts::PI * (1.0 - (theta_o + theta_e / 2.0).min(f64::consts::PI).cos());
+        LightBounds {
+            bounds: Aabb::new(position, position),
+            axis,
+            theta_o,
+            theta_e,
+            power: solid_angle * intensity.luminance(),
+        }
+    }
+
+    pub fn union(a: LightBounds, b: LightBounds) -> LightBounds {
+        if a.power == 0.0 {
+            return b;
+        }
+        if b.power == 0.0 {
+            return a;
+        }
+
+        let (axis, theta_o) = union_cones(a.axis, a.theta_o, b.axis, b.theta_o);
+        LightBounds {
+            bounds: Aabb::surrounding_box(a.bounds, b.bounds),
+            axis,
+            theta_o,
+            theta_e: a.theta_e.max(b.theta_e),
+            power: a.power + b.power,
+        }
+    }
+
+    /// Upper estimate of the light reaching `p`, only meaningful relative to other bounds.
+    pub fn importance(&self, p: Vec3) -> f64 {
+        let center = (self.bounds.min + self.bounds.max) / 2.0;
+        let radius = (self.bounds.max - self.bounds.min).length() / 2.0;
+        let to_point = p - center;
+        let distance = to_point.length();
+
+        // Don't let the estimate blow up close to the lights
+        let distance_squared = (distance * distance).max(radius * radius).max(1e-8);
+        if distance <= radius {
+            return self.power / distance_squared;
+        }
+
+        // Smallest angle between the emission cone and the directions from the bounds towards p
+        let theta_w = self.axis.dot(to_point / distance).clamp(-1.0, 1.0).acos();
+        let theta_b = (radius / distance).asin();
+        let theta = (theta_w - self.theta_o - theta_b).max(0.0);
+        if theta >= self.theta_e {
+            return 0.0;
+        }
+
+        self.power * theta.cos() / distance_squared
+    }
+}
+
+/// Smallest cone, as an axis and half angle, containing both cones.
+fn union_cones(axis_a: Vec3, theta_a: f64, axis_b: Vec3, theta_b: f64) -> (Vec3, f64) {
+    let theta_d = axis_a.dot(axis_b).clamp(-1.0, 1.0).acos();
+    if (theta_d + theta_b).min(f64::consts::PI) <= theta_a {
+        return (axis_a, theta_a);
+    }
+    if (theta_d + theta_a).min(f64::consts::PI) <= theta_b {
+        return (axis_b, theta_b);
+    }
+
+    let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+    let rotation_axis = axis_a.cross(axis_b);
+    if theta_o >= f64::consts::PI || rotation_axis.squared_length() == 0.0 {
+        return (axis_a, f64::consts::PI);
+    }
+
+    // Rotate axis_a towards axis_b, the rotation axis is perpendicular to it
+    let theta_r = theta_o - theta_a;
+    let k = rotation_axis.make_unit_vec();
+    (
+        axis_a * theta_r.cos() + k.cross(axis_a) * theta_r.sin(),
+        theta_o,
+    )
+}
+
+/// Uniformly samples a direction within `acos(cos_max)` of +z, -1 covers the whole sphere.
+fn sample_cone(cos_max: f64) -> Vec3 {
+    let cos_theta = 1.0 - sampler::random() * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * f64::consts::PI * sampler::random();
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// Lights that can't be hit by rays (points, directions) and are sampled directly by the integrator.
+pub trait Light: Send + Sync {
+    fn sample(&self, p: Vec3) -> Option<LightSample>;
+
+    /// `None` for lights infinitely far away, which reach every point in the scene.
+    fn bounds(&self) -> Option<LightBounds>;
+
+    /// Samples a ray leaving the light, `None` for lights that can't start paths.
+    fn sample_emission(&self) -> Option<EmissionSample> {
+        None
+    }
+
+    /// Solid angle density of `sample_emission` leaving along the unit vector `direction`.
+    fn emission_pdf(&self, _direction: Vec3) -> f64 {
+        0.0
+    }
+}
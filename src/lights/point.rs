@@ -0,0 +1,60 @@
+use crate::lights::{sample_cone, EmissionSample, Light, LightBounds, LightSample};
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Emits equally in all directions from a single point.
+#[derive(Debug)]
+pub struct PointLight {
+    position: Vec3,
+    /// Radiant (or luminous, in candela) intensity.
+    intensity: Vec3,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Vec3) -> PointLight {
+        PointLight {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: Vec3) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.squared_length();
+        if distance_squared == 0.0 {
+            return None;
+        }
+
+        let distance = distance_squared.sqrt();
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: self.intensity / distance_squared,
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds::point(
+            self.position,
+            Vec3::new(0.0, 0.0, 1.0),
+            f64::consts::PI,
+            f64::consts::FRAC_PI_2,
+            self.intensity,
+        ))
+    }
+
+    fn sample_emission(&self) -> Option<EmissionSample> {
+        Some(EmissionSample {
+            ray: Ray::new(self.position, sample_cone(-1.0)),
+            intensity: self.intensity,
+            pdf: 1.0 / (4.0 * f64::consts::PI),
+        })
+    }
+
+    fn emission_pdf(&self, _direction: Vec3) -> f64 {
+        1.0 / (4.0 * f64::consts::PI)
+    }
+}
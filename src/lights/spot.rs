@@ -0,0 +1,99 @@
+use crate::lights::{sample_cone, EmissionSample, Light, LightBounds, LightSample};
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Point light restricted to a cone, fading out smoothly between the inner and outer angle.
+#[derive(Debug)]
+pub struct SpotLight {
+    position: Vec3,
+    direction: Vec3,
+    intensity: Vec3,
+    cos_inner: f64,
+    cos_outer: f64,
+}
+
+impl SpotLight {
+    /// Cone angles are in degrees, measured from `direction` to the edge of the cone.
+    /// Cones wider than a hemisphere are better expressed as masked point lights.
+    pub fn new(
+        position: Vec3,
+        direction: Vec3,
+        intensity: Vec3,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> SpotLight {
+        let outer_angle = outer_angle.clamp(inner_angle.min(90.0), 90.0);
+        let inner_angle = inner_angle.min(outer_angle);
+        SpotLight {
+            position,
+            direction: direction.make_unit_vec(),
+            intensity,
+            cos_inner: inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+        }
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.0;
+        }
+
+        let t = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: Vec3) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_squared = to_light.squared_length();
+        if distance_squared == 0.0 {
+            return None;
+        }
+
+        let distance = distance_squared.sqrt();
+        let direction = to_light / distance;
+        let falloff = self.falloff((direction * -1.0).dot(self.direction));
+        if falloff == 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.intensity * (falloff / distance_squared),
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let theta_o = self.cos_inner.acos();
+        Some(LightBounds::point(
+            self.position,
+            self.direction,
+            theta_o,
+            self.cos_outer.acos() - theta_o,
+            self.intensity,
+        ))
+    }
+
+    fn sample_emission(&self) -> Option<EmissionSample> {
+        let local = sample_cone(self.cos_outer);
+        Some(EmissionSample {
+            ray: Ray::new(self.position, Onb::from_w(self.direction).local(local)),
+            intensity: self.intensity * self.falloff(local.z),
+            pdf: self.emission_pdf(self.direction),
+        })
+    }
+
+    fn emission_pdf(&self, direction: Vec3) -> f64 {
+        if direction.dot(self.direction) < self.cos_outer {
+            return 0.0;
+        }
+        1.0 / (2.0 * f64::consts::PI * (1.0 - self.cos_outer))
+    }
+}
@@ -0,0 +1,328 @@
+use crate::integrators::path::{Emitter, Event};
+use crate::materials::Scattering;
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+
+/// Label of a single vertex of a path.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Atom {
+    Camera,
+    /// Scattering event, `None` matching any kind or direction.
+    Event {
+        transmitted: Option<bool>,
+        scattering: Option<Scattering>,
+    },
+    /// Lights and emissive surfaces.
+    Light,
+    Background,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Atom(Atom),
+    Sequence(Vec<Node>),
+    Alternation(Vec<Node>),
+    /// Zero or more repetitions.
+    Star(Box<Node>),
+    Optional(Box<Node>),
+}
+
+/// Regular expression over the vertices of light paths (light path expressions, Heckbert 1990),
+/// selecting which of the light reaching the camera goes into a custom pass.
+///
+/// Paths are read from the camera `C` through their scattering events to the emitter, either `L`
+/// for lights and emissive surfaces or `B` for the sky. Events are written `<RD>`, a direction
+/// out of `R`eflection, `T`ransmission or `.` for either, then a kind out of `D`iffuse, `G`lossy,
+/// `S`pecular or `.` for any. `D`, `G`, `S`, `R` and `T` alone match every event of that kind or
+/// direction and `.` any event. Atoms combine as in regular expressions with `[...]`, `(...)`,
+/// `|`, `*`, `+` and `?`: `C<RS>+L` is light seen in mirrors, `CD.*[LB]` everything after a
+/// diffuse bounce.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightPathExpression {
+    root: Node,
+}
+
+fn atom(symbol: char) -> Option<Atom> {
+    let event = |transmitted, scattering| Atom::Event {
+        transmitted,
+        scattering,
+    };
+    match symbol {
+        'C' => Some(Atom::Camera),
+        'L' => Some(Atom::Light),
+        'B' => Some(Atom::Background),
+        '.' => Some(event(None, None)),
+        'R' => Some(event(Some(false), None)),
+        'T' => Some(event(Some(true), None)),
+        'D' => Some(event(None, Some(Scattering::Diffuse))),
+        'G' => Some(event(None, Some(Scattering::Glossy))),
+        'S' => Some(event(None, Some(Scattering::Specular))),
+        _ => None,
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected `{}`, found `{}`", expected, c)),
+            None => Err(format!("expected `{}` at the end", expected)),
+        }
+    }
+
+    fn alternation(&mut self) -> Result<Node, String> {
+        let mut options = vec![self.sequence()?];
+        while self.chars.peek() == Some(&'|') {
+            self.chars.next();
+            options.push(self.sequence()?);
+        }
+        Ok(if options.len() == 1 {
+            options.remove(0)
+        } else {
+            Node::Alternation(options)
+        })
+    }
+
+    fn sequence(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(&c) = self.chars.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            nodes.push(self.repetition()?);
+        }
+        Ok(Node::Sequence(nodes))
+    }
+
+    fn repetition(&mut self) -> Result<Node, String> {
+        let mut node = self.atom()?;
+        while let Some(&c) = self.chars.peek() {
+            node = match c {
+                '*' => Node::Star(Box::new(node)),
+                '+' => Node::Sequence(vec![node.clone(), Node::Star(Box::new(node))]),
+                '?' => Node::Optional(Box::new(node)),
+                _ => break,
+            };
+            self.chars.next();
+        }
+        Ok(node)
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.chars.next() {
+            Some('(') => {
+                let node = self.alternation()?;
+                self.expect(')')?;
+                Ok(node)
+            }
+            Some('[') => {
+                let mut options = Vec::new();
+                while self.chars.peek() != Some(&']') {
+                    options.push(self.atom()?);
+                }
+                self.chars.next();
+                Ok(Node::Alternation(options))
+            }
+            Some('<') => {
+                let direction = self.chars.next();
+                let kind = self.chars.next();
+                self.expect('>')?;
+                let transmitted = match direction {
+                    Some('R') => Some(false),
+                    Some('T') => Some(true),
+                    Some('.') => None,
+                    _ => return Err("events start with `R`, `T` or `.`".to_string()),
+                };
+                let scattering = match kind {
+                    Some('D') => Some(Scattering::Diffuse),
+                    Some('G') => Some(Scattering::Glossy),
+                    Some('S') => Some(Scattering::Specular),
+                    Some('.') => None,
+                    _ => return Err("events end with `D`, `G`, `S` or `.`".to_string()),
+                };
+                Ok(Node::Atom(Atom::Event {
+                    transmitted,
+                    scattering,
+                }))
+            }
+            Some(c) => atom(c)
+                .map(Node::Atom)
+                .ok_or_else(|| format!("unexpected `{}`", c)),
+            None => Err("unexpected end".to_string()),
+        }
+    }
+}
+
+impl FromStr for LightPathExpression {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<LightPathExpression, String> {
+        let without_spaces: String = expression.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut parser = Parser {
+            chars: without_spaces.chars().peekable(),
+        };
+        let root = parser.alternation()?;
+        match parser.chars.next() {
+            Some(c) => Err(format!("unexpected `{}`", c)),
+            None => Ok(LightPathExpression { root }),
+        }
+    }
+}
+
+impl Atom {
+    fn matches(self, path: &Path<'_>, index: usize) -> bool {
+        match (self, path.label(index)) {
+            (Atom::Camera, Label::Camera) => true,
+            (Atom::Light, Label::Emitter(Emitter::Light))
+            | (Atom::Light, Label::Emitter(Emitter::Surface))
+            | (Atom::Background, Label::Emitter(Emitter::Background)) => true,
+            (
+                Atom::Event {
+                    transmitted,
+                    scattering,
+                },
+                Label::Event(event),
+            ) => {
+                transmitted.is_none_or(|t| t == event.transmitted)
+                    && scattering.is_none_or(|s| s == event.scattering)
+            }
+            _ => false,
+        }
+    }
+}
+
+enum Label {
+    Camera,
+    Event(Event),
+    Emitter(Emitter),
+}
+
+/// The camera, `events` and `emitter`, without collecting them.
+struct Path<'a> {
+    events: &'a [Event],
+    emitter: Emitter,
+}
+
+impl<'a> Path<'a> {
+    fn len(&self) -> usize {
+        self.events.len() + 2
+    }
+
+    fn label(&self, index: usize) -> Label {
+        match index {
+            0 => Label::Camera,
+            i if i <= self.events.len() => Label::Event(self.events[i - 1]),
+            _ => Label::Emitter(self.emitter),
+        }
+    }
+}
+
+impl Node {
+    /// Positions in `path` where a match of the node can end, given those it can start from.
+    fn ends(&self, path: &Path<'_>, starts: &[bool]) -> Vec<bool> {
+        match self {
+            Node::Atom(atom) => {
+                let mut ends = vec![false; starts.len()];
+                for i in 0..path.len() {
+                    ends[i + 1] = starts[i] && atom.matches(path, i);
+                }
+                ends
+            }
+            Node::Sequence(nodes) => nodes
+                .iter()
+                .fold(starts.to_vec(), |starts, node| node.ends(path, &starts)),
+            Node::Alternation(options) => {
+                let mut ends = vec![false; starts.len()];
+                for option in options {
+                    for (end, matched) in ends.iter_mut().zip(option.ends(path, starts)) {
+                        *end |= matched;
+                    }
+                }
+                ends
+            }
+            Node::Star(node) => {
+                let mut reached = starts.to_vec();
+                loop {
+                    let mut next = node.ends(path, &reached);
+                    for (next, reached) in next.iter_mut().zip(&reached) {
+                        *next |= reached;
+                    }
+                    if next == reached {
+                        return reached;
+                    }
+                    reached = next;
+                }
+            }
+            Node::Optional(node) => {
+                let mut ends = node.ends(path, starts);
+                for (end, start) in ends.iter_mut().zip(starts) {
+                    *end |= start;
+                }
+                ends
+            }
+        }
+    }
+}
+
+impl LightPathExpression {
+    /// Whether the whole path from the camera through `events` to `emitter` matches.
+    pub fn matches(&self, events: &[Event], emitter: Emitter) -> bool {
+        let path = Path { events, emitter };
+        let mut starts = vec![false; path.len() + 1];
+        starts[0] = true;
+        self.root.ends(&path, &starts)[path.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::integrators::path::{Emitter, Event};
+    use crate::lpe::LightPathExpression;
+    use crate::materials::Scattering;
+
+    fn event(scattering: Scattering, transmitted: bool) -> Event {
+        Event {
+            scattering,
+            transmitted,
+        }
+    }
+
+    #[test]
+    fn matches_whole_paths() {
+        let mirror = event(Scattering::Specular, false);
+        let glass = event(Scattering::Specular, true);
+        let wall = event(Scattering::Diffuse, false);
+        let caustics: LightPathExpression = "C<RS>+L".parse().unwrap();
+
+        assert!(caustics.matches(&[mirror], Emitter::Light));
+        assert!(caustics.matches(&[mirror, mirror], Emitter::Surface));
+        assert!(!caustics.matches(&[], Emitter::Light));
+        assert!(!caustics.matches(&[mirror, glass], Emitter::Light));
+        assert!(!caustics.matches(&[mirror], Emitter::Background));
+
+        let indirect: LightPathExpression = "C D . .* [LB]".parse().unwrap();
+        assert!(indirect.matches(&[wall, glass, wall], Emitter::Background));
+        assert!(!indirect.matches(&[wall], Emitter::Light));
+        assert!(!indirect.matches(&[glass, wall], Emitter::Light));
+
+        let either: LightPathExpression = "C(T|<RD>)?L".parse().unwrap();
+        assert!(either.matches(&[], Emitter::Light));
+        assert!(either.matches(&[glass], Emitter::Light));
+        assert!(!either.matches(&[mirror], Emitter::Light));
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for expression in &["C<RX>L", "C(DL", "CQL", "C<R"] {
+            assert!(
+                expression.parse::<LightPathExpression>().is_err(),
+                "{}",
+                expression
+            );
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::hitable::HitRecord;
+use crate::materials::microfacet::{fresnel_dielectric, reflect, reflection, reflection_pdf, Ggx};
+use crate::materials::{Material, Scattering};
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::Vec3;
+
+/// Thin dielectric coating (lacquer, varnish) layered over another material.
+///
+/// Light either reflects off the coat, with probability given by its Fresnel term, or goes
+/// through to the base material. Light leaving the base loses the part that the coat reflects
+/// back inside, and is tinted by the coat color over the distance it travelled through the layer.
+///
+/// The coat is treated as infinitely thin: the base is scattered and evaluated with the
+/// directions outside the coat rather than the refracted ones, and light reflected back down by
+/// the underside of the coat is lost instead of bouncing between the layers. Only the tint
+/// accounts for the steeper path through the layer.
+pub struct Clearcoat {
+    base: Box<dyn Material + Send + Sync>,
+    refraction_idx: f64,
+    distribution: Ggx,
+    tint: Vec3,
+    thickness: f64,
+}
+
+impl Clearcoat {
+    pub fn new(
+        base: Box<dyn Material + Send + Sync>,
+        refraction_idx: f64,
+        roughness: f64,
+    ) -> Clearcoat {
+        Clearcoat {
+            base,
+            refraction_idx,
+            distribution: Ggx::from_roughness(roughness, roughness),
+            tint: Vec3::new(1.0, 1.0, 1.0),
+            thickness: 0.0,
+        }
+    }
+
+    /// Colors the coat, `tint` is the transmittance through a layer of unit `thickness` at normal incidence.
+    pub fn with_tint(mut self, tint: Vec3, thickness: f64) -> Clearcoat {
+        self.tint = tint;
+        self.thickness = thickness;
+        self
+    }
+
+    /// Transmittance for a single pass through the layer, for light crossing the surface at `cos`.
+    fn transmittance(&self, cos: f64) -> Vec3 {
+        if self.thickness <= 0.0 {
+            return Vec3::new(1.0, 1.0, 1.0);
+        }
+
+        // Refracted directions are steeper inside the layer than outside
+        let sin2_inside = (1.0 - cos * cos) / (self.refraction_idx * self.refraction_idx);
+        let distance = self.thickness / (1.0 - sin2_inside).max(1e-4).sqrt();
+
+        Vec3::new(
+            self.tint.x.powf(distance),
+            self.tint.y.powf(distance),
+            self.tint.z.powf(distance),
+        )
+    }
+}
+
+impl Material for Clearcoat {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let normal = hit_record.facing_normal(r_in);
+        let frame = Onb::from_w(normal);
+
+        let wo = frame.to_local(r_in.direction.make_unit_vec() * -1.0);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let m = self
+            .distribution
+            .sample_visible_normal(wo, sampler::random(), sampler::random());
+        let fresnel_in = fresnel_dielectric(wo.dot(m), self.refraction_idx);
+
+        // Reflect off the coat, the Fresnel term cancels out with the selection probability.
+        if sampler::random() < fresnel_in {
+            let wi = reflect(wo, m);
+            if wi.z <= 0.0 {
+                return None;
+            }
+            let weight = self.distribution.g2(wo, wi) / self.distribution.g1(wo);
+            return Some((
+                Vec3::new(weight, weight, weight),
+                Ray::new(hit_record.position, frame.local(wi)),
+            ));
+        }
+
+        let (attenuation, scattered) = self.base.scatter(r_in, hit_record)?;
+        let cos_out = scattered.direction.make_unit_vec().dot(normal);
+        if cos_out <= 0.0 {
+            // Went through the base (eg. glass), the coat only matters on the way in
+            return Some((attenuation * self.transmittance(wo.z), scattered));
+        }
+
+        let fresnel_out = fresnel_dielectric(cos_out, self.refraction_idx);
+        Some((
+            attenuation
+                * self.transmittance(wo.z)
+                * self.transmittance(cos_out)
+                * (1.0 - fresnel_out),
+            scattered,
+        ))
+    }
+
+    fn bsdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Option<Vec3> {
+        let normal = hit_record.facing_normal(r_in);
+        let frame = Onb::from_w(normal);
+        let wo = frame.to_local(r_in.direction.make_unit_vec() * -1.0);
+        let wi = frame.to_local(direction);
+
+        let coat = match reflection(&self.distribution, wo, wi) {
+            Some((half, value)) => fresnel_dielectric(wo.dot(half), self.refraction_idx) * value,
+            None => 0.0,
+        };
+
+        let base = match self.base.bsdf(r_in, hit_record, direction) {
+            Some(base) if wo.z > 0.0 && wi.z > 0.0 => {
+                let fresnel_in = fresnel_dielectric(wo.z, self.refraction_idx);
+                let fresnel_out = fresnel_dielectric(wi.z, self.refraction_idx);
+                base * self.transmittance(wo.z)
+                    * self.transmittance(wi.z)
+                    * ((1.0 - fresnel_in) * (1.0 - fresnel_out))
+            }
+            _ => Vec3::origin(),
+        };
+
+        Some(base + Vec3::new(coat, coat, coat))
+    }
+
+    fn pdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        let normal = hit_record.facing_normal(r_in);
+        let frame = Onb::from_w(normal);
+        let wo = frame.to_local(r_in.direction.make_unit_vec() * -1.0);
+        let wi = frame.to_local(direction);
+
+        // The coat is picked with the Fresnel of the sampled normal, approximated by the half vector
+        let fresnel = fresnel_dielectric(wo.dot((wo + wi).make_unit_vec()), self.refraction_idx);
+        fresnel * reflection_pdf(&self.distribution, wo, wi)
+            + (1.0 - fresnel) * self.base.pdf(r_in, hit_record, direction)
+    }
+
+    fn scattering(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Scattering {
+        let normal = hit_record.facing_normal(r_in);
+        let frame = Onb::from_w(normal);
+        let wo = frame.to_local(r_in.direction.make_unit_vec() * -1.0);
+        let wi = frame.to_local(direction);
+
+        let fresnel = fresnel_dielectric(wo.dot((wo + wi).make_unit_vec()), self.refraction_idx);
+        let coat = fresnel * reflection_pdf(&self.distribution, wo, wi);
+        let base = (1.0 - fresnel) * self.base.pdf(r_in, hit_record, direction);
+        if coat > base {
+            Scattering::Glossy
+        } else if base > 0.0 {
+            self.base.scattering(r_in, hit_record, direction)
+        } else {
+            Scattering::Specular
+        }
+    }
+
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+
+    /// The base seen through the coat and back at normal incidence.
+    fn albedo(&self, r_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        let transmittance = self.transmittance(1.0);
+        self.base.albedo(r_in, hit_record) * transmittance * transmittance
+    }
+
+    fn emitted(&self, r_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        let cos_out = r_in.direction.make_unit_vec().dot(hit_record.normal).abs();
+        let fresnel_out = fresnel_dielectric(cos_out, self.refraction_idx);
+
+        self.base.emitted(r_in, hit_record) * self.transmittance(cos_out) * (1.0 - fresnel_out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::materials::clearcoat::Clearcoat;
+    use crate::materials::lambertian::Lambertian;
+    use crate::materials::microfacet::fresnel_dielectric;
+    use crate::materials::testing::{hit, incoming};
+    use crate::materials::Material;
+    use crate::ray::Ray;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn reflects_off_the_coat_with_the_fresnel_probability() {
+        let coat = Clearcoat::new(Box::new(Lambertian::new(Vec3::origin())), 1.5, 0.0);
+        let hit = hit(&coat);
+
+        for &cos_theta in [1.0, 0.2].iter() {
+            let r_in = incoming(cos_theta);
+            let mirrored = Vec3::new(r_in.direction.x, 0.0, cos_theta);
+            let samples = 20_000;
+            let reflected = (0..samples)
+                .filter_map(|_| coat.scatter(&r_in, &hit))
+                .filter(|(_, scattered)| {
+                    (scattered.direction.make_unit_vec() - mirrored).length() < 1e-2
+                })
+                .count();
+
+            let expected = fresnel_dielectric(cos_theta, 1.5);
+            let fraction = reflected as f64 / samples as f64;
+            assert!(
+                (fraction - expected).abs() < 0.015,
+                "{} at {}",
+                fraction,
+                cos_theta
+            );
+        }
+    }
+
+    #[test]
+    fn conserves_energy() {
+        let white = || Box::new(Lambertian::new(Vec3::new(1.0, 1.0, 1.0)));
+        let clear = Clearcoat::new(white(), 1.5, 0.2);
+        let tinted = Clearcoat::new(white(), 1.5, 0.2).with_tint(Vec3::new(0.5, 0.5, 0.5), 0.5);
+
+        let albedo = |material: &dyn Material, r_in: &Ray| {
+            let hit = hit(material);
+            let samples = 20_000;
+            let sum = (0..samples)
+                .filter_map(|_| material.scatter(r_in, &hit))
+                .fold(Vec3::origin(), |sum, (attenuation, _)| sum + attenuation);
+            sum / samples as f64
+        };
+
+        for &cos_theta in [1.0, 0.5, 0.1].iter() {
+            let r_in = incoming(cos_theta);
+            let clear = albedo(&clear, &r_in);
+            let tinted = albedo(&tinted, &r_in);
+            assert!(
+                clear.x < 1.0 && clear.x > 0.8,
+                "{:?} at {}",
+                clear,
+                cos_theta
+            );
+            assert!(tinted.x < clear.x, "{:?} at {}", tinted, cos_theta);
+        }
+    }
+}
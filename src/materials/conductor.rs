@@ -0,0 +1,213 @@
+use crate::hitable::HitRecord;
+use crate::materials::microfacet::{
+    fresnel_conductor_rgb, reflect, reflection, reflection_pdf, Ggx,
+};
+use crate::materials::{Material, Scattering};
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::Vec3;
+
+/// Rough metal with a GGX microfacet distribution and Fresnel from a complex index of refraction.
+///
+/// `eta` and `k` are given per RGB channel. Anisotropic roughness stretches the highlight along
+/// the tangent of the shading frame built around the normal.
+#[derive(Debug)]
+pub struct Conductor {
+    distribution: Ggx,
+    eta: Vec3,
+    k: Vec3,
+}
+
+impl Conductor {
+    pub fn new(roughness_x: f64, roughness_y: f64, eta: Vec3, k: Vec3) -> Conductor {
+        Conductor {
+            distribution: Ggx::from_roughness(roughness_x, roughness_y),
+            eta,
+            k,
+        }
+    }
+
+    pub fn isotropic(roughness: f64, eta: Vec3, k: Vec3) -> Conductor {
+        Conductor::new(roughness, roughness, eta, k)
+    }
+
+    pub fn gold(roughness: f64) -> Conductor {
+        Conductor::isotropic(
+            roughness,
+            Vec3::new(0.143, 0.374, 1.442),
+            Vec3::new(3.983, 2.385, 1.603),
+        )
+    }
+
+    pub fn copper(roughness: f64) -> Conductor {
+        Conductor::isotropic(
+            roughness,
+            Vec3::new(0.200, 0.924, 1.102),
+            Vec3::new(3.912, 2.452, 2.142),
+        )
+    }
+
+    pub fn aluminium(roughness: f64) -> Conductor {
+        Conductor::isotropic(
+            roughness,
+            Vec3::new(1.657, 0.880, 0.521),
+            Vec3::new(9.224, 6.270, 4.837),
+        )
+    }
+
+    pub fn silver(roughness: f64) -> Conductor {
+        Conductor::isotropic(
+            roughness,
+            Vec3::new(0.155, 0.117, 0.138),
+            Vec3::new(4.828, 3.122, 2.147),
+        )
+    }
+}
+
+impl Conductor {
+    /// Shading frame on the side of the surface the ray arrives from, opaque surfaces are two sided.
+    fn frame(r_in: &Ray, hit_record: &HitRecord) -> Onb {
+        Onb::from_w(hit_record.facing_normal(r_in))
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let frame = Conductor::frame(r_in, hit_record);
+
+        let wo = frame.to_local(r_in.direction.make_unit_vec() * -1.0);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let m = self
+            .distribution
+            .sample_visible_normal(wo, sampler::random(), sampler::random());
+        let wi = reflect(wo, m);
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        // With visible normal sampling everything but Fresnel and shadowing cancels out.
+        let fresnel = fresnel_conductor_rgb(wo.dot(m), self.eta, self.k);
+        let weight = fresnel * (self.distribution.g2(wo, wi) / self.distribution.g1(wo));
+
+        Some((weight, Ray::new(hit_record.position, frame.local(wi))))
+    }
+
+    fn bsdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Option<Vec3> {
+        let frame = Conductor::frame(r_in, hit_record);
+        let wo = frame.to_local(r_in.direction.make_unit_vec() * -1.0);
+        let wi = frame.to_local(direction);
+
+        Some(match reflection(&self.distribution, wo, wi) {
+            Some((half, value)) => fresnel_conductor_rgb(wo.dot(half), self.eta, self.k) * value,
+            None => Vec3::origin(),
+        })
+    }
+
+    fn pdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        let frame = Conductor::frame(r_in, hit_record);
+        let wo = frame.to_local(r_in.direction.make_unit_vec() * -1.0);
+        reflection_pdf(&self.distribution, wo, frame.to_local(direction))
+    }
+
+    fn scattering(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Scattering {
+        if self.pdf(r_in, hit_record, direction) > 0.0 {
+            Scattering::Glossy
+        } else {
+            Scattering::Specular
+        }
+    }
+
+    /// Reflectance at normal incidence, the colour of the metal.
+    fn albedo(&self, _r_in: &Ray, _hit_record: &HitRecord) -> Vec3 {
+        fresnel_conductor_rgb(1.0, self.eta, self.k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::materials::conductor::Conductor;
+    use crate::materials::testing::{hit, incoming};
+    use crate::materials::Material;
+    use crate::onb::Onb;
+    use crate::ray::Ray;
+    use crate::vec3::Vec3;
+
+    fn aluminium(roughness_x: f64, roughness_y: f64) -> Conductor {
+        Conductor::new(
+            roughness_x,
+            roughness_y,
+            Vec3::new(1.657, 0.880, 0.521),
+            Vec3::new(9.224, 6.270, 4.837),
+        )
+    }
+
+    #[test]
+    fn scatter_weight_is_bsdf_over_pdf() {
+        for &(roughness_x, roughness_y) in &[(0.3, 0.3), (0.1, 0.6)] {
+            let conductor = aluminium(roughness_x, roughness_y);
+            let r_in = incoming(0.6);
+            let hit = hit(&conductor);
+            for _ in 0..1000 {
+                let (weight, scattered) = match conductor.scatter(&r_in, &hit) {
+                    Some(scattered) => scattered,
+                    None => continue,
+                };
+                let direction = scattered.direction.make_unit_vec();
+                let bsdf = conductor.bsdf(&r_in, &hit, direction).expect("glossy");
+                let pdf = conductor.pdf(&r_in, &hit, direction);
+                assert!(pdf > 0.0);
+                assert!(
+                    (weight - bsdf / pdf).length() < 1e-6 * weight.length().max(1.0),
+                    "{:?} {:?}",
+                    weight,
+                    bsdf / pdf
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn anisotropic_roughness_stretches_the_lobe() {
+        // Spread of the reflections off a surface seen head on, along the tangents of the
+        // shading frame
+        let spread = |conductor: &Conductor| {
+            let r_in = incoming(1.0);
+            let hit = hit(conductor);
+            let frame = Onb::from_w(hit.normal);
+            let mut spread = (0.0, 0.0);
+            for _ in 0..10_000 {
+                if let Some((_, scattered)) = conductor.scatter(&r_in, &hit) {
+                    let local = frame.to_local(scattered.direction.make_unit_vec());
+                    spread.0 += local.x * local.x;
+                    spread.1 += local.y * local.y;
+                }
+            }
+            spread
+        };
+
+        let (x, y) = spread(&aluminium(0.05, 0.5));
+        assert!(x * 4.0 < y, "{} {}", x, y);
+        let (x, y) = spread(&aluminium(0.5, 0.05));
+        assert!(y * 4.0 < x, "{} {}", x, y);
+    }
+
+    #[test]
+    fn presets_have_known_reflectance() {
+        // ((eta - 1)^2 + k^2) / ((eta + 1)^2 + k^2) at normal incidence
+        let presets = [
+            (Conductor::gold(0.1), Vec3::new(0.967, 0.803, 0.324)),
+            (Conductor::copper(0.1), Vec3::new(0.952, 0.620, 0.511)),
+            (Conductor::aluminium(0.1), Vec3::new(0.928, 0.918, 0.919)),
+            (Conductor::silver(0.1), Vec3::new(0.975, 0.957, 0.907)),
+        ];
+        let r_in = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        for (conductor, f0) in &presets {
+            let albedo = conductor.albedo(&r_in, &hit(conductor));
+            assert!((albedo - *f0).length() < 1e-3, "{:?} {:?}", albedo, f0);
+        }
+    }
+}
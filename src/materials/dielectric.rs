@@ -1,16 +1,93 @@
 use crate::hitable::HitRecord;
 use crate::materials::Material;
+use crate::medium_stack::Interior;
 use crate::ray::Ray;
+use crate::sampler;
 use crate::vec3::Vec3;
 
+/// Index of refraction varying with wavelength, wavelengths are converted to micrometers.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Dispersion {
+    /// n = a + b / lambda^2
+    Cauchy { a: f64, b: f64 },
+    /// n^2 = 1 + sum(b_i * lambda^2 / (lambda^2 - c_i))
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Schott N-BK7 crown glass
+    pub fn bk7() -> Dispersion {
+        Dispersion::Sellmeier {
+            b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+            c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+        }
+    }
+
+    pub fn diamond() -> Dispersion {
+        Dispersion::Sellmeier {
+            b: [4.3356, 0.3306, 0.0],
+            c: [0.011_236, 0.030_625, 0.0],
+        }
+    }
+
+    pub fn refraction_idx(&self, wavelength_nm: f64) -> f64 {
+        let lambda = wavelength_nm / 1000.0;
+        let lambda2 = lambda * lambda;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / lambda2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = b
+                    .iter()
+                    .zip(c.iter())
+                    .map(|(b, c)| b * lambda2 / (lambda2 - c))
+                    .sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Dielectric {
     pub refraction_idx: f64,
+    /// Beer-Lambert absorption coefficient per unit length, for colored glass or liquids.
+    pub absorption: Vec3,
+    /// Set to take part in nested dielectric handling, see `MediumStack`.
+    pub priority: Option<u32>,
+    /// Wavelength dependent index of refraction, only used when rendering spectrally.
+    pub dispersion: Option<Dispersion>,
 }
 
 impl Dielectric {
     pub fn new(refraction_idx: f64) -> Dielectric {
-        Dielectric { refraction_idx }
+        Dielectric {
+            refraction_idx,
+            absorption: Vec3::origin(),
+            priority: None,
+            dispersion: None,
+        }
+    }
+
+    pub fn with_absorption(mut self, absorption: Vec3) -> Dielectric {
+        self.absorption = absorption;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: u32) -> Dielectric {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn with_dispersion(mut self, dispersion: Dispersion) -> Dielectric {
+        self.dispersion = Some(dispersion);
+        self
+    }
+
+    fn refraction_idx_at(&self, wavelength: Option<f64>) -> f64 {
+        match (self.dispersion, wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.refraction_idx(wavelength),
+            _ => self.refraction_idx,
+        }
     }
 
     fn reflect(v: Vec3, normal: Vec3) -> Vec3 {
@@ -36,21 +113,59 @@ impl Dielectric {
 
 impl Material for Dielectric {
     fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let refraction_idx = self.refraction_idx_at(r_in.wavelength);
+        let (_, scattered) = self.scatter_between(r_in, hit_record, 1.0, refraction_idx)?;
+
+        // Light leaving the glass was absorbed along the way in
+        let attenuation = if r_in.direction.dot(hit_record.normal) > 0.0 {
+            let distance = hit_record.t * r_in.direction.length();
+            Vec3::new(
+                (-self.absorption.x * distance).exp(),
+                (-self.absorption.y * distance).exp(),
+                (-self.absorption.z * distance).exp(),
+            )
+        } else {
+            Vec3::new(1.0, 1.0, 1.0)
+        };
+
+        Some((attenuation, scattered))
+    }
+
+    fn dispersive(&self) -> bool {
+        self.dispersion.is_some()
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        self.priority.map(|priority| Interior {
+            priority,
+            refraction_idx: self.refraction_idx,
+            absorption: self.absorption,
+            dispersion: self.dispersion,
+        })
+    }
+
+    fn scatter_between(
+        &self,
+        r_in: &Ray,
+        hit_record: &HitRecord,
+        outside_idx: f64,
+        inside_idx: f64,
+    ) -> Option<(Vec3, Ray)> {
+        let relative_idx = inside_idx / outside_idx;
         let (outward_normal, ni_over_nt, cosine) = if r_in.direction.dot(hit_record.normal) > 0.0 {
             let outward_normal = hit_record.normal * -1.0;
             (
                 outward_normal,
-                self.refraction_idx,
-                self.refraction_idx
-                    * (r_in.direction.dot(hit_record.normal) / r_in.direction.length()),
+                relative_idx,
+                relative_idx * (r_in.direction.dot(hit_record.normal) / r_in.direction.length()),
             )
         } else {
             // total refraction
             let outward_normal = hit_record.normal;
             (
                 outward_normal,
-                1.0 / self.refraction_idx,
-                -1.0 * (r_in.direction.dot(hit_record.normal) / r_in.direction.length()),
+                1.0 / relative_idx,
+                -(r_in.direction.dot(hit_record.normal) / r_in.direction.length()),
             )
         };
 
@@ -60,8 +175,8 @@ impl Material for Dielectric {
         match Dielectric::refract(r_in.direction, outward_normal, ni_over_nt) {
             Some(refracted) => {
                 // Calculate chance for total internal refraction
-                let reflect_prob = Dielectric::schlick(cosine, self.refraction_idx);
-                if rand::random::<f64>() < reflect_prob {
+                let reflect_prob = Dielectric::schlick(cosine, relative_idx);
+                if sampler::random() < reflect_prob {
                     Some((attenuation, Ray::new(hit_record.position, reflected)))
                 } else {
                     Some((attenuation, Ray::new(hit_record.position, refracted)))
@@ -0,0 +1,81 @@
+use crate::hitable::HitRecord;
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths, Spectrum};
+use crate::vec3::Vec3;
+
+/// Emits light uniformly from the front (normal) side of a surface, and absorbs everything.
+#[derive(Debug)]
+pub struct DiffuseLight {
+    spectrum: Spectrum,
+    scale: f64,
+    /// `spectrum * scale` converted to RGB once, for RGB rendering.
+    color: Vec3,
+}
+
+impl DiffuseLight {
+    /// Emits an RGB color, which is upsampled when rendering spectrally.
+    pub fn new(color: Vec3) -> DiffuseLight {
+        DiffuseLight {
+            spectrum: Spectrum::Constant(0.0),
+            scale: 0.0,
+            color,
+        }
+    }
+
+    /// Emits the spectrum scaled to the given luminance (see `photometry`).
+    pub fn from_spectrum(spectrum: Spectrum, luminance: f64) -> DiffuseLight {
+        let spectrum_luminance = spectrum.luminance();
+        let scale = if spectrum_luminance > 0.0 {
+            luminance / spectrum_luminance
+        } else {
+            0.0
+        };
+
+        DiffuseLight {
+            color: spectrum.to_rgb() * scale,
+            spectrum,
+            scale,
+        }
+    }
+
+    /// Black body at `temperature` kelvin with the given luminance.
+    pub fn blackbody(temperature: f64, luminance: f64) -> DiffuseLight {
+        DiffuseLight::from_spectrum(Spectrum::blackbody(temperature), luminance)
+    }
+
+    fn is_front_face(r_in: &Ray, hit_record: &HitRecord) -> bool {
+        r_in.direction.dot(hit_record.normal) < 0.0
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        None
+    }
+
+    fn emitted(&self, r_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        if DiffuseLight::is_front_face(r_in, hit_record) {
+            self.color
+        } else {
+            Vec3::origin()
+        }
+    }
+
+    fn emitted_spectrum(
+        &self,
+        r_in: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        if !DiffuseLight::is_front_face(r_in, hit_record) {
+            return SampledSpectrum::constant(0.0);
+        }
+
+        if self.scale == 0.0 {
+            return wavelengths.spectrum_from_rgb(self.color);
+        }
+
+        wavelengths.spectrum_from_fn(|lambda| self.spectrum.value(lambda) * self.scale)
+    }
+}
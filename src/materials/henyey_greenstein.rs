@@ -0,0 +1,135 @@
+use crate::hitable::HitRecord;
+use crate::materials::Material;
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Anisotropic phase function, `g` > 0 favours forward scattering and `g` < 0 back scattering.
+/// `g` = 0 is the same as `Isotropic`.
+#[derive(Debug)]
+pub struct HenyeyGreenstein {
+    pub albedo: Vec3,
+    pub g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Vec3, g: f64) -> HenyeyGreenstein {
+        HenyeyGreenstein {
+            albedo,
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    /// Phase function value for the cosine between the incoming travel direction and the scattered direction.
+    pub fn phase(&self, cos_theta: f64) -> f64 {
+        let denominator = 1.0 + self.g * self.g - 2.0 * self.g * cos_theta;
+        (1.0 - self.g * self.g) / (4.0 * f64::consts::PI * denominator * denominator.sqrt())
+    }
+
+    /// Samples the cosine between the incoming and the scattered direction.
+    fn sample_cos_theta(&self, xi: f64) -> f64 {
+        if self.g.abs() < 1e-3 {
+            return 1.0 - 2.0 * xi;
+        }
+
+        let g = self.g;
+        let square = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+        (1.0 + g * g - square * square) / (2.0 * g)
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let cos_theta = self.sample_cos_theta(sampler::random());
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * f64::consts::PI * sampler::random();
+
+        let frame = Onb::from_w(r_in.direction);
+        let direction = frame.local(Vec3::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+
+        Some((self.albedo, Ray::new(hit_record.position, direction)))
+    }
+
+    fn bsdf(&self, r_in: &Ray, _hit_record: &HitRecord, direction: Vec3) -> Option<Vec3> {
+        let cos_theta = r_in.direction.make_unit_vec().dot(direction);
+        Some(self.albedo * self.phase(cos_theta))
+    }
+
+    fn pdf(&self, r_in: &Ray, _hit_record: &HitRecord, direction: Vec3) -> f64 {
+        self.phase(r_in.direction.make_unit_vec().dot(direction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::materials::henyey_greenstein::HenyeyGreenstein;
+    use crate::materials::testing::hit;
+    use crate::materials::Material;
+    use crate::ray::Ray;
+    use crate::vec3::Vec3;
+    use std::f64;
+
+    /// Cosines between the incoming and the scattered directions of `samples` scattered rays.
+    fn sampled_cosines(phase: &HenyeyGreenstein, samples: usize) -> Vec<f64> {
+        let r_in = Ray::new(Vec3::new(0.0, 1.0, 1.0), Vec3::new(0.0, -1.0, -1.0));
+        let hit = hit(phase);
+        (0..samples)
+            .map(|_| {
+                let (_, scattered) = phase.scatter(&r_in, &hit).expect("always scatters");
+                r_in.direction
+                    .make_unit_vec()
+                    .dot(scattered.direction.make_unit_vec())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mean_cosine_is_g() {
+        for &g in &[-0.3, 0.0, 0.6] {
+            let phase = HenyeyGreenstein::new(Vec3::new(1.0, 1.0, 1.0), g);
+            let cosines = sampled_cosines(&phase, 50_000);
+            let mean = cosines.iter().sum::<f64>() / cosines.len() as f64;
+            assert!((mean - g).abs() < 0.01, "{} {}", g, mean);
+        }
+    }
+
+    #[test]
+    fn samples_follow_the_phase_function() {
+        let phase = HenyeyGreenstein::new(Vec3::new(1.0, 1.0, 1.0), 0.5);
+        let samples = 50_000;
+        let cosines = sampled_cosines(&phase, samples);
+
+        // Fraction of the samples in each band of cosines against the integral over its solid
+        // angle, 2 pi per unit of cosine
+        let bins = 8;
+        for bin in 0..bins {
+            let low = -1.0 + 2.0 * f64::from(bin) / f64::from(bins);
+            let high = low + 2.0 / f64::from(bins);
+            let sampled =
+                cosines.iter().filter(|&&c| c >= low && c < high).count() as f64 / samples as f64;
+
+            let steps = 1000;
+            let expected = (0..steps)
+                .map(|i| {
+                    let cos_theta = low + (high - low) * (f64::from(i) + 0.5) / f64::from(steps);
+                    2.0 * f64::consts::PI * phase.phase(cos_theta)
+                })
+                .sum::<f64>()
+                * (high - low)
+                / f64::from(steps);
+            assert!(
+                (sampled - expected).abs() < 0.01,
+                "{} {} {}",
+                low,
+                sampled,
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use crate::hitable::HitRecord;
+use crate::integrators::sky;
+use crate::materials::{Compositing, Material};
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Stands in for objects of the photograph a render is composited onto: hides what's behind it
+/// and shows the background in its place, with an alpha of 0 where the camera sees it. It still
+/// casts shadows, and reflections show the background where it is.
+#[derive(Debug, Default)]
+pub struct Holdout;
+
+impl Holdout {
+    pub fn new() -> Holdout {
+        Holdout
+    }
+}
+
+impl Material for Holdout {
+    fn scatter(&self, _r_in: &Ray, _hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        None
+    }
+
+    fn emitted(&self, r_in: &Ray, _hit_record: &HitRecord) -> Vec3 {
+        sky(r_in)
+    }
+
+    fn compositing(&self) -> Compositing {
+        Compositing::Holdout
+    }
+}
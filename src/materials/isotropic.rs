@@ -0,0 +1,75 @@
+use crate::hitable::HitRecord;
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Phase function scattering uniformly in every direction, used inside participating media.
+#[derive(Debug)]
+pub struct Isotropic {
+    pub albedo: Vec3,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Vec3) -> Isotropic {
+        Isotropic { albedo }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, _r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let direction = Vec3::random_in_unit_sphere().make_unit_vec();
+        Some((self.albedo, Ray::new(hit_record.position, direction)))
+    }
+
+    fn bsdf(&self, _r_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> Option<Vec3> {
+        Some(self.albedo / (4.0 * f64::consts::PI))
+    }
+
+    fn pdf(&self, _r_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> f64 {
+        1.0 / (4.0 * f64::consts::PI)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hitable::HitRecord;
+    use crate::materials::isotropic::Isotropic;
+    use crate::materials::Material;
+    use crate::ray::Ray;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn scatters_uniformly_over_the_sphere() {
+        let fog = Isotropic::new(Vec3::new(0.8, 0.8, 0.8));
+        let hit = HitRecord::new(
+            0.0,
+            Vec3::origin(),
+            Vec3::new(1.0, 0.0, 0.0),
+            0.0,
+            0.0,
+            &fog,
+        );
+        let r_in = Ray::new(Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+
+        let samples = 20_000;
+        let mut sum = Vec3::origin();
+        let mut forward = 0;
+        for _ in 0..samples {
+            let (attenuation, scattered) = fog.scatter(&r_in, &hit).expect("always scatters");
+            assert_eq!(attenuation, Vec3::new(0.8, 0.8, 0.8));
+            assert!((scattered.direction.length() - 1.0).abs() < 1e-9);
+            sum += scattered.direction;
+            if scattered.direction.x > 0.5 {
+                forward += 1;
+            }
+        }
+        let mean = sum / samples as f64;
+
+        assert!(mean.length() < 0.03);
+        // The cap above x = 0.5 covers a quarter of the sphere
+        assert!((forward as f64 / samples as f64 - 0.25).abs() < 0.015);
+        let pdf = fog.pdf(&r_in, &hit, Vec3::new(0.0, 1.0, 0.0));
+        assert!((pdf * 4.0 * std::f64::consts::PI - 1.0).abs() < 1e-12);
+    }
+}
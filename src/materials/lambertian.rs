@@ -1,7 +1,9 @@
 use crate::hitable::HitRecord;
 use crate::materials::Material;
+use crate::onb::{random_cosine_direction, Onb};
 use crate::ray::Ray;
 use crate::vec3::Vec3;
+use std::f64;
 
 #[derive(Debug)]
 pub struct Lambertian {
@@ -15,10 +17,53 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
-        let p = hit_record.position;
-        let target = hit_record.position + hit_record.normal + Vec3::random_in_unit_sphere();
-        let direction = target - p;
-        Some((self.albedo, Ray::new(p, direction)))
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        // Around the normal on the side the ray comes from, cosine weighted like `pdf`, so the
+        // albedo is the whole weight
+        let normal = hit_record.facing_normal(r_in);
+        let direction = Onb::from_w(normal).local(random_cosine_direction());
+        Some((self.albedo, Ray::new(hit_record.position, direction)))
+    }
+
+    fn bsdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Option<Vec3> {
+        let cosine = direction.dot(hit_record.facing_normal(r_in)).max(0.0);
+        Some(self.albedo * (cosine / f64::consts::PI))
+    }
+
+    fn pdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        direction.dot(hit_record.facing_normal(r_in)).max(0.0) / f64::consts::PI
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hitable::HitRecord;
+    use crate::materials::lambertian::Lambertian;
+    use crate::materials::Material;
+    use crate::ray::Ray;
+    use crate::vec3::Vec3;
+    use std::f64;
+
+    #[test]
+    fn back_faces_match_bsdf_and_pdf() {
+        let lambertian = Lambertian::new(Vec3::new(0.5, 0.5, 0.5));
+        let hit = HitRecord::new(
+            1.0,
+            Vec3::origin(),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            0.0,
+            &lambertian,
+        );
+        let below = Ray::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let down = Vec3::new(0.0, -1.0, 0.0);
+
+        let bsdf = lambertian.bsdf(&below, &hit, down).expect("has a BSDF");
+        assert!((bsdf.x - 0.5 / f64::consts::PI).abs() < 1e-12);
+        assert!((lambertian.pdf(&below, &hit, down) - 1.0 / f64::consts::PI).abs() < 1e-12);
+        assert_eq!(lambertian.pdf(&below, &hit, down * -1.0), 0.0);
+
+        let (_, scattered) = lambertian.scatter(&below, &hit).expect("always scatters");
+        assert!(scattered.direction.y <= 0.0);
     }
 }
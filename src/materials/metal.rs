@@ -1,5 +1,5 @@
 use crate::hitable::HitRecord;
-use crate::materials::Material;
+use crate::materials::{Material, Scattering};
 use crate::ray::Ray;
 use crate::vec3::Vec3;
 
@@ -21,16 +21,57 @@ impl Metal {
 
 impl Material for Metal {
     fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
-        let reflection = Metal::reflect(r_in.direction.make_unit_vec(), hit_record.normal);
+        // Reflects off either side
+        let normal = hit_record.facing_normal(r_in);
+        let reflection = Metal::reflect(r_in.direction.make_unit_vec(), normal);
         let scattered = Ray::new(
             hit_record.position,
             reflection + Vec3::random_in_unit_sphere() * self.fuzz,
         );
 
-        if scattered.direction.dot(hit_record.normal) > 0.0 {
+        if scattered.direction.dot(normal) > 0.0 {
             Some((self.albedo, scattered))
         } else {
             None
         }
     }
+
+    fn scattering(&self, _r_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> Scattering {
+        if self.fuzz > 0.0 {
+            Scattering::Glossy
+        } else {
+            Scattering::Specular
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hitable::Hitable;
+    use crate::materials::metal::Metal;
+    use crate::ray::Ray;
+    use crate::rect::AxisAlignedRect;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn reflects_off_the_back_of_one_sided_shapes() {
+        // The normal of the rectangle points along +z
+        let mirror = AxisAlignedRect::xy(
+            (-1.0, 1.0),
+            (-1.0, 1.0),
+            0.0,
+            Box::new(Metal::new(Vec3::new(0.8, 0.8, 0.8), 0.0)),
+        );
+        for &z in &[1.0, -1.0] {
+            let ray = Ray::new(Vec3::new(-0.5, 0.0, z), Vec3::new(0.5, 0.0, -z));
+            let hit = mirror
+                .hit(&ray, 0.001, f64::MAX)
+                .expect("ray points at the mirror");
+            assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+
+            let (_, scattered) = hit.material.scatter(&ray, &hit).expect("reflected");
+            let expected = Vec3::new(0.5, 0.0, z).make_unit_vec();
+            assert!((scattered.direction - expected).length() < 1e-9);
+        }
+    }
 }
@@ -0,0 +1,296 @@
+//! Shared pieces of the microfacet materials.
+//! All directions are in the local shading frame, where the surface normal is +z.
+
+use crate::sampler;
+use crate::vec3::Vec3;
+use std::f64;
+
+/// GGX (Trowbridge-Reitz) distribution of microfacet normals.
+/// Different `alpha_x` and `alpha_y` make the highlight anisotropic.
+#[derive(Debug, Copy, Clone)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    /// Minimal alpha, perfectly smooth surfaces make the distribution degenerate.
+    const MIN_ALPHA: f64 = 1e-4;
+
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Ggx {
+        Ggx {
+            alpha_x: alpha_x.max(Ggx::MIN_ALPHA),
+            alpha_y: alpha_y.max(Ggx::MIN_ALPHA),
+        }
+    }
+
+    /// Maps artist friendly roughness in [0, 1] to alpha by squaring it.
+    pub fn from_roughness(roughness_x: f64, roughness_y: f64) -> Ggx {
+        Ggx::new(roughness_x * roughness_x, roughness_y * roughness_y)
+    }
+
+    /// Density of microfacet normals `m`.
+    pub fn d(&self, m: Vec3) -> f64 {
+        if m.z <= 0.0 {
+            return 0.0;
+        }
+
+        let x = m.x / self.alpha_x;
+        let y = m.y / self.alpha_y;
+        let e = x * x + y * y + m.z * m.z;
+        1.0 / (f64::consts::PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    /// Smith auxiliary function.
+    pub fn lambda(&self, w: Vec3) -> f64 {
+        if w.z == 0.0 {
+            return f64::INFINITY;
+        }
+
+        let x = self.alpha_x * w.x;
+        let y = self.alpha_y * w.y;
+        let tan2 = (x * x + y * y) / (w.z * w.z);
+        ((1.0 + tan2).sqrt() - 1.0) / 2.0
+    }
+
+    /// Smith masking for a single direction.
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Height correlated masking-shadowing.
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of visible normals as seen from `wo`, the pdf of `sample_visible_normal`.
+    pub fn visible_normal_pdf(&self, wo: Vec3, m: Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / wo.z
+    }
+
+    /// Samples a microfacet normal visible from `wo` (Heitz 2018), `wo` must be above the surface.
+    pub fn sample_visible_normal(&self, wo: Vec3, u1: f64, u2: f64) -> Vec3 {
+        // Stretch the view direction so the distribution becomes the hemisphere.
+        let vh = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).make_unit_vec();
+
+        let length_squared = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if length_squared > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / length_squared.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(t1);
+
+        // Sample the projected area of the hemisphere
+        let r = u1.sqrt();
+        let phi = 2.0 * f64::consts::PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        // Unstretch
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(0.0)).make_unit_vec()
+    }
+}
+
+/// Microfacet reflection from `wo` to `wi` without the Fresnel term, times the cosine of `wi`.
+/// Returns the half vector, which the Fresnel term should be evaluated with, and the value.
+pub fn reflection(distribution: &Ggx, wo: Vec3, wi: Vec3) -> Option<(Vec3, f64)> {
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return None;
+    }
+
+    let half = (wo + wi).make_unit_vec();
+    let value = distribution.d(half) * distribution.g2(wo, wi) / (4.0 * wo.z);
+    Some((half, value))
+}
+
+/// Solid angle density of reflecting `wo` off a visible normal into `wi`.
+pub fn reflection_pdf(distribution: &Ggx, wo: Vec3, wi: Vec3) -> f64 {
+    if wo.z <= 0.0 || wi.z <= 0.0 {
+        return 0.0;
+    }
+
+    let half = (wo + wi).make_unit_vec();
+    distribution.visible_normal_pdf(wo, half) / (4.0 * wo.dot(half))
+}
+
+/// Generalized half vector of refracting from `wo` into `wi` on the other side of the surface,
+/// facing up, with `eta` as in `refract`. `None` when no microfacet refracts one into the other.
+fn refraction_half(wo: Vec3, wi: Vec3, eta: f64) -> Option<Vec3> {
+    if wo.z <= 0.0 || wi.z >= 0.0 {
+        return None;
+    }
+
+    let half = (wo + wi * eta) * -1.0;
+    let half = if half.z < 0.0 { half * -1.0 } else { half };
+    if half.squared_length() == 0.0 {
+        return None;
+    }
+    let half = half.make_unit_vec();
+    if wo.dot(half) <= 0.0 || wi.dot(half) >= 0.0 {
+        return None;
+    }
+    Some(half)
+}
+
+/// Microfacet refraction from `wo` to `wi` below the surface without the `1 - F` Fresnel term,
+/// times the cosine of `wi` (Walter et al. 2007, eq. 21), `eta` as in `refract`. Returns the
+/// half vector, which the Fresnel term should be evaluated with, and the value.
+pub fn transmission(distribution: &Ggx, wo: Vec3, wi: Vec3, eta: f64) -> Option<(Vec3, f64)> {
+    let half = refraction_half(wo, wi, eta)?;
+    let wo_h = wo.dot(half);
+    let wi_h = wi.dot(half);
+    let denominator = wo_h + eta * wi_h;
+    let value = (wo_h * wi_h).abs() * eta * eta * distribution.d(half) * distribution.g2(wo, wi)
+        / (wo.z * denominator * denominator);
+    Some((half, value))
+}
+
+/// Solid angle density of refracting `wo` through a visible normal into `wi`.
+pub fn transmission_pdf(distribution: &Ggx, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+    let half = match refraction_half(wo, wi, eta) {
+        Some(half) => half,
+        None => return 0.0,
+    };
+    let wi_h = wi.dot(half);
+    let denominator = wo.dot(half) + eta * wi_h;
+    distribution.visible_normal_pdf(wo, half) * eta * eta * wi_h.abs() / (denominator * denominator)
+}
+
+/// Mirror `w` around `m`, both pointing away from the surface.
+pub fn reflect(w: Vec3, m: Vec3) -> Vec3 {
+    m * (2.0 * w.dot(m)) - w
+}
+
+/// Refracts `w` through a microfacet with normal `m`, `eta` is the ratio of the index of refraction
+/// on the far side over the side `w` is on. Returns `None` on total internal reflection.
+pub fn refract(w: Vec3, m: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_theta_i = w.dot(m);
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(w * (-1.0 / eta) + m * (cos_theta_i / eta - cos_theta_t))
+}
+
+/// Samples reflection or refraction through a rough dielectric interface as seen from `wo`,
+/// choosing between them by the exact Fresnel term. Returns the direction and its weight.
+pub fn sample_rough_dielectric(distribution: &Ggx, wo: Vec3, eta: f64) -> Option<(Vec3, f64)> {
+    let m = distribution.sample_visible_normal(wo, sampler::random(), sampler::random());
+    let fresnel = fresnel_dielectric(wo.dot(m), eta);
+
+    // Fresnel cancels out of the weight since it is also the selection probability.
+    let wi = match refract(wo, m, eta) {
+        Some(refracted) if sampler::random() >= fresnel => {
+            if refracted.z >= 0.0 {
+                return None;
+            }
+            refracted
+        }
+        _ => {
+            let reflected = reflect(wo, m);
+            if reflected.z <= 0.0 {
+                return None;
+            }
+            reflected
+        }
+    };
+
+    Some((wi, distribution.g2(wo, wi) / distribution.g1(wo)))
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric interface, `eta` as in `refract`.
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    let rs = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    let rp = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// Unpolarized Fresnel reflectance of a conductor with complex index of refraction `eta + ik`.
+pub fn fresnel_conductor(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+    let cos2 = cos_theta_i * cos_theta_i;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+/// `fresnel_conductor` for each RGB channel.
+pub fn fresnel_conductor_rgb(cos_theta_i: f64, eta: Vec3, k: Vec3) -> Vec3 {
+    Vec3::new(
+        fresnel_conductor(cos_theta_i, eta.x, k.x),
+        fresnel_conductor(cos_theta_i, eta.y, k.y),
+        fresnel_conductor(cos_theta_i, eta.z, k.z),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::materials::microfacet::{fresnel_conductor, fresnel_dielectric, refract, Ggx};
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn conductor_fresnel_at_normal_incidence() {
+        let (eta, k) = (0.2, 3.9);
+        let expected = ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+
+        assert!((fresnel_conductor(1.0, eta, k) - expected).abs() < 1e-9);
+        assert!((fresnel_conductor(0.0, eta, k) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn dielectric_fresnel_and_refraction() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-9);
+        assert_eq!(fresnel_dielectric(0.2, 1.0 / 1.5), 1.0);
+
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let straight = refract(normal, normal, 1.5).expect("no total internal reflection");
+        assert!((straight - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-9);
+
+        let grazing = Vec3::new(0.98, 0.0, 0.2).make_unit_vec();
+        assert!(refract(grazing, normal, 1.0 / 1.5).is_none());
+    }
+
+    #[test]
+    fn visible_normals_face_the_viewer() {
+        let ggx = Ggx::new(0.5, 0.1);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+
+        for i in 0..10 {
+            for j in 0..10 {
+                let m = ggx.sample_visible_normal(wo, f64::from(i) / 10.0, f64::from(j) / 10.0);
+                assert!(m.z >= 0.0);
+                assert!(wo.dot(m) >= 0.0);
+                assert!((m.length() - 1.0).abs() < 1e-9);
+            }
+        }
+    }
+}
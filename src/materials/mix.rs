@@ -0,0 +1,271 @@
+use crate::hitable::HitRecord;
+use crate::materials::{Compositing, Material, Scattering};
+use crate::medium_stack::Interior;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths};
+use crate::textures::Texture;
+use crate::vec3::Vec3;
+
+/// Blends two materials by a mask, 0 is all `first` and 1 is all `second`.
+///
+/// Scattering picks one of the materials with probability given by the mask, which averages to
+/// the weighted blend of the two over many samples. Emission is blended directly.
+/// The mask is read from the first channel of the texture.
+pub struct Mix {
+    first: Box<dyn Material + Send + Sync>,
+    second: Box<dyn Material + Send + Sync>,
+    mask: Box<dyn Texture>,
+}
+
+impl Mix {
+    pub fn new(
+        first: Box<dyn Material + Send + Sync>,
+        second: Box<dyn Material + Send + Sync>,
+        mask: Box<dyn Texture>,
+    ) -> Mix {
+        Mix {
+            first,
+            second,
+            mask,
+        }
+    }
+
+    fn weight(&self, hit_record: &HitRecord) -> f64 {
+        self.mask
+            .value(hit_record.u, hit_record.v, hit_record.position)
+            .x
+            .clamp(0.0, 1.0)
+    }
+
+    /// One of the materials picked with the probability given by the mask.
+    fn pick(&self, hit_record: &HitRecord) -> &dyn Material {
+        if sampler::random() < self.weight(hit_record) {
+            self.second.as_ref()
+        } else {
+            self.first.as_ref()
+        }
+    }
+}
+
+impl Material for Mix {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        self.pick(hit_record).scatter(r_in, hit_record)
+    }
+
+    fn bsdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Option<Vec3> {
+        let weight = self.weight(hit_record);
+        let first = self.first.bsdf(r_in, hit_record, direction);
+        let second = self.second.bsdf(r_in, hit_record, direction);
+        if first.is_none() && second.is_none() {
+            return None;
+        }
+
+        // A specular material in the mix simply isn't lit directly
+        Some(
+            first.unwrap_or_else(Vec3::origin) * (1.0 - weight)
+                + second.unwrap_or_else(Vec3::origin) * weight,
+        )
+    }
+
+    fn pdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        let weight = self.weight(hit_record);
+        self.first.pdf(r_in, hit_record, direction) * (1.0 - weight)
+            + self.second.pdf(r_in, hit_record, direction) * weight
+    }
+
+    fn scattering(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Scattering {
+        let weight = self.weight(hit_record);
+        let first = self.first.pdf(r_in, hit_record, direction) * (1.0 - weight);
+        let second = self.second.pdf(r_in, hit_record, direction) * weight;
+
+        // Without a density, the material more likely to be picked
+        if second > first || (second == first && weight > 0.5) {
+            self.second.scattering(r_in, hit_record, direction)
+        } else {
+            self.first.scattering(r_in, hit_record, direction)
+        }
+    }
+
+    fn albedo(&self, r_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        let weight = self.weight(hit_record);
+        self.first.albedo(r_in, hit_record) * (1.0 - weight)
+            + self.second.albedo(r_in, hit_record) * weight
+    }
+
+    fn dispersive(&self) -> bool {
+        self.first.dispersive() || self.second.dispersive()
+    }
+
+    fn emitted(&self, r_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        let weight = self.weight(hit_record);
+        self.first.emitted(r_in, hit_record) * (1.0 - weight)
+            + self.second.emitted(r_in, hit_record) * weight
+    }
+
+    fn emitted_spectrum(
+        &self,
+        r_in: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        let weight = self.weight(hit_record);
+        SampledSpectrum::constant(1.0 - weight)
+            * self.first.emitted_spectrum(r_in, hit_record, wavelengths)
+            + SampledSpectrum::constant(weight)
+                * self.second.emitted_spectrum(r_in, hit_record, wavelengths)
+    }
+
+    /// Compositing can't be blended, a holdout or shadow catcher in either material applies to
+    /// the whole surface, with `first` taking precedence.
+    fn compositing(&self) -> Compositing {
+        match self.first.compositing() {
+            Compositing::Opaque => self.second.compositing(),
+            compositing => compositing,
+        }
+    }
+
+    /// Media can't be blended, the interior is the one of `first`, or of `second` if `first`
+    /// has none.
+    fn interior(&self) -> Option<Interior> {
+        self.first.interior().or_else(|| self.second.interior())
+    }
+
+    /// The name of `first`, or of `second` if `first` has none.
+    fn name(&self) -> Option<&str> {
+        self.first.name().or_else(|| self.second.name())
+    }
+
+    fn scatter_between(
+        &self,
+        r_in: &Ray,
+        hit_record: &HitRecord,
+        outside_idx: f64,
+        inside_idx: f64,
+    ) -> Option<(Vec3, Ray)> {
+        self.pick(hit_record)
+            .scatter_between(r_in, hit_record, outside_idx, inside_idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hitable::HitRecord;
+    use crate::materials::dielectric::Dielectric;
+    use crate::materials::diffuse_light::DiffuseLight;
+    use crate::materials::holdout::Holdout;
+    use crate::materials::lambertian::Lambertian;
+    use crate::materials::metal::Metal;
+    use crate::materials::mix::Mix;
+    use crate::materials::named::Named;
+    use crate::materials::{Compositing, Material};
+    use crate::ray::Ray;
+    use crate::spectrum::SampledWavelengths;
+    use crate::textures::constant::ConstantTexture;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn picks_materials_by_the_mask() {
+        let red = Vec3::new(0.8, 0.1, 0.1);
+        let mix = Mix::new(
+            Box::new(Lambertian::new(red)),
+            Box::new(Metal::new(Vec3::new(1.0, 1.0, 1.0), 0.0)),
+            Box::new(ConstantTexture::scalar(0.25)),
+        );
+        let hit = HitRecord::new(
+            1.0,
+            Vec3::origin(),
+            Vec3::new(0.0, 0.0, 1.0),
+            0.0,
+            0.0,
+            &mix,
+        );
+        let r_in = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let samples = 20_000;
+        let metal = (0..samples)
+            .filter_map(|_| mix.scatter(&r_in, &hit))
+            .filter(|(attenuation, _)| *attenuation != red)
+            .count();
+        assert!((metal as f64 / samples as f64 - 0.25).abs() < 0.015);
+
+        // The mirror has no BSDF, so only the diffuse part is lit directly
+        let direction = Vec3::new(0.6, 0.0, 0.8);
+        let lambertian = Lambertian::new(red);
+        let expected = lambertian.bsdf(&r_in, &hit, direction).expect("has a BSDF") * 0.75;
+        let bsdf = mix.bsdf(&r_in, &hit, direction).expect("has a BSDF");
+        assert!((bsdf - expected).length() < 1e-12);
+        let pdf = mix.pdf(&r_in, &hit, direction);
+        assert!((pdf - lambertian.pdf(&r_in, &hit, direction) * 0.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn takes_the_interior_of_either_material() {
+        let mix = |first, second| {
+            Mix::new(first, second, Box::new(ConstantTexture::scalar(0.5))).interior()
+        };
+        let glass = || Box::new(Dielectric::new(1.5).with_priority(2));
+        let lambertian = || Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+
+        let interior = mix(lambertian(), glass()).expect("glass has an interior");
+        assert_eq!((interior.priority, interior.refraction_idx), (2, 1.5));
+        assert!(mix(glass(), lambertian()).is_some());
+        assert!(mix(lambertian(), lambertian()).is_none());
+    }
+
+    #[test]
+    fn blends_the_emitted_spectrum() {
+        let light = DiffuseLight::blackbody(4000.0, 2.0);
+        let mix = Mix::new(
+            Box::new(DiffuseLight::blackbody(4000.0, 2.0)),
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+            Box::new(ConstantTexture::scalar(0.25)),
+        );
+        let hit = HitRecord::new(
+            1.0,
+            Vec3::origin(),
+            Vec3::new(0.0, 0.0, 1.0),
+            0.0,
+            0.0,
+            &mix,
+        );
+        let r_in = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let wavelengths = SampledWavelengths::sample(0.3);
+
+        let expected = light.emitted_spectrum(&r_in, &hit, &wavelengths);
+        let emitted = mix.emitted_spectrum(&r_in, &hit, &wavelengths);
+        assert!(expected.0.iter().all(|&value| value > 0.0));
+        for (value, expected) in emitted.0.iter().zip(expected.0.iter()) {
+            assert!((value - 0.75 * expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn holdouts_apply_to_the_whole_surface() {
+        let mix = |first, second| {
+            Mix::new(first, second, Box::new(ConstantTexture::scalar(0.5))).compositing()
+        };
+        let holdout = || Box::new(Holdout::new());
+        let lambertian = || Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+
+        assert_eq!(mix(lambertian(), holdout()), Compositing::Holdout);
+        assert_eq!(mix(holdout(), lambertian()), Compositing::Holdout);
+        assert_eq!(mix(lambertian(), lambertian()), Compositing::Opaque);
+    }
+
+    #[test]
+    fn takes_the_name_of_either_material() {
+        let mix = |first, second| Mix::new(first, second, Box::new(ConstantTexture::scalar(0.5)));
+        let named = |name| {
+            Box::new(Named::new(
+                name,
+                Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+            ))
+        };
+        let lambertian = || Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+
+        assert_eq!(mix(lambertian(), named("floor")).name(), Some("floor"));
+        assert_eq!(mix(named("wall"), named("floor")).name(), Some("wall"));
+        assert_eq!(mix(lambertian(), lambertian()).name(), None);
+    }
+}
@@ -1,11 +1,129 @@
+pub mod clearcoat;
+pub mod conductor;
 pub mod dielectric;
+pub mod diffuse_light;
+pub mod henyey_greenstein;
+pub mod holdout;
+pub mod isotropic;
 pub mod lambertian;
 pub mod metal;
+pub mod microfacet;
+pub mod mix;
+pub mod named;
+pub mod principled;
+pub mod rough_dielectric;
+pub mod shadow_catcher;
+#[cfg(test)]
+mod testing;
 
 use crate::hitable::HitRecord;
+use crate::medium_stack::Interior;
 use crate::ray::Ray;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths};
 use crate::vec3::Vec3;
 
+/// How a material scatters light into a direction, for splitting renders into passes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Scattering {
+    Diffuse,
+    /// Blurry reflections and refractions of rough surfaces.
+    Glossy,
+    /// Perfect mirrors and clear glass.
+    Specular,
+}
+
+/// How the surface is composited onto a photograph when rendering with a transparent background.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compositing {
+    Opaque,
+    /// Shows the background and leaves a hole in the alpha, see `holdout::Holdout`.
+    Holdout,
+    /// Only the shadows and reflections on it are seen, see `shadow_catcher::ShadowCatcher`.
+    ShadowCatcher,
+}
+
 pub trait Material: Send + Sync {
     fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)>;
+
+    /// BSDF times the cosine term for light arriving from the unit vector `direction`, so lights
+    /// can be sampled directly. Specular parts of the material are left out, and (near) specular
+    /// materials return `None`, they can only be lit by following their scattered rays.
+    fn bsdf(&self, _r_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> Option<Vec3> {
+        None
+    }
+
+    /// Solid angle density of `scatter` picking the unit vector `direction`, for the materials
+    /// with a `bsdf`. It only weights sampling strategies against each other, so a close
+    /// approximation of the real sampling density is good enough.
+    fn pdf(&self, _r_in: &Ray, _hit_record: &HitRecord, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    /// Kind of scattering that sends light from the unit vector `direction` towards `r_in`.
+    /// Materials that mix several kinds report the one most likely to have been sampled.
+    fn scattering(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Scattering {
+        if self.pdf(r_in, hit_record, direction) > 0.0 {
+            Scattering::Diffuse
+        } else {
+            Scattering::Specular
+        }
+    }
+
+    /// Colour of the material, what it reflects of the light it scatters towards `r_in`, or the
+    /// light it emits for lights. Shown by the albedo passes, which guide the denoiser. Defaults
+    /// to the weight of a scattered ray, materials whose weight is noisy override it.
+    fn albedo(&self, r_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        match self.scatter(r_in, hit_record) {
+            Some((attenuation, _)) => attenuation,
+            None => self.emitted(r_in, hit_record),
+        }
+    }
+
+    /// Radiance emitted at the hit point towards the incoming ray, black for non emissive materials.
+    fn emitted(&self, _r_in: &Ray, _hit_record: &HitRecord) -> Vec3 {
+        Vec3::origin()
+    }
+
+    /// `emitted` at the wavelengths of a spectral path, upsampled from RGB unless the material
+    /// knows its actual emission spectrum.
+    fn emitted_spectrum(
+        &self,
+        r_in: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        wavelengths.spectrum_from_rgb(self.emitted(r_in, hit_record))
+    }
+
+    /// Whether scattering depends on the wavelength of the ray (eg. dispersion), in which case
+    /// spectral rendering only keeps following a single wavelength.
+    fn dispersive(&self) -> bool {
+        false
+    }
+
+    fn compositing(&self) -> Compositing {
+        Compositing::Opaque
+    }
+
+    /// Medium enclosed by surfaces with this material, for nested dielectrics.
+    fn interior(&self) -> Option<Interior> {
+        None
+    }
+
+    /// Scatters across an interface with index of refraction `outside_idx` on the side the normal
+    /// points to and `inside_idx` on the other. Only used for materials with an `interior`.
+    fn scatter_between(
+        &self,
+        r_in: &Ray,
+        hit_record: &HitRecord,
+        _outside_idx: f64,
+        _inside_idx: f64,
+    ) -> Option<(Vec3, Ray)> {
+        self.scatter(r_in, hit_record)
+    }
+
+    /// Name selecting the material in ID mattes, see `named::Named`.
+    fn name(&self) -> Option<&str> {
+        None
+    }
 }
@@ -0,0 +1,84 @@
+use crate::hitable::HitRecord;
+use crate::materials::{Compositing, Material, Scattering};
+use crate::medium_stack::Interior;
+use crate::ray::Ray;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths};
+use crate::vec3::Vec3;
+
+/// Gives a material a name, to select it in ID mattes. Behaves exactly like the material.
+pub struct Named {
+    name: String,
+    material: Box<dyn Material + Send + Sync>,
+}
+
+impl Named {
+    pub fn new(name: impl Into<String>, material: Box<dyn Material + Send + Sync>) -> Named {
+        Named {
+            name: name.into(),
+            material,
+        }
+    }
+}
+
+impl Material for Named {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        self.material.scatter(r_in, hit_record)
+    }
+
+    fn bsdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Option<Vec3> {
+        self.material.bsdf(r_in, hit_record, direction)
+    }
+
+    fn pdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        self.material.pdf(r_in, hit_record, direction)
+    }
+
+    fn scattering(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Scattering {
+        self.material.scattering(r_in, hit_record, direction)
+    }
+
+    fn albedo(&self, r_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        self.material.albedo(r_in, hit_record)
+    }
+
+    fn emitted(&self, r_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        self.material.emitted(r_in, hit_record)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        r_in: &Ray,
+        hit_record: &HitRecord,
+        wavelengths: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        self.material
+            .emitted_spectrum(r_in, hit_record, wavelengths)
+    }
+
+    fn dispersive(&self) -> bool {
+        self.material.dispersive()
+    }
+
+    fn compositing(&self) -> Compositing {
+        self.material.compositing()
+    }
+
+    fn interior(&self) -> Option<Interior> {
+        self.material.interior()
+    }
+
+    fn scatter_between(
+        &self,
+        r_in: &Ray,
+        hit_record: &HitRecord,
+        outside_idx: f64,
+        inside_idx: f64,
+    ) -> Option<(Vec3, Ray)> {
+        self.material
+            .scatter_between(r_in, hit_record, outside_idx, inside_idx)
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+}
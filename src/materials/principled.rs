@@ -0,0 +1,510 @@
+use crate::hitable::HitRecord;
+use crate::materials::microfacet::{
+    reflect, reflection, reflection_pdf, sample_rough_dielectric, Ggx,
+};
+use crate::materials::{Material, Scattering};
+use crate::onb::{random_cosine_direction, Onb};
+use crate::ray::Ray;
+use crate::sampler;
+use crate::textures::constant::ConstantTexture;
+use crate::textures::Texture;
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Disney style "uber" material, described by the parameters artists are used to.
+///
+/// The BSDF is a weighted sum of a diffuse (+ sheen) lobe, a GGX specular lobe, a clearcoat lobe
+/// and a rough dielectric transmission lobe. The diffuse lobe is weighted by the light the
+/// specular layer transmits, `1 - F`. Every scatter event picks one lobe proportionally to its
+/// estimated contribution and divides by the selection probability.
+///
+/// Scalar parameters are read from the first channel of their texture.
+pub struct Principled {
+    base_color: Box<dyn Texture>,
+    metallic: Box<dyn Texture>,
+    roughness: Box<dyn Texture>,
+    specular: Box<dyn Texture>,
+    clearcoat: Box<dyn Texture>,
+    clearcoat_roughness: Box<dyn Texture>,
+    sheen: Box<dyn Texture>,
+    transmission: Box<dyn Texture>,
+    ior: f64,
+}
+
+/// Parameters evaluated at a hit point.
+struct Parameters {
+    base_color: Vec3,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    clearcoat: f64,
+    clearcoat_roughness: f64,
+    sheen: f64,
+    transmission: f64,
+}
+
+impl Parameters {
+    /// Reflectance at normal incidence, dielectrics get a grey `specular` and metals their base color.
+    fn specular_f0(&self) -> Vec3 {
+        lerp(
+            Vec3::new(1.0, 1.0, 1.0) * (0.08 * self.specular),
+            self.base_color,
+            self.metallic,
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Lobe {
+    Diffuse,
+    Specular,
+    Clearcoat,
+    Transmission,
+}
+
+fn scalar(value: f64) -> Box<dyn Texture> {
+    Box::new(ConstantTexture::scalar(value))
+}
+
+fn lerp(a: Vec3, b: Vec3, t: f64) -> Vec3 {
+    a * (1.0 - t) + b * t
+}
+
+/// Schlick's Fresnel approximation, with the grazing reflectance faded out for very low `f0`
+/// so a zero specular really turns the lobe off.
+fn schlick(f0: Vec3, cos_theta: f64) -> Vec3 {
+    let f90 = (50.0 * f0.luminance()).min(1.0);
+    let weight = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0 + (Vec3::new(f90, f90, f90) - f0) * weight
+}
+
+impl Principled {
+    /// A plastic like dielectric, adjust with the `with_*` methods.
+    pub fn new(base_color: Box<dyn Texture>) -> Principled {
+        Principled {
+            base_color,
+            metallic: scalar(0.0),
+            roughness: scalar(0.5),
+            specular: scalar(0.5),
+            clearcoat: scalar(0.0),
+            clearcoat_roughness: scalar(0.03),
+            sheen: scalar(0.0),
+            transmission: scalar(0.0),
+            ior: 1.5,
+        }
+    }
+
+    pub fn with_metallic(mut self, metallic: Box<dyn Texture>) -> Principled {
+        self.metallic = metallic;
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: Box<dyn Texture>) -> Principled {
+        self.roughness = roughness;
+        self
+    }
+
+    /// Reflectance of dielectrics at normal incidence, 0.5 maps to the usual 4%.
+    pub fn with_specular(mut self, specular: Box<dyn Texture>) -> Principled {
+        self.specular = specular;
+        self
+    }
+
+    pub fn with_clearcoat(
+        mut self,
+        clearcoat: Box<dyn Texture>,
+        clearcoat_roughness: Box<dyn Texture>,
+    ) -> Principled {
+        self.clearcoat = clearcoat;
+        self.clearcoat_roughness = clearcoat_roughness;
+        self
+    }
+
+    pub fn with_sheen(mut self, sheen: Box<dyn Texture>) -> Principled {
+        self.sheen = sheen;
+        self
+    }
+
+    pub fn with_transmission(mut self, transmission: Box<dyn Texture>, ior: f64) -> Principled {
+        self.transmission = transmission;
+        self.ior = ior;
+        self
+    }
+
+    /// Matches `Lambertian`.
+    pub fn lambertian(albedo: Vec3) -> Principled {
+        Principled::new(Box::new(ConstantTexture::new(albedo)))
+            .with_roughness(scalar(1.0))
+            .with_specular(scalar(0.0))
+    }
+
+    /// Approximates `Metal`, using the fuzz as roughness.
+    pub fn metal(albedo: Vec3, fuzz: f64) -> Principled {
+        Principled::new(Box::new(ConstantTexture::new(albedo)))
+            .with_metallic(scalar(1.0))
+            .with_roughness(scalar(fuzz.min(1.0)))
+    }
+
+    /// Matches `Dielectric`.
+    pub fn dielectric(refraction_idx: f64) -> Principled {
+        Principled::new(Box::new(ConstantTexture::scalar(1.0)))
+            .with_roughness(scalar(0.0))
+            .with_transmission(scalar(1.0), refraction_idx)
+    }
+
+    fn parameters(&self, hit_record: &HitRecord) -> Parameters {
+        let (u, v, p) = (hit_record.u, hit_record.v, hit_record.position);
+        let scalar = |texture: &dyn Texture| texture.value(u, v, p).x.clamp(0.0, 1.0);
+
+        Parameters {
+            base_color: self.base_color.value(u, v, p),
+            metallic: scalar(self.metallic.as_ref()),
+            roughness: scalar(self.roughness.as_ref()),
+            specular: scalar(self.specular.as_ref()),
+            clearcoat: scalar(self.clearcoat.as_ref()),
+            clearcoat_roughness: scalar(self.clearcoat_roughness.as_ref()),
+            sheen: scalar(self.sheen.as_ref()),
+            transmission: scalar(self.transmission.as_ref()),
+        }
+    }
+
+    /// Weight of every lobe in the sum, and the probability of sampling it.
+    fn lobes(parameters: &Parameters, cos_theta_o: f64) -> [(Lobe, f64, f64); 4] {
+        let dielectric = 1.0 - parameters.metallic;
+        // The diffuse base only receives what the dielectric specular layer lets through
+        let dielectric_f0 = Vec3::new(1.0, 1.0, 1.0) * (0.08 * parameters.specular);
+        let diffuse = dielectric
+            * (1.0 - parameters.transmission)
+            * (1.0 - schlick(dielectric_f0, cos_theta_o).luminance());
+        let transmission = dielectric * parameters.transmission;
+        let specular = 1.0 - transmission;
+        let clearcoat = parameters.clearcoat;
+
+        let coat_f0 = Vec3::new(0.04, 0.04, 0.04);
+
+        let estimates = [
+            diffuse * (parameters.base_color.luminance() + parameters.sheen),
+            specular * schlick(parameters.specular_f0(), cos_theta_o).luminance(),
+            clearcoat * schlick(coat_f0, cos_theta_o).luminance(),
+            transmission,
+        ];
+        let total: f64 = estimates.iter().sum();
+        let probability = |i: usize| {
+            if total > 0.0 {
+                estimates[i] / total
+            } else {
+                0.0
+            }
+        };
+
+        [
+            (Lobe::Diffuse, diffuse, probability(0)),
+            (Lobe::Specular, specular, probability(1)),
+            (Lobe::Clearcoat, clearcoat, probability(2)),
+            (Lobe::Transmission, transmission, probability(3)),
+        ]
+    }
+
+    /// Samples a single lobe, returning the direction and its weight (BSDF * cos / pdf).
+    fn sample_lobe(
+        lobe: Lobe,
+        parameters: &Parameters,
+        wo: Vec3,
+        eta: f64,
+    ) -> Option<(Vec3, Vec3)> {
+        match lobe {
+            Lobe::Diffuse => {
+                let wi = random_cosine_direction();
+                let half = (wo + wi).make_unit_vec();
+                let sheen = parameters.sheen * (1.0 - wi.dot(half)).powi(5);
+                Some((wi, parameters.base_color + Vec3::new(sheen, sheen, sheen)))
+            }
+            Lobe::Specular => {
+                let distribution = Ggx::from_roughness(parameters.roughness, parameters.roughness);
+                Principled::sample_reflection(&distribution, parameters.specular_f0(), wo)
+            }
+            Lobe::Clearcoat => {
+                let distribution = Ggx::from_roughness(
+                    parameters.clearcoat_roughness,
+                    parameters.clearcoat_roughness,
+                );
+                Principled::sample_reflection(&distribution, Vec3::new(0.04, 0.04, 0.04), wo)
+            }
+            Lobe::Transmission => {
+                let distribution = Ggx::from_roughness(parameters.roughness, parameters.roughness);
+                let (wi, weight) = sample_rough_dielectric(&distribution, wo, eta)?;
+                // Only tint light that actually went through the surface
+                let tint = if wi.z < 0.0 {
+                    parameters.base_color
+                } else {
+                    Vec3::new(1.0, 1.0, 1.0)
+                };
+                Some((wi, tint * weight))
+            }
+        }
+    }
+
+    fn sample_reflection(distribution: &Ggx, f0: Vec3, wo: Vec3) -> Option<(Vec3, Vec3)> {
+        let m = distribution.sample_visible_normal(wo, sampler::random(), sampler::random());
+        let wi = reflect(wo, m);
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        let weight = distribution.g2(wo, wi) / distribution.g1(wo);
+        Some((wi, schlick(f0, wo.dot(m)) * weight))
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let parameters = self.parameters(hit_record);
+
+        // Opaque lobes are two sided, transmission needs to know which side the ray is on.
+        let entering = r_in.direction.dot(hit_record.normal) < 0.0;
+        let (normal, eta) = if entering {
+            (hit_record.normal, self.ior)
+        } else {
+            (hit_record.normal * -1.0, 1.0 / self.ior)
+        };
+        let frame = Onb::from_w(normal);
+
+        let wo = frame.to_local(r_in.direction.make_unit_vec() * -1.0);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let lobes = Principled::lobes(&parameters, wo.z);
+        let mut xi = sampler::random();
+        let (lobe, weight, probability) = lobes
+            .iter()
+            .find(|(_, _, probability)| {
+                xi -= probability;
+                xi < 0.0
+            })
+            .or_else(|| {
+                lobes
+                    .iter()
+                    .rev()
+                    .find(|(_, _, probability)| *probability > 0.0)
+            })?;
+
+        let (wi, lobe_weight) = Principled::sample_lobe(*lobe, &parameters, wo, eta)?;
+
+        Some((
+            lobe_weight * (weight / probability),
+            Ray::new(hit_record.position, frame.local(wi)),
+        ))
+    }
+
+    /// Only the reflection lobes, transmission is left to the scattered rays.
+    fn bsdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Option<Vec3> {
+        let parameters = self.parameters(hit_record);
+        let normal = hit_record.facing_normal(r_in);
+        let frame = Onb::from_w(normal);
+        let wo = frame.to_local(r_in.direction.make_unit_vec() * -1.0);
+        let wi = frame.to_local(direction);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Some(Vec3::origin());
+        }
+
+        let lobes = Principled::lobes(&parameters, wo.z);
+        let half = (wo + wi).make_unit_vec();
+
+        let sheen = parameters.sheen * (1.0 - wi.dot(half)).powi(5);
+        let diffuse = (parameters.base_color + Vec3::new(sheen, sheen, sheen))
+            * (lobes[0].1 * wi.z / f64::consts::PI);
+
+        let distribution = Ggx::from_roughness(parameters.roughness, parameters.roughness);
+        let specular = match reflection(&distribution, wo, wi) {
+            Some((half, value)) => schlick(parameters.specular_f0(), wo.dot(half)) * value,
+            None => Vec3::origin(),
+        };
+
+        let coat_distribution = Ggx::from_roughness(
+            parameters.clearcoat_roughness,
+            parameters.clearcoat_roughness,
+        );
+        let clearcoat = match reflection(&coat_distribution, wo, wi) {
+            Some((half, value)) => schlick(Vec3::new(0.04, 0.04, 0.04), wo.dot(half)) * value,
+            None => Vec3::origin(),
+        };
+
+        Some(diffuse + specular * lobes[1].1 + clearcoat * lobes[2].1)
+    }
+
+    fn pdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        let parameters = self.parameters(hit_record);
+        let normal = hit_record.facing_normal(r_in);
+        let frame = Onb::from_w(normal);
+        let wo = frame.to_local(r_in.direction.make_unit_vec() * -1.0);
+        let wi = frame.to_local(direction);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        let distribution = Ggx::from_roughness(parameters.roughness, parameters.roughness);
+        let coat_distribution = Ggx::from_roughness(
+            parameters.clearcoat_roughness,
+            parameters.clearcoat_roughness,
+        );
+
+        let lobes = Principled::lobes(&parameters, wo.z);
+        lobes[0].2 * wi.z / f64::consts::PI
+            + lobes[1].2 * reflection_pdf(&distribution, wo, wi)
+            + lobes[2].2 * reflection_pdf(&coat_distribution, wo, wi)
+    }
+
+    fn scattering(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Scattering {
+        let parameters = self.parameters(hit_record);
+        let normal = hit_record.facing_normal(r_in);
+        let frame = Onb::from_w(normal);
+        let wo = frame.to_local(r_in.direction.make_unit_vec() * -1.0);
+        let wi = frame.to_local(direction);
+
+        // Light coming through the surface can only come from the transmission lobe
+        let glossy = if wo.z <= 0.0 || wi.z <= 0.0 {
+            parameters.roughness > 0.0
+        } else {
+            let distribution = Ggx::from_roughness(parameters.roughness, parameters.roughness);
+            let coat_distribution = Ggx::from_roughness(
+                parameters.clearcoat_roughness,
+                parameters.clearcoat_roughness,
+            );
+
+            let lobes = Principled::lobes(&parameters, wo.z);
+            let diffuse = lobes[0].2 * wi.z / f64::consts::PI;
+            let reflection = lobes[1].2 * reflection_pdf(&distribution, wo, wi)
+                + lobes[2].2 * reflection_pdf(&coat_distribution, wo, wi);
+            if diffuse >= reflection {
+                return Scattering::Diffuse;
+            }
+            true
+        };
+
+        if glossy {
+            Scattering::Glossy
+        } else {
+            Scattering::Specular
+        }
+    }
+
+    fn albedo(&self, _r_in: &Ray, hit_record: &HitRecord) -> Vec3 {
+        self.parameters(hit_record).base_color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::materials::dielectric::Dielectric;
+    use crate::materials::lambertian::Lambertian;
+    use crate::materials::metal::Metal;
+    use crate::materials::principled::{scalar, Principled};
+    use crate::materials::testing::{albedo, hit, incoming};
+    use crate::materials::Material;
+    use crate::textures::constant::ConstantTexture;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn white_furnace() {
+        let white = || Box::new(ConstantTexture::scalar(1.0));
+        let materials = [
+            Principled::new(white()),
+            Principled::new(white()).with_roughness(scalar(0.1)),
+            Principled::new(white()).with_specular(scalar(1.0)),
+            Principled::new(white())
+                .with_metallic(scalar(1.0))
+                .with_roughness(scalar(0.1)),
+        ];
+
+        for material in materials.iter() {
+            for &cos_theta in [1.0, 0.5, 0.1].iter() {
+                let albedo = albedo(material, &incoming(cos_theta), 20_000);
+                // Single scattering GGX loses energy at grazing angles, but never creates any
+                assert!(albedo.x < 1.02, "{:?} at {}", albedo, cos_theta);
+                if cos_theta == 1.0 {
+                    assert!(albedo.x > 0.95, "{:?}", albedo);
+                }
+            }
+        }
+
+        let glass = Principled::dielectric(1.5);
+        let albedo = albedo(&glass, &incoming(0.7), 20_000);
+        assert!((albedo.x - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn lambertian_preset_matches_lambertian() {
+        let color = Vec3::new(0.8, 0.4, 0.2);
+        let principled = Principled::lambertian(color);
+        let lambertian = Lambertian::new(color);
+        let r_in = incoming(0.6);
+
+        for direction in [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.6, 0.0, 0.8),
+            Vec3::new(-0.3, 0.5, 0.2).make_unit_vec(),
+        ]
+        .iter()
+        {
+            let expected = lambertian
+                .bsdf(&r_in, &hit(&lambertian), *direction)
+                .expect("has a BSDF");
+            let value = principled
+                .bsdf(&r_in, &hit(&principled), *direction)
+                .expect("has a BSDF");
+            assert!((value - expected).length() < 1e-9);
+
+            let expected = lambertian.pdf(&r_in, &hit(&lambertian), *direction);
+            let pdf = principled.pdf(&r_in, &hit(&principled), *direction);
+            assert!((pdf - expected).abs() < 1e-9);
+        }
+
+        let albedo = albedo(&principled, &r_in, 1_000);
+        assert!((albedo - color).length() < 1e-9);
+    }
+
+    #[test]
+    fn metal_preset_matches_metal() {
+        let color = Vec3::new(0.9, 0.6, 0.3);
+        let principled = Principled::metal(color, 0.0);
+        let metal = Metal::new(color, 0.0);
+        let r_in = incoming(1.0);
+
+        let (expected, mirrored) = metal.scatter(&r_in, &hit(&metal)).expect("reflects");
+        let mirrored = mirrored.direction.make_unit_vec();
+        let mut near_mirror = 0;
+        for _ in 0..100 {
+            let (attenuation, scattered) = principled
+                .scatter(&r_in, &hit(&principled))
+                .expect("reflects");
+            assert!((attenuation - expected).length() < 1e-6);
+            if (scattered.direction.make_unit_vec() - mirrored).length() < 1e-2 {
+                near_mirror += 1;
+            }
+        }
+        // Smooth GGX keeps a tiny minimal roughness whose long tail
+        // occasionally strays from the mirror direction
+        assert!(near_mirror >= 95);
+    }
+
+    #[test]
+    fn dielectric_preset_matches_dielectric() {
+        let principled = Principled::dielectric(1.5);
+        let dielectric = Dielectric::new(1.5);
+        let r_in = incoming(0.3);
+
+        let reflected = |material: &dyn Material| {
+            let samples = 20_000;
+            let hit = hit(material);
+            let count = (0..samples)
+                .filter_map(|_| material.scatter(&r_in, &hit))
+                .filter(|(_, scattered)| scattered.direction.z > 0.0)
+                .count();
+            count as f64 / samples as f64
+        };
+
+        // Schlick's approximation and the exact Fresnel term agree within a percent here
+        assert!((reflected(&principled) - reflected(&dielectric)).abs() < 0.02);
+    }
+}
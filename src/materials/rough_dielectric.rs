@@ -0,0 +1,198 @@
+use crate::hitable::HitRecord;
+use crate::materials::microfacet::{
+    fresnel_dielectric, reflection, reflection_pdf, sample_rough_dielectric, transmission,
+    transmission_pdf, Ggx,
+};
+use crate::materials::{Material, Scattering};
+use crate::onb::Onb;
+use crate::ray::Ray;
+use crate::textures::Texture;
+use crate::vec3::Vec3;
+
+/// Frosted or etched glass, reflecting and refracting through GGX microfacets (Walter et al. 2007).
+///
+/// Roughness is read from the first channel of the texture, so it can vary across the surface.
+pub struct RoughDielectric {
+    pub refraction_idx: f64,
+    roughness: Box<dyn Texture>,
+}
+
+impl RoughDielectric {
+    pub fn new(refraction_idx: f64, roughness: Box<dyn Texture>) -> RoughDielectric {
+        RoughDielectric {
+            refraction_idx,
+            roughness,
+        }
+    }
+}
+
+impl RoughDielectric {
+    fn roughness(&self, hit_record: &HitRecord) -> f64 {
+        self.roughness
+            .value(hit_record.u, hit_record.v, hit_record.position)
+            .x
+    }
+
+    /// Shading frame on the side of the surface the ray arrives from, the direction towards the
+    /// ray in it and the relative index of refraction of the other side.
+    fn frame(&self, r_in: &Ray, hit_record: &HitRecord) -> (Onb, Vec3, f64) {
+        let entering = r_in.direction.dot(hit_record.normal) < 0.0;
+        let (normal, eta) = if entering {
+            (hit_record.normal, self.refraction_idx)
+        } else {
+            (hit_record.normal * -1.0, 1.0 / self.refraction_idx)
+        };
+        let frame = Onb::from_w(normal);
+        let wo = frame.to_local(r_in.direction.make_unit_vec() * -1.0);
+        (frame, wo, eta)
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        let (frame, wo, eta) = self.frame(r_in, hit_record);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let roughness = self.roughness(hit_record);
+        let distribution = Ggx::from_roughness(roughness, roughness);
+
+        let (wi, weight) = sample_rough_dielectric(&distribution, wo, eta)?;
+
+        Some((
+            Vec3::new(weight, weight, weight),
+            Ray::new(hit_record.position, frame.local(wi)),
+        ))
+    }
+
+    /// Reflection and refraction, each weighted by the chance of the Fresnel term picking it.
+    fn bsdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Option<Vec3> {
+        let roughness = self.roughness(hit_record);
+        if roughness == 0.0 {
+            return None;
+        }
+        let distribution = Ggx::from_roughness(roughness, roughness);
+        let (frame, wo, eta) = self.frame(r_in, hit_record);
+        let wi = frame.to_local(direction);
+
+        let value = if wi.z > 0.0 {
+            reflection(&distribution, wo, wi)
+                .map(|(half, value)| fresnel_dielectric(wo.dot(half), eta) * value)
+        } else {
+            transmission(&distribution, wo, wi, eta)
+                .map(|(half, value)| (1.0 - fresnel_dielectric(wo.dot(half), eta)) * value)
+        };
+        let value = value.unwrap_or(0.0);
+        Some(Vec3::new(value, value, value))
+    }
+
+    fn pdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        let roughness = self.roughness(hit_record);
+        let distribution = Ggx::from_roughness(roughness, roughness);
+        let (frame, wo, eta) = self.frame(r_in, hit_record);
+        let wi = frame.to_local(direction);
+
+        if wi.z > 0.0 {
+            let half = (wo + wi).make_unit_vec();
+            fresnel_dielectric(wo.dot(half), eta) * reflection_pdf(&distribution, wo, wi)
+        } else {
+            match transmission(&distribution, wo, wi, eta) {
+                Some((half, _)) => {
+                    (1.0 - fresnel_dielectric(wo.dot(half), eta))
+                        * transmission_pdf(&distribution, wo, wi, eta)
+                }
+                None => 0.0,
+            }
+        }
+    }
+
+    fn scattering(&self, _r_in: &Ray, hit_record: &HitRecord, _direction: Vec3) -> Scattering {
+        if self.roughness(hit_record) > 0.0 {
+            Scattering::Glossy
+        } else {
+            Scattering::Specular
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::materials::rough_dielectric::RoughDielectric;
+    use crate::materials::testing::{albedo, hit, incoming};
+    use crate::materials::Material;
+    use crate::sampler;
+    use crate::textures::constant::ConstantTexture;
+    use crate::vec3::Vec3;
+    use std::f64;
+
+    fn glass(roughness: f64) -> RoughDielectric {
+        RoughDielectric::new(1.5, Box::new(ConstantTexture::scalar(roughness)))
+    }
+
+    #[test]
+    fn scatter_weight_is_bsdf_over_pdf() {
+        let glass = glass(0.4);
+        let hit = hit(&glass);
+
+        // From outside and from inside the glass
+        for &cos_theta in [0.8, 0.2, -0.6].iter() {
+            let r_in = incoming(cos_theta);
+            for _ in 0..1_000 {
+                let (weight, scattered) = match glass.scatter(&r_in, &hit) {
+                    Some(scattered) => scattered,
+                    None => continue,
+                };
+                let direction = scattered.direction.make_unit_vec();
+                let bsdf = glass.bsdf(&r_in, &hit, direction).expect("has a BSDF");
+                let pdf = glass.pdf(&r_in, &hit, direction);
+                assert!(pdf > 0.0, "{:?} at {}", direction, cos_theta);
+                assert!(
+                    (weight - bsdf / pdf).length() < 1e-6,
+                    "{:?} {:?} at {}",
+                    weight,
+                    bsdf / pdf,
+                    cos_theta
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn white_furnace() {
+        for &cos_theta in [1.0, 0.5, -0.7].iter() {
+            let r_in = incoming(cos_theta);
+
+            // Single scattering GGX loses energy at grazing angles, but never creates any
+            let smooth = albedo(&glass(0.1), &r_in, 20_000);
+            assert!(smooth.x <= 1.0 && smooth.x > 0.95, "{:?}", smooth);
+
+            // The BSDF integrates to the average scatter weight over the whole sphere
+            let rough = glass(0.5);
+            let hit = hit(&rough);
+            // Stratified over the sphere, uniform samples miss the peaks too often. The strata
+            // pole lies along the surface, normal incidence peaks would fall in its thin slivers
+            let strata = 400;
+            let mut integral = 0.0;
+            for i in 0..strata {
+                for j in 0..strata {
+                    let x = 1.0 - 2.0 * (i as f64 + sampler::random()) / strata as f64;
+                    let r = (1.0 - x * x).sqrt();
+                    let phi =
+                        2.0 * f64::consts::PI * (j as f64 + sampler::random()) / strata as f64;
+                    let direction = Vec3::new(x, r * phi.cos(), r * phi.sin());
+                    let bsdf = rough.bsdf(&r_in, &hit, direction).expect("has a BSDF");
+                    integral += bsdf.x * 4.0 * f64::consts::PI / (strata * strata) as f64;
+                }
+            }
+            let expected = albedo(&rough, &r_in, 20_000).x;
+            assert!(
+                (integral - expected).abs() < 0.03,
+                "{} {} at {}",
+                integral,
+                expected,
+                cos_theta
+            );
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use crate::hitable::HitRecord;
+use crate::materials::lambertian::Lambertian;
+use crate::materials::{Compositing, Material};
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Ground of the photograph a render is composited onto. The camera only sees the shadows and
+/// the reflected light the rendered objects cast onto it, as alpha and colour over a transparent
+/// background, while other surfaces see a diffuse surface of `albedo` like the real ground.
+///
+/// Only `PathTracer` separates the shadows, other integrators render a diffuse surface.
+#[derive(Debug)]
+pub struct ShadowCatcher {
+    surface: Lambertian,
+}
+
+impl ShadowCatcher {
+    pub fn new(albedo: Vec3) -> ShadowCatcher {
+        ShadowCatcher {
+            surface: Lambertian::new(albedo),
+        }
+    }
+}
+
+impl Material for ShadowCatcher {
+    fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+        self.surface.scatter(r_in, hit_record)
+    }
+
+    fn bsdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> Option<Vec3> {
+        self.surface.bsdf(r_in, hit_record, direction)
+    }
+
+    fn pdf(&self, r_in: &Ray, hit_record: &HitRecord, direction: Vec3) -> f64 {
+        self.surface.pdf(r_in, hit_record, direction)
+    }
+
+    fn compositing(&self) -> Compositing {
+        Compositing::ShadowCatcher
+    }
+}
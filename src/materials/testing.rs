@@ -0,0 +1,39 @@
+//! Fixtures shared by the tests of the materials.
+
+use crate::hitable::HitRecord;
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Hit at the origin of a surface facing +z.
+pub fn hit(material: &dyn Material) -> HitRecord<'_> {
+    HitRecord::new(
+        1.0,
+        Vec3::origin(),
+        Vec3::new(0.0, 0.0, 1.0),
+        0.0,
+        0.0,
+        material,
+    )
+}
+
+/// Ray arriving at the origin with the given cosine to the normal.
+pub fn incoming(cos_theta: f64) -> Ray {
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    Ray::new(
+        Vec3::new(-sin_theta, 0.0, cos_theta),
+        Vec3::new(sin_theta, 0.0, -cos_theta),
+    )
+}
+
+/// Average scatter weight, the fraction of the incoming light that is scattered.
+pub fn albedo(material: &dyn Material, r_in: &Ray, samples: usize) -> Vec3 {
+    let hit = hit(material);
+    let mut sum = Vec3::origin();
+    for _ in 0..samples {
+        if let Some((attenuation, _)) = material.scatter(r_in, &hit) {
+            sum += attenuation;
+        }
+    }
+    sum / samples as f64
+}
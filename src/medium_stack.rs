@@ -0,0 +1,227 @@
+use crate::hitable::HitRecord;
+use crate::materials::dielectric::Dispersion;
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// Medium enclosed by a closed dielectric surface, for nested dielectrics.
+///
+/// Where volumes overlap (ice cubes in a glass of water), the one with the highest `priority`
+/// fills the overlap and surfaces of the others are ignored inside it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Interior {
+    pub priority: u32,
+    pub refraction_idx: f64,
+    /// Beer-Lambert absorption coefficient per unit length, for each channel.
+    pub absorption: Vec3,
+    /// Wavelength dependent index of refraction, used instead of `refraction_idx` for rays
+    /// carrying a wavelength.
+    pub dispersion: Option<Dispersion>,
+}
+
+impl Interior {
+    fn refraction_idx_at(&self, wavelength: Option<f64>) -> f64 {
+        match (self.dispersion, wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.refraction_idx(wavelength),
+            _ => self.refraction_idx,
+        }
+    }
+}
+
+/// Media a path is currently inside of, from the outermost to the most recently entered.
+/// Paths start in vacuum (index of refraction 1, no absorption).
+#[derive(Debug, Clone, Default)]
+pub struct MediumStack {
+    entries: Vec<(usize, Interior)>,
+}
+
+/// Identifies the object a material belongs to, every object owns its own material.
+fn material_id(material: &dyn Material) -> usize {
+    material as *const dyn Material as *const () as usize
+}
+
+impl MediumStack {
+    pub fn new() -> MediumStack {
+        MediumStack::default()
+    }
+
+    fn current_entry(&self) -> Option<&(usize, Interior)> {
+        self.entries
+            .iter()
+            .max_by(|(_, a), (_, b)| a.priority.cmp(&b.priority))
+    }
+
+    /// The medium filling the current position, highest priority wins and ties go to the latest entered.
+    pub fn current(&self) -> Option<&Interior> {
+        self.current_entry().map(|(_, interior)| interior)
+    }
+
+    fn refraction_idx(&self, wavelength: Option<f64>) -> f64 {
+        self.current()
+            .map_or(1.0, |interior| interior.refraction_idx_at(wavelength))
+    }
+
+    /// Fraction of light surviving a path of the given length through the current medium.
+    pub fn transmittance(&self, distance: f64) -> Vec3 {
+        match self.current() {
+            Some(interior) => Vec3::new(
+                (-interior.absorption.x * distance).exp(),
+                (-interior.absorption.y * distance).exp(),
+                (-interior.absorption.z * distance).exp(),
+            ),
+            None => Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    fn entered(&self, id: usize, interior: Interior) -> MediumStack {
+        let mut media = self.clone();
+        media.entries.push((id, interior));
+        media
+    }
+
+    fn exited(&self, id: usize) -> MediumStack {
+        let mut media = self.clone();
+        if let Some(index) = media.entries.iter().rposition(|(entry, _)| *entry == id) {
+            media.entries.remove(index);
+        }
+        media
+    }
+
+    /// Scatters off the hit surface, returning the media the scattered ray travels in.
+    ///
+    /// Surfaces bounding an `Interior` refract between the media on both sides of them,
+    /// or are passed through unchanged when a higher priority medium overlaps them.
+    pub fn scatter(&self, r_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray, MediumStack)> {
+        let interior = match hit_record.material.interior() {
+            Some(interior) => interior,
+            None => {
+                let (attenuation, scattered) = hit_record.material.scatter(r_in, hit_record)?;
+                return Some((attenuation, scattered, self.clone()));
+            }
+        };
+
+        let id = material_id(hit_record.material);
+        let entering = r_in.direction.dot(hit_record.normal) < 0.0;
+        let is_inside = self.entries.iter().any(|(entry, _)| *entry == id);
+
+        // Media on both sides of the surface. Leaving an object we never entered
+        // (eg. the camera starts inside it) is treated as if we had.
+        let inside = if is_inside {
+            self.clone()
+        } else {
+            self.entered(id, interior)
+        };
+        let outside = if is_inside {
+            self.exited(id)
+        } else {
+            self.clone()
+        };
+
+        // The surface is only real if it bounds the medium that wins on its inner side.
+        let false_hit = inside.current_entry().map(|(entry, _)| *entry) != Some(id);
+
+        if false_hit {
+            let through = Ray::new(hit_record.position, r_in.direction)
+                .with_wavelength(r_in.wavelength)
+                .with_kind(r_in.kind);
+            let media = if entering { inside } else { outside };
+            return Some((Vec3::new(1.0, 1.0, 1.0), through, media));
+        }
+
+        let (attenuation, scattered) = hit_record.material.scatter_between(
+            r_in,
+            hit_record,
+            outside.refraction_idx(r_in.wavelength),
+            inside.refraction_idx(r_in.wavelength),
+        )?;
+
+        // Reflected rays stay where they came from
+        let crossed = scattered.direction.dot(hit_record.normal) < 0.0;
+        let media = if crossed { inside } else { outside };
+        Some((attenuation, scattered, media))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hitable::HitRecord;
+    use crate::materials::dielectric::{Dielectric, Dispersion};
+    use crate::medium_stack::MediumStack;
+    use crate::ray::Ray;
+    use crate::vec3::Vec3;
+
+    fn hit_plane(material: &Dielectric) -> HitRecord<'_> {
+        HitRecord::new(
+            1.0,
+            Vec3::origin(),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            0.0,
+            material,
+        )
+    }
+
+    #[test]
+    fn lower_priority_surfaces_are_skipped() {
+        let glass = Dielectric::new(1.5).with_priority(2);
+        let water = Dielectric::new(1.33)
+            .with_priority(1)
+            .with_absorption(Vec3::new(0.5, 0.1, 0.0));
+        let down = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+
+        // Enter the glass straight on, which always refracts at normal incidence or reflects back
+        let (_, scattered, in_glass) = loop {
+            let (attenuation, scattered, media) = MediumStack::new()
+                .scatter(&down, &hit_plane(&glass))
+                .expect("dielectrics always scatter");
+            if scattered.direction.y < 0.0 {
+                break (attenuation, scattered, media);
+            }
+        };
+        assert_eq!(in_glass.current().map(|i| i.priority), Some(2));
+
+        // Water surface inside the glass is ignored
+        let (attenuation, through, in_both) = in_glass
+            .scatter(&scattered, &hit_plane(&water))
+            .expect("passes through");
+        assert_eq!(attenuation, Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(through.direction, scattered.direction);
+        assert_eq!(in_both.current().map(|i| i.priority), Some(2));
+        assert_eq!(in_both.transmittance(2.0), Vec3::new(1.0, 1.0, 1.0));
+
+        // Leaving the glass puts us in the water
+        let up_normal_side = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let exits = (0..100)
+            .filter_map(|_| in_both.scatter(&up_normal_side, &hit_plane(&glass)))
+            .find(|(_, scattered, _)| scattered.direction.y > 0.0);
+        let (_, _, in_water) = exits.expect("leaves the glass at normal incidence");
+        assert_eq!(in_water.current().map(|i| i.priority), Some(1));
+        assert_eq!(
+            in_water.transmittance(2.0),
+            Vec3::new((-1.0_f64).exp(), (-0.2_f64).exp(), 1.0)
+        );
+    }
+
+    #[test]
+    fn nested_dielectrics_disperse() {
+        let prism = Dielectric::new(1.5)
+            .with_priority(1)
+            .with_dispersion(Dispersion::diamond());
+        let slanted = Ray::new(Vec3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+
+        let refracted = |wavelength: f64| {
+            let ray = slanted.with_wavelength(Some(wavelength));
+            loop {
+                let (_, scattered, _) = MediumStack::new()
+                    .scatter(&ray, &hit_plane(&prism))
+                    .expect("dielectrics always scatter");
+                if scattered.direction.y < 0.0 {
+                    break scattered.direction.make_unit_vec();
+                }
+            }
+        };
+
+        // Blue bends more towards the normal than red
+        assert!(refracted(450.0).x < refracted(650.0).x - 1e-3);
+    }
+}
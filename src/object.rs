@@ -0,0 +1,162 @@
+use crate::aabb::Aabb;
+use crate::hitable::{HitRecord, Hitable, SurfaceSample};
+use crate::ray::{Ray, RayKind};
+use crate::vec3::Vec3;
+
+/// Which kinds of rays see an object, to hide it from some effects without changing the others:
+/// a light blocker that stays out of the image, a character that casts no shadow.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Visibility {
+    pub camera: bool,
+    pub shadow: bool,
+    /// Glossy and specular reflections and refractions.
+    pub specular: bool,
+    /// Diffuse bounces, the indirect light the object receives and sends to others.
+    pub diffuse: bool,
+}
+
+impl Visibility {
+    pub const ALL: Visibility = Visibility {
+        camera: true,
+        shadow: true,
+        specular: true,
+        diffuse: true,
+    };
+
+    pub fn sees(self, kind: RayKind) -> bool {
+        match kind {
+            RayKind::Camera => self.camera,
+            RayKind::Shadow => self.shadow,
+            RayKind::Specular => self.specular,
+            RayKind::Diffuse => self.diffuse,
+        }
+    }
+}
+
+impl Default for Visibility {
+    fn default() -> Visibility {
+        Visibility::ALL
+    }
+}
+
+/// Object of the scene with the settings shapes don't have themselves: its name, which rays see
+/// it, and which lights illuminate it (light linking).
+///
+/// Integrators only tell rays apart as far as they know why they trace them: the path tracer
+/// marks every ray, others at least camera and shadow rays. Light links restrict the light objects
+/// receive directly from the lights, they're still lit by its reflections off other objects and
+/// emissive surfaces light every object.
+pub struct Object {
+    hitable: Box<dyn Hitable>,
+    name: Option<String>,
+    visibility: Visibility,
+    lights: Option<Vec<usize>>,
+}
+
+impl Object {
+    pub fn new(hitable: Box<dyn Hitable>) -> Object {
+        Object {
+            hitable,
+            name: None,
+            visibility: Visibility::ALL,
+            lights: None,
+        }
+    }
+
+    /// Name selecting the object in ID mattes.
+    pub fn with_name(mut self, name: impl Into<String>) -> Object {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_visibility(mut self, visibility: Visibility) -> Object {
+        self.visibility = visibility;
+        self
+    }
+
+    /// Only lets the lights at these indices in `Scene::lights` illuminate the object.
+    pub fn with_lights(mut self, lights: Vec<usize>) -> Object {
+        self.lights = Some(lights);
+        self
+    }
+}
+
+impl Hitable for Object {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        if !self.visibility.sees(ray.kind) {
+            return None;
+        }
+
+        let mut hit = self.hitable.hit(ray, t_min, t_max)?;
+        // Names and links of nested objects win
+        if hit.object.is_none() {
+            hit.object = self.name.as_deref();
+        }
+        if hit.lights.is_none() {
+            hit.lights = self.lights.as_deref();
+        }
+        Some(hit)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        if !self.visibility.sees(ray.kind) {
+            return 1.0;
+        }
+        self.hitable.transmittance(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.hitable.bounding_box()
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample<'_>> {
+        let mut sample = self.hitable.sample_surface()?;
+        if sample.hit.object.is_none() {
+            sample.hit.object = self.name.as_deref();
+        }
+        if sample.hit.lights.is_none() {
+            sample.hit.lights = self.lights.as_deref();
+        }
+        Some(sample)
+    }
+
+    fn normal_cone(&self) -> (Vec3, f64) {
+        self.hitable.normal_cone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hitable::Hitable;
+    use crate::materials::lambertian::Lambertian;
+    use crate::object::{Object, Visibility};
+    use crate::ray::{Ray, RayKind};
+    use crate::sphere::Sphere;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn hides_from_some_rays_only() {
+        let sphere = Sphere::new(
+            Vec3::origin(),
+            1.0,
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let object = Object::new(Box::new(sphere))
+            .with_visibility(Visibility {
+                camera: false,
+                ..Visibility::ALL
+            })
+            .with_lights(vec![1]);
+
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(object
+            .hit(&ray.with_kind(RayKind::Camera), 0.001, 10.0)
+            .is_none());
+
+        let hit = object
+            .hit(&ray.with_kind(RayKind::Diffuse), 0.001, 10.0)
+            .expect("diffuse rays see the sphere");
+        assert!(hit.lit_by(1));
+        assert!(!hit.lit_by(0));
+    }
+}
@@ -0,0 +1,49 @@
+use crate::sampler;
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Orthonormal basis, used to move directions between world space and a frame around a normal.
+#[derive(Debug, Copy, Clone)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    /// Builds a basis whose `w` axis is the given (not necessarily unit) vector.
+    pub fn from_w(n: Vec3) -> Onb {
+        let w = n.make_unit_vec();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(a).make_unit_vec();
+        let u = w.cross(v);
+
+        Onb { u, v, w }
+    }
+
+    /// Local coordinates to world space.
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        self.u * a.x + self.v * a.y + self.w * a.z
+    }
+
+    /// World space to local coordinates.
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+}
+
+/// Random direction around +z with a density proportional to its cosine with it.
+pub fn random_cosine_direction() -> Vec3 {
+    let r1 = sampler::random();
+    let r2 = sampler::random();
+    let phi = 2.0 * f64::consts::PI * r1;
+    Vec3::new(
+        phi.cos() * r2.sqrt(),
+        phi.sin() * r2.sqrt(),
+        (1.0 - r2).sqrt(),
+    )
+}
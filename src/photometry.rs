@@ -0,0 +1,27 @@
+//! Conversions from the photometric units lighting is specified in to renderer radiance.
+//!
+//! Renderer radiance is scaled so that its luminance (the CIE Y of its color, see
+//! `Spectrum::luminance`) is in candela per square meter (nits), with scene units in meters.
+
+use std::f64;
+
+/// Luminance of a one sided diffuse emitter of the given area emitting `lumens` in total.
+pub fn luminance_from_lumens(lumens: f64, area: f64) -> f64 {
+    lumens / (f64::consts::PI * area)
+}
+
+/// Luminance of an emitter of the given projected area with luminous intensity `candela`.
+pub fn luminance_from_candela(candela: f64, projected_area: f64) -> f64 {
+    candela / projected_area
+}
+
+/// Luminance of a distant source (eg. the sun) covering `solid_angle` steradians,
+/// producing `lux` of illuminance on a surface facing it.
+pub fn luminance_from_lux(lux: f64, solid_angle: f64) -> f64 {
+    lux / solid_angle
+}
+
+/// Luminous intensity of an isotropic point source emitting `lumens` in total.
+pub fn candela_from_lumens(lumens: f64) -> f64 {
+    lumens / (4.0 * f64::consts::PI)
+}
@@ -0,0 +1,145 @@
+use crate::aabb::Aabb;
+use crate::hitable::HitRecord;
+use crate::hitable::{Hitable, SurfaceSample};
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::Vec3;
+
+/// Parallelogram spanned by the edges `u` and `v` from the corner `q`.
+/// The normal follows the right hand rule, `u x v`, on both sides of the quad.
+pub struct Quad {
+    q: Vec3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    d: f64,
+    w: Vec3,
+    material: Box<dyn Material + Send + Sync>,
+}
+
+impl Quad {
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, material: Box<dyn Material + Send + Sync>) -> Quad {
+        let n = u.cross(v);
+        let normal = n.make_unit_vec();
+
+        Quad {
+            q,
+            u,
+            v,
+            normal,
+            d: normal.dot(q),
+            w: n / n.dot(n),
+            material,
+        }
+    }
+}
+
+impl Hitable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let denominator = self.normal.dot(ray.direction);
+
+        // Parallel to the plane
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - self.normal.dot(ray.origin)) / denominator;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        // Express the intersection point in the (u, v) basis of the plane.
+        let p = ray.point_at_parameter(t);
+        let planar = p - self.q;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        Some(HitRecord::new(
+            t,
+            p,
+            self.normal,
+            alpha,
+            beta,
+            self.material.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bbox = Aabb::from_points(&[
+            self.q,
+            self.q + self.u,
+            self.q + self.v,
+            self.q + self.u + self.v,
+        ]);
+
+        Some(bbox.padded(0.0001))
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample<'_>> {
+        let area = self.u.cross(self.v).length();
+        if area <= 0.0 {
+            return None;
+        }
+
+        let (alpha, beta) = (sampler::random(), sampler::random());
+        Some(SurfaceSample {
+            hit: HitRecord::new(
+                0.0,
+                self.q + self.u * alpha + self.v * beta,
+                self.normal,
+                alpha,
+                beta,
+                self.material.as_ref(),
+            ),
+            area,
+        })
+    }
+
+    fn normal_cone(&self) -> (Vec3, f64) {
+        (self.normal, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hitable::Hitable;
+    use crate::materials::lambertian::Lambertian;
+    use crate::quad::Quad;
+    use crate::ray::Ray;
+    use crate::vec3::Vec3;
+
+    /// Parallelogram in the z = 0 plane, sheared along x.
+    fn sheared() -> Quad {
+        Quad::new(
+            Vec3::origin(),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn hits_with_uvs_along_the_edges() {
+        let ray = Ray::new(Vec3::new(1.5, 0.5, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let quad = sheared();
+        let hit = quad
+            .hit(&ray, 0.001, f64::MAX)
+            .expect("ray points at the quad");
+
+        assert_eq!(hit.t, 3.0);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!((hit.u - 0.5).abs() < 1e-12 && (hit.v - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn misses_outside_the_shear() {
+        // Inside the bounding box, but left of the slanted edge
+        let ray = Ray::new(Vec3::new(0.2, 0.8, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(sheared().hit(&ray, 0.001, f64::MAX).is_none());
+    }
+}
@@ -1,14 +1,45 @@
 use crate::vec3::Vec3;
 
-#[derive(Debug)]
+/// What a ray is traced for, deciding which objects it sees (see `object::Visibility`).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RayKind {
+    /// Leaves the camera.
+    Camera,
+    /// Tests whether a light is occluded.
+    Shadow,
+    /// Follows a glossy or specular reflection or refraction.
+    Specular,
+    /// Follows a diffuse bounce, and any ray not marked otherwise.
+    Diffuse,
+}
+
+#[derive(Debug, Copy, Clone)]
 pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
+    /// Wavelength in nanometers, when rendering spectrally.
+    pub wavelength: Option<f64>,
+    pub kind: RayKind,
 }
 
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            wavelength: None,
+            kind: RayKind::Diffuse,
+        }
+    }
+
+    pub fn with_wavelength(mut self, wavelength: Option<f64>) -> Ray {
+        self.wavelength = wavelength;
+        self
+    }
+
+    pub fn with_kind(mut self, kind: RayKind) -> Ray {
+        self.kind = kind;
+        self
     }
 
     pub fn point_at_parameter(&self, t: f64) -> Vec3 {
@@ -0,0 +1,215 @@
+use crate::aabb::Aabb;
+use crate::hitable::HitRecord;
+use crate::hitable::{Hitable, SurfaceSample};
+use crate::materials::Material;
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::Vec3;
+
+/// The plane a rectangle lies in, the normal points along the remaining positive axis on both
+/// sides of the rectangle.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Plane {
+    XY,
+    XZ,
+    YZ,
+}
+
+impl Plane {
+    /// Splits a vector into (a, b, k) where `a` and `b` span the plane and `k` is along the normal.
+    fn split(self, v: Vec3) -> (f64, f64, f64) {
+        match self {
+            Plane::XY => (v.x, v.y, v.z),
+            Plane::XZ => (v.x, v.z, v.y),
+            Plane::YZ => (v.y, v.z, v.x),
+        }
+    }
+
+    fn join(self, a: f64, b: f64, k: f64) -> Vec3 {
+        match self {
+            Plane::XY => Vec3::new(a, b, k),
+            Plane::XZ => Vec3::new(a, k, b),
+            Plane::YZ => Vec3::new(k, a, b),
+        }
+    }
+}
+
+pub struct AxisAlignedRect {
+    plane: Plane,
+    a0: f64,
+    a1: f64,
+    b0: f64,
+    b1: f64,
+    k: f64,
+    material: Box<dyn Material + Send + Sync>,
+}
+
+impl AxisAlignedRect {
+    pub fn new(
+        plane: Plane,
+        (a0, a1): (f64, f64),
+        (b0, b1): (f64, f64),
+        k: f64,
+        material: Box<dyn Material + Send + Sync>,
+    ) -> AxisAlignedRect {
+        AxisAlignedRect {
+            plane,
+            a0: a0.min(a1),
+            a1: a0.max(a1),
+            b0: b0.min(b1),
+            b1: b0.max(b1),
+            k,
+            material,
+        }
+    }
+
+    /// Rectangle spanning [x0, x1] x [y0, y1] at `z = k`.
+    pub fn xy(
+        x: (f64, f64),
+        y: (f64, f64),
+        k: f64,
+        material: Box<dyn Material + Send + Sync>,
+    ) -> AxisAlignedRect {
+        AxisAlignedRect::new(Plane::XY, x, y, k, material)
+    }
+
+    /// Rectangle spanning [x0, x1] x [z0, z1] at `y = k`.
+    pub fn xz(
+        x: (f64, f64),
+        z: (f64, f64),
+        k: f64,
+        material: Box<dyn Material + Send + Sync>,
+    ) -> AxisAlignedRect {
+        AxisAlignedRect::new(Plane::XZ, x, z, k, material)
+    }
+
+    /// Rectangle spanning [y0, y1] x [z0, z1] at `x = k`.
+    pub fn yz(
+        y: (f64, f64),
+        z: (f64, f64),
+        k: f64,
+        material: Box<dyn Material + Send + Sync>,
+    ) -> AxisAlignedRect {
+        AxisAlignedRect::new(Plane::YZ, y, z, k, material)
+    }
+}
+
+impl Hitable for AxisAlignedRect {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (origin_a, origin_b, origin_k) = self.plane.split(ray.origin);
+        let (direction_a, direction_b, direction_k) = self.plane.split(ray.direction);
+
+        // Parallel to the plane
+        if direction_k == 0.0 {
+            return None;
+        }
+
+        let t = (self.k - origin_k) / direction_k;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let a = origin_a + t * direction_a;
+        let b = origin_b + t * direction_b;
+        if a < self.a0 || a > self.a1 || b < self.b0 || b > self.b1 {
+            return None;
+        }
+
+        // Degenerate rectangles have a zero extent, they map to 0 along it
+        let ratio = |offset: f64, extent: f64| if extent > 0.0 { offset / extent } else { 0.0 };
+        Some(HitRecord::new(
+            t,
+            ray.point_at_parameter(t),
+            self.plane.join(0.0, 0.0, 1.0),
+            ratio(a - self.a0, self.a1 - self.a0),
+            ratio(b - self.b0, self.b1 - self.b0),
+            self.material.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(
+            Aabb::new(
+                self.plane.join(self.a0, self.b0, self.k),
+                self.plane.join(self.a1, self.b1, self.k),
+            )
+            .padded(0.0001),
+        )
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample<'_>> {
+        let area = (self.a1 - self.a0) * (self.b1 - self.b0);
+        if area <= 0.0 {
+            return None;
+        }
+
+        let (u, v) = (sampler::random(), sampler::random());
+        let a = self.a0 + u * (self.a1 - self.a0);
+        let b = self.b0 + v * (self.b1 - self.b0);
+        Some(SurfaceSample {
+            hit: HitRecord::new(
+                0.0,
+                self.plane.join(a, b, self.k),
+                self.plane.join(0.0, 0.0, 1.0),
+                u,
+                v,
+                self.material.as_ref(),
+            ),
+            area,
+        })
+    }
+
+    fn normal_cone(&self) -> (Vec3, f64) {
+        (self.plane.join(0.0, 0.0, 1.0), 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hitable::Hitable;
+    use crate::materials::lambertian::Lambertian;
+    use crate::ray::Ray;
+    use crate::rect::AxisAlignedRect;
+    use crate::vec3::Vec3;
+
+    fn floor(x: (f64, f64)) -> AxisAlignedRect {
+        AxisAlignedRect::xz(
+            x,
+            (2.0, -2.0),
+            1.0,
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn hits_inside_with_uvs_across_the_rect() {
+        let ray = Ray::new(Vec3::new(0.5, 3.0, 1.0), Vec3::new(0.0, -1.0, 0.0));
+        let rect = floor((-1.0, 1.0));
+        let hit = rect
+            .hit(&ray, 0.001, f64::MAX)
+            .expect("ray points at the rect");
+
+        assert_eq!(hit.t, 2.0);
+        assert_eq!(hit.normal, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!((hit.u, hit.v), (0.75, 0.75));
+    }
+
+    #[test]
+    fn misses_outside_and_parallel() {
+        let rect = floor((-1.0, 1.0));
+        let beside = Ray::new(Vec3::new(1.5, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let parallel = Ray::new(Vec3::new(0.0, 1.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+
+        assert!(rect.hit(&beside, 0.001, f64::MAX).is_none());
+        assert!(rect.hit(&parallel, 0.001, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn degenerate_rect_has_finite_uvs() {
+        let ray = Ray::new(Vec3::new(0.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let rect = floor((0.0, 0.0));
+        let hit = rect.hit(&ray, 0.001, f64::MAX).expect("ray hits the line");
+
+        assert_eq!((hit.u, hit.v), (0.0, 0.5));
+    }
+}
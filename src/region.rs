@@ -0,0 +1,103 @@
+use std::str::FromStr;
+
+/// Rectangle of pixels of an image, from `x0`, `y0` included to `x1`, `y1` excluded, counting
+/// rows from the top like image files do.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Region {
+    pub fn new(x0: usize, y0: usize, x1: usize, y1: usize) -> Region {
+        Region { x0, y0, x1, y1 }
+    }
+
+    /// The whole `width` by `height` image.
+    pub fn full(width: usize, height: usize) -> Region {
+        Region::new(0, 0, width, height)
+    }
+
+    pub fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+
+    pub fn area(&self) -> usize {
+        self.width() * self.height()
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x0..self.x1).contains(&x) && (self.y0..self.y1).contains(&y)
+    }
+
+    /// Whether the region is a non-empty part of `other`.
+    pub fn is_within(&self, other: &Region) -> bool {
+        self.x0 < self.x1
+            && self.y0 < self.y1
+            && other.x0 <= self.x0
+            && other.y0 <= self.y0
+            && self.x1 <= other.x1
+            && self.y1 <= other.y1
+    }
+
+    /// Squares of `size` pixels covering the region row by row, smaller along the right and
+    /// bottom edges.
+    pub fn tiles(&self, size: usize) -> Vec<Region> {
+        let mut tiles = Vec::new();
+        for y0 in (self.y0..self.y1).step_by(size) {
+            for x0 in (self.x0..self.x1).step_by(size) {
+                tiles.push(Region::new(
+                    x0,
+                    y0,
+                    (x0 + size).min(self.x1),
+                    (y0 + size).min(self.y1),
+                ));
+            }
+        }
+        tiles
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    /// Parses `x0,y0,x1,y1`.
+    fn from_str(region: &str) -> Result<Region, String> {
+        let bounds = region
+            .split(',')
+            .map(|bound| bound.trim().parse())
+            .collect::<Result<Vec<usize>, _>>()
+            .map_err(|e| format!("invalid region `{}`: {}", region, e))?;
+        match bounds[..] {
+            [x0, y0, x1, y1] if x0 < x1 && y0 < y1 => Ok(Region::new(x0, y0, x1, y1)),
+            [_, _, _, _] => Err(format!("region `{}` is empty", region)),
+            _ => Err(format!("expected `x0,y0,x1,y1`, got `{}`", region)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::region::Region;
+
+    #[test]
+    fn tiles_cover_region_once() {
+        let region: Region = "10, 5, 35, 25".parse().unwrap();
+        let tiles = region.tiles(10);
+
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles[2], Region::new(30, 5, 35, 15));
+        assert_eq!(tiles.iter().map(Region::area).sum::<usize>(), region.area());
+        assert!(tiles.iter().all(|tile| tile.is_within(&region)));
+        assert!(!region.is_within(&Region::full(30, 30)));
+
+        assert!("1,2,3".parse::<Region>().is_err());
+        assert!("4,0,4,1".parse::<Region>().is_err());
+    }
+}
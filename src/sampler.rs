@@ -0,0 +1,43 @@
+//! Source of the random numbers used while rendering.
+//!
+//! Everything that samples draws from `random`, which uses the sampler installed on the current
+//! thread, or the thread's random number generator when there is none. Integrators that need
+//! control over the numbers a path is built from, like Metropolis light transport, install their
+//! own with `with_sampler`.
+
+use std::any::Any;
+use std::cell::RefCell;
+
+pub trait Sampler: Any {
+    /// Next number in [0, 1).
+    fn next(&mut self) -> f64;
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Box<dyn Sampler>>> = RefCell::new(None);
+}
+
+/// Uniform random number in [0, 1).
+pub fn random() -> f64 {
+    CURRENT.with(|current| match current.borrow_mut().as_mut() {
+        Some(sampler) => sampler.next(),
+        None => rand::random(),
+    })
+}
+
+/// Runs `f` drawing its random numbers from `sampler`, and hands the sampler back.
+pub fn with_sampler<S: Sampler, R>(sampler: S, f: impl FnOnce() -> R) -> (S, R) {
+    let previous = CURRENT.with(|current| current.replace(Some(Box::new(sampler))));
+    let result = f();
+    let sampler = CURRENT
+        .with(|current| current.replace(previous))
+        .expect("the sampler is only taken out here");
+
+    let sampler = sampler
+        .into_any()
+        .downcast::<S>()
+        .expect("the same sampler is handed back");
+    (*sampler, result)
+}
@@ -0,0 +1,185 @@
+use crate::camera::Camera;
+use crate::hitable::{HitRecord, Hitable, HitableList};
+use crate::lights::bvh::LightBvh;
+use crate::lights::{Light, LightBounds};
+use crate::ray::Ray;
+use crate::sampler;
+use crate::vec3::Vec3;
+use std::f64;
+
+/// Object of the world emitting light from a surface that can be sampled.
+#[derive(Debug, Copy, Clone)]
+struct SurfaceEmitter {
+    /// Index of the object in `world`.
+    index: usize,
+    area: f64,
+    bounds: LightBounds,
+}
+
+/// Where a path leaving the lights starts, see `Scene::sample_emitter`.
+pub enum Emitter<'a> {
+    /// A light with its index in the lights of the scene.
+    Light(usize, &'a dyn Light),
+    /// An emissive object of the world with its index, its surface can be sampled.
+    Surface(usize, &'a dyn Hitable),
+}
+
+/// Everything the integrators need to render: the geometry, and the lights that can't be hit by
+/// rays and are sampled directly instead.
+pub struct Scene {
+    pub camera: Camera,
+    pub world: HitableList,
+    lights: Vec<Box<dyn Light>>,
+    /// Hierarchy over the lights followed by the surface emitters.
+    light_bvh: LightBvh,
+    surface_emitters: Vec<SurfaceEmitter>,
+}
+
+/// Objects of `world` whose surface can be sampled and emits light towards its normal, with the
+/// power they emit estimated from a single point.
+fn surface_emitters(world: &HitableList) -> Vec<SurfaceEmitter> {
+    world
+        .iter()
+        .enumerate()
+        .filter_map(|(index, object)| {
+            let sample = object.sample_surface()?;
+            let hit = &sample.hit;
+            let towards = Ray::new(hit.position + hit.normal, hit.normal * -1.0);
+            let radiance = hit.material.emitted(&towards, hit).luminance();
+            if radiance <= 0.0 {
+                return None;
+            }
+
+            // Emission falls off with the cosine to the normal
+            let (axis, theta_o) = object.normal_cone();
+            Some(SurfaceEmitter {
+                index,
+                area: sample.area,
+                bounds: LightBounds {
+                    bounds: object.bounding_box()?,
+                    axis,
+                    theta_o,
+                    theta_e: f64::consts::FRAC_PI_2,
+                    power: f64::consts::PI * sample.area * radiance,
+                },
+            })
+        })
+        .collect()
+}
+
+impl Scene {
+    pub fn new(world: HitableList, lights: Vec<Box<dyn Light>>) -> Scene {
+        let surface_emitters = surface_emitters(&world);
+        let light_bvh = LightBvh::from_bounds(
+            lights
+                .iter()
+                .map(|light| light.bounds())
+                .chain(surface_emitters.iter().map(|emitter| Some(emitter.bounds))),
+        );
+        Scene {
+            camera: Camera::default(),
+            world,
+            lights,
+            light_bvh,
+            surface_emitters,
+        }
+    }
+
+    pub fn with_camera(mut self, camera: Camera) -> Scene {
+        self.camera = camera;
+        self
+    }
+
+    /// Closest hit of `ray` within `t_min` and `t_max`, with the index of the object in `world`
+    /// it belongs to.
+    pub fn hit_object(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(usize, HitRecord<'_>)> {
+        let mut closest = t_max;
+        let mut found = None;
+        for (index, object) in self.world.iter().enumerate() {
+            if let Some(hit) = object.hit(ray, t_min, closest) {
+                closest = hit.t;
+                found = Some((index, hit));
+            }
+        }
+        found
+    }
+
+    pub fn lights(&self) -> &[Box<dyn Light>] {
+        &self.lights
+    }
+
+    /// Lights and emissive surfaces to sample for the direct lighting at `p`, with the
+    /// probability they were picked: all infinite lights, and one of the others chosen by its
+    /// estimated contribution.
+    pub fn sample_lights(&self, p: Vec3) -> impl Iterator<Item = (Emitter<'_>, f64)> + '_ {
+        let infinite = self
+            .light_bvh
+            .infinite()
+            .iter()
+            .map(move |&index| (self.emitter(index), 1.0));
+        let bounded = self
+            .light_bvh
+            .sample(p, sampler::random())
+            .map(|(index, probability)| (self.emitter(index), probability));
+
+        infinite.chain(bounded)
+    }
+
+    /// Lights infinitely far away, they can't start paths.
+    pub fn infinite_lights(&self) -> impl Iterator<Item = &dyn Light> + '_ {
+        self.light_bvh
+            .infinite()
+            .iter()
+            .map(move |&index| self.lights[index].as_ref())
+    }
+
+    /// Light or surface emitter at `index` in the light hierarchy.
+    fn emitter(&self, index: usize) -> Emitter<'_> {
+        match self.lights.get(index) {
+            Some(light) => Emitter::Light(index, light.as_ref()),
+            None => {
+                let object = self.surface_emitters[index - self.lights.len()].index;
+                Emitter::Surface(object, self.world[object].as_ref())
+            }
+        }
+    }
+
+    /// One of the lights or emissive surfaces that can start paths, picked proportionally to its
+    /// power, with the probability it was picked with.
+    pub fn sample_emitter(&self, u: f64) -> Option<(Emitter<'_>, f64)> {
+        self.light_bvh
+            .sample_power(u)
+            .map(|(index, probability)| (self.emitter(index), probability))
+    }
+
+    /// Index in `surface_emitters` of the object at `index` in `world`.
+    fn surface_emitter(&self, index: usize) -> Option<usize> {
+        self.surface_emitters
+            .binary_search_by_key(&index, |emitter| emitter.index)
+            .ok()
+    }
+
+    /// Area density of `sample_emitter` and `Hitable::sample_surface` picking a point on
+    /// `world[index]`, zero for objects that don't start paths.
+    pub fn surface_emitter_pdf(&self, index: usize) -> f64 {
+        match self.surface_emitter(index) {
+            Some(found) => {
+                let emitter = &self.surface_emitters[found];
+                emitter.bounds.power / (self.light_bvh.power() * emitter.area)
+            }
+            None => 0.0,
+        }
+    }
+
+    /// Area density of `sample_lights` and `Hitable::sample_surface` picking a point on
+    /// `world[index]` to light `p`, zero for objects that aren't sampled.
+    pub fn surface_light_pdf(&self, p: Vec3, index: usize) -> f64 {
+        match self.surface_emitter(index) {
+            Some(found) => {
+                let emitter = &self.surface_emitters[found];
+                self.light_bvh.pdf(p, self.lights.len() + found) / emitter.area
+            }
+            None => 0.0,
+        }
+    }
+}
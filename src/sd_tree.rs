@@ -0,0 +1,445 @@
+use crate::aabb::Aabb;
+use crate::sampler;
+use crate::vec3::Vec3;
+use std::f64;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Deepest a directional quadtree is refined to.
+const MAX_DIRECTIONAL_DEPTH: usize = 20;
+/// Deepest the spatial tree is split to.
+const MAX_SPATIAL_DEPTH: usize = 48;
+
+/// `f64` that can be accumulated into from several threads.
+#[derive(Debug, Default)]
+struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    fn new(value: f64) -> AtomicF64 {
+        AtomicF64(AtomicU64::new(value.to_bits()))
+    }
+
+    fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn add(&self, value: f64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + value).to_bits())
+            });
+    }
+}
+
+/// Quadtree node, the sums are the energy recorded in each quadrant and their subtrees.
+#[derive(Debug, Default)]
+struct QuadNode {
+    sums: [AtomicF64; 4],
+    /// Index of the node refining each quadrant, 0 for leaves.
+    children: [usize; 4],
+}
+
+impl QuadNode {
+    fn sum(&self, quadrant: usize) -> f64 {
+        self.sums[quadrant].get()
+    }
+
+    fn sums(&self) -> [f64; 4] {
+        [self.sum(0), self.sum(1), self.sum(2), self.sum(3)]
+    }
+
+    fn total(&self) -> f64 {
+        self.sums().iter().sum()
+    }
+}
+
+impl Clone for QuadNode {
+    fn clone(&self) -> QuadNode {
+        QuadNode {
+            sums: [
+                AtomicF64::new(self.sum(0)),
+                AtomicF64::new(self.sum(1)),
+                AtomicF64::new(self.sum(2)),
+                AtomicF64::new(self.sum(3)),
+            ],
+            children: self.children,
+        }
+    }
+}
+
+/// Maps a unit vector to the unit square with an area preserving cylindrical projection, so
+/// densities on the square only differ from solid angle densities by a factor 4 pi.
+fn to_square(direction: Vec3) -> (f64, f64) {
+    let cos_theta = direction.z.clamp(-1.0, 1.0);
+    let phi = direction
+        .y
+        .atan2(direction.x)
+        .rem_euclid(2.0 * f64::consts::PI);
+    (
+        ((cos_theta + 1.0) / 2.0).min(1.0 - f64::EPSILON),
+        (phi / (2.0 * f64::consts::PI)).min(1.0 - f64::EPSILON),
+    )
+}
+
+fn from_square(x: f64, y: f64) -> Vec3 {
+    let cos_theta = 2.0 * x - 1.0;
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * f64::consts::PI * y;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// Quadrant of the unit square containing `(x, y)`, and the point rescaled to that quadrant.
+fn quadrant(x: f64, y: f64) -> (usize, f64, f64) {
+    let (qx, x) = if x < 0.5 {
+        (0, x * 2.0)
+    } else {
+        (1, x * 2.0 - 1.0)
+    };
+    let (qy, y) = if y < 0.5 {
+        (0, y * 2.0)
+    } else {
+        (2, y * 2.0 - 1.0)
+    };
+    (qx + qy, x, y)
+}
+
+/// Distribution of incident radiance over the sphere of directions, as a quadtree adapting its
+/// resolution to where the energy is.
+#[derive(Debug, Clone)]
+struct DirectionalTree {
+    nodes: Vec<QuadNode>,
+}
+
+impl DirectionalTree {
+    fn new() -> DirectionalTree {
+        DirectionalTree {
+            nodes: vec![QuadNode::default()],
+        }
+    }
+
+    fn total(&self) -> f64 {
+        self.nodes[0].total()
+    }
+
+    fn record(&self, direction: Vec3, value: f64) {
+        let (mut x, mut y) = to_square(direction);
+        let mut index = 0;
+        loop {
+            let (q, qx, qy) = quadrant(x, y);
+            self.nodes[index].sums[q].add(value);
+            x = qx;
+            y = qy;
+            index = self.nodes[index].children[q];
+            if index == 0 {
+                return;
+            }
+        }
+    }
+
+    /// Samples a direction proportionally to the recorded energy, `None` if nothing was recorded.
+    fn sample(&self) -> Option<Vec3> {
+        if self.total() <= 0.0 {
+            return None;
+        }
+
+        let (mut x0, mut y0, mut size) = (0.0, 0.0, 1.0);
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            let mut u = sampler::random() * node.total();
+            let mut q = 3;
+            for candidate in 0..4 {
+                if u < node.sum(candidate) {
+                    q = candidate;
+                    break;
+                }
+                u -= node.sum(candidate);
+            }
+
+            size /= 2.0;
+            x0 += size * (q % 2) as f64;
+            y0 += size * (q / 2) as f64;
+            index = node.children[q];
+            if index == 0 {
+                return Some(from_square(
+                    x0 + size * sampler::random(),
+                    y0 + size * sampler::random(),
+                ));
+            }
+        }
+    }
+
+    /// Solid angle density of `sample` picking `direction`.
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let total = self.total();
+        if total <= 0.0 {
+            return 0.0;
+        }
+
+        let (mut x, mut y) = to_square(direction);
+        let mut pdf = 1.0;
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            let (q, qx, qy) = quadrant(x, y);
+            let node_total = node.total();
+            if node_total <= 0.0 {
+                return 0.0;
+            }
+            pdf *= 4.0 * node.sum(q) / node_total;
+            x = qx;
+            y = qy;
+            index = node.children[q];
+            if index == 0 {
+                return pdf / (4.0 * f64::consts::PI);
+            }
+        }
+    }
+
+    /// Empty tree to record the next pass into, subdividing the quadrants holding more than
+    /// `threshold` of the energy and merging the others.
+    fn refined(&self, threshold: f64) -> DirectionalTree {
+        let total = self.total();
+        let mut nodes = vec![QuadNode::default()];
+
+        // New node, the matching old node if there is one, the energy of each quadrant and depth
+        let mut stack = vec![(0, Some(0), self.nodes[0].sums(), 1)];
+        while let Some((index, old, sums, depth)) = stack.pop() {
+            for (q, &energy) in sums.iter().enumerate() {
+                if total <= 0.0 || energy / total <= threshold || depth >= MAX_DIRECTIONAL_DEPTH {
+                    continue;
+                }
+
+                // Energy is assumed to be spread evenly in quadrants that weren't refined yet
+                let old_child = old
+                    .map(|old| self.nodes[old].children[q])
+                    .filter(|&child| child != 0);
+                let child_sums = match old_child {
+                    Some(child) => self.nodes[child].sums(),
+                    None => [energy / 4.0; 4],
+                };
+
+                let child = nodes.len();
+                nodes.push(QuadNode::default());
+                nodes[index].children[q] = child;
+                stack.push((child, old_child, child_sums, depth + 1));
+            }
+        }
+
+        DirectionalTree { nodes }
+    }
+}
+
+#[derive(Debug)]
+enum SpatialNode {
+    Leaf {
+        /// Distribution learned in the previous pass.
+        sampling: DirectionalTree,
+        /// Distribution being recorded in this pass.
+        building: DirectionalTree,
+        records: AtomicUsize,
+    },
+    Interior {
+        axis: usize,
+        children: [usize; 2],
+    },
+}
+
+/// Spatial-directional tree of practical path guiding (Müller et al. 2017).
+///
+/// A binary tree over the scene halving boxes along alternating axes, with a directional quadtree
+/// of incident radiance in every leaf. Paths record the radiance they find while rendering a pass,
+/// and `refine` turns those records into the distributions sampled in the next pass, splitting
+/// leaves that received many records and quadrants that received much energy.
+#[derive(Debug)]
+pub struct SdTree {
+    bounds: Aabb,
+    nodes: Vec<SpatialNode>,
+}
+
+fn component(v: Vec3, axis: usize) -> f64 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+impl SdTree {
+    /// Tree covering `bounds`, padded to a cube so splits stay well shaped.
+    pub fn new(bounds: Aabb) -> SdTree {
+        let center = (bounds.min + bounds.max) / 2.0;
+        let extent = bounds.max - bounds.min;
+        let half = extent.x.max(extent.y).max(extent.z) / 2.0 * 1.01;
+        let half = Vec3::new(half, half, half);
+
+        SdTree {
+            bounds: Aabb::new(center - half, center + half),
+            nodes: vec![SpatialNode::Leaf {
+                sampling: DirectionalTree::new(),
+                building: DirectionalTree::new(),
+                records: AtomicUsize::new(0),
+            }],
+        }
+    }
+
+    fn leaf(&self, p: Vec3) -> &SpatialNode {
+        let (mut min, mut max) = (self.bounds.min, self.bounds.max);
+        let mut index = 0;
+        loop {
+            match self.nodes[index] {
+                SpatialNode::Leaf { .. } => return &self.nodes[index],
+                SpatialNode::Interior { axis, children } => {
+                    let middle = (component(min, axis) + component(max, axis)) / 2.0;
+                    let split = |v: &mut Vec3| match axis {
+                        0 => v.x = middle,
+                        1 => v.y = middle,
+                        _ => v.z = middle,
+                    };
+                    if component(p, axis) < middle {
+                        split(&mut max);
+                        index = children[0];
+                    } else {
+                        split(&mut min);
+                        index = children[1];
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records radiance `value` arriving at `p` from `direction`, already divided by the density
+    /// the direction was sampled with.
+    pub fn record(&self, p: Vec3, direction: Vec3, value: f64) {
+        if let SpatialNode::Leaf {
+            building, records, ..
+        } = self.leaf(p)
+        {
+            records.fetch_add(1, Ordering::Relaxed);
+            if value > 0.0 && value.is_finite() {
+                building.record(direction, value);
+            }
+        }
+    }
+
+    /// Samples a direction at `p` from the learned distribution, `None` where nothing was learned.
+    pub fn sample(&self, p: Vec3) -> Option<Vec3> {
+        match self.leaf(p) {
+            SpatialNode::Leaf { sampling, .. } => sampling.sample(),
+            SpatialNode::Interior { .. } => None,
+        }
+    }
+
+    /// Solid angle density of `sample` picking `direction` at `p`.
+    pub fn pdf(&self, p: Vec3, direction: Vec3) -> f64 {
+        match self.leaf(p) {
+            SpatialNode::Leaf { sampling, .. } => sampling.pdf(direction),
+            SpatialNode::Interior { .. } => 0.0,
+        }
+    }
+
+    /// Whether `sample` can be used at `p`.
+    pub fn can_sample(&self, p: Vec3) -> bool {
+        match self.leaf(p) {
+            SpatialNode::Leaf { sampling, .. } => sampling.total() > 0.0,
+            SpatialNode::Interior { .. } => false,
+        }
+    }
+
+    /// Learns from the records of the pass: leaves with more than `spatial_threshold` records are
+    /// split, and quadrants with more than `directional_threshold` of the energy of their leaf are
+    /// subdivided. The recorded distributions are sampled from then on and recording starts over.
+    pub fn refine(&mut self, spatial_threshold: usize, directional_threshold: f64) {
+        // Depth of every node, to alternate the split axis
+        let mut depths = vec![0; self.nodes.len()];
+        for index in 0..self.nodes.len() {
+            if let SpatialNode::Interior { children, .. } = self.nodes[index] {
+                depths[children[0]] = depths[index] + 1;
+                depths[children[1]] = depths[index] + 1;
+            }
+        }
+
+        let mut index = 0;
+        while index < self.nodes.len() {
+            let depth = depths[index];
+            let split = match &self.nodes[index] {
+                SpatialNode::Leaf {
+                    building, records, ..
+                } => {
+                    let count = records.load(Ordering::Relaxed);
+                    if count > spatial_threshold && depth < MAX_SPATIAL_DEPTH {
+                        Some((building.clone(), count))
+                    } else {
+                        None
+                    }
+                }
+                SpatialNode::Interior { .. } => None,
+            };
+
+            if let Some((building, count)) = split {
+                // Both halves start from the parent's records, assumed to split evenly
+                let first = self.nodes.len();
+                for _ in 0..2 {
+                    self.nodes.push(SpatialNode::Leaf {
+                        sampling: DirectionalTree::new(),
+                        building: building.clone(),
+                        records: AtomicUsize::new(count / 2),
+                    });
+                    depths.push(depth + 1);
+                }
+                self.nodes[index] = SpatialNode::Interior {
+                    axis: depth % 3,
+                    children: [first, first + 1],
+                };
+            }
+            index += 1;
+        }
+
+        for node in &mut self.nodes {
+            if let SpatialNode::Leaf {
+                sampling,
+                building,
+                records,
+            } = node
+            {
+                let refined = building.refined(directional_threshold);
+                *sampling = std::mem::replace(building, refined);
+                *records = AtomicUsize::new(0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::Aabb;
+    use crate::sd_tree::SdTree;
+    use crate::vec3::Vec3;
+    use std::f64;
+
+    #[test]
+    fn learns_where_light_comes_from() {
+        let mut tree = SdTree::new(Aabb::new(Vec3::origin(), Vec3::new(1.0, 1.0, 1.0)));
+        let p = Vec3::new(0.5, 0.5, 0.5);
+        let up = Vec3::new(0.1, 0.0, 1.0).make_unit_vec();
+        let side = Vec3::new(1.0, 0.0, 0.0);
+
+        for pass in 0..4 {
+            for _ in 0..1000 {
+                tree.record(p, up, 1.0);
+                tree.record(p, side, 0.01);
+            }
+            tree.refine(100_000, 0.01);
+            assert!(tree.can_sample(p), "pass {}", pass);
+        }
+
+        let uniform = 1.0 / (4.0 * f64::consts::PI);
+        assert!(tree.pdf(p, up) > 100.0 * uniform);
+        assert!(tree.pdf(p, side) < uniform);
+
+        let near_up = (0..1000)
+            .filter(|_| tree.sample(p).expect("learned").dot(up) > 0.9)
+            .count();
+        assert!(near_up > 900);
+    }
+}
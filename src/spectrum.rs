@@ -0,0 +1,412 @@
+//! Building blocks for spectral rendering: wavelength sampling, RGB to spectrum upsampling
+//! and conversion of spectral samples back to color.
+
+use crate::vec3::Vec3;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::ops::{Add, Mul};
+use std::path::Path;
+
+/// Visible range covered by the color matching functions, in nanometers.
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+/// Number of wavelengths carried along every path.
+pub const WAVELENGTH_SAMPLES: usize = 4;
+
+/// Integral of `cie_y` over the visible range, normalizes a constant spectrum of 1 to luminance 1.
+const CIE_Y_INTEGRAL: f64 = 106.922_074_5;
+
+/// Piecewise gaussian used by the color matching function fits.
+fn gaussian(x: f64, mu: f64, sigma_left: f64, sigma_right: f64) -> f64 {
+    let sigma = if x < mu { sigma_left } else { sigma_right };
+    let t = (x - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+/// CIE 1931 2 degree color matching functions, multi lobe fit by Wyman, Sloan & Shirley (2013).
+pub fn cie_x(lambda: f64) -> f64 {
+    1.056 * gaussian(lambda, 599.8, 37.9, 31.0) + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2)
+}
+
+pub fn cie_y(lambda: f64) -> f64 {
+    0.821 * gaussian(lambda, 568.8, 46.9, 40.5) + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1)
+}
+
+pub fn cie_z(lambda: f64) -> f64 {
+    1.217 * gaussian(lambda, 437.0, 11.8, 36.0) + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8)
+}
+
+/// XYZ to linear sRGB, including a Bradford adaptation from the equal energy white point to D65
+/// so that a constant spectrum comes out white.
+pub fn xyz_to_rgb(xyz: Vec3) -> Vec3 {
+    Vec3::new(
+        3.146_251_0 * xyz.x - 1.666_123_9 * xyz.y - 0.480_127_1 * xyz.z,
+        -0.995_535_0 * xyz.x + 1.955_763_4 * xyz.y + 0.039_771_5 * xyz.z,
+        0.063_597_8 * xyz.x - 0.214_596_5 * xyz.y + 1.150_998_7 * xyz.z,
+    )
+}
+
+/// Basis spectra of Smits (1999), sampled in 10 bins over 380..720nm.
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+/// Value of a smooth spectrum matching `rgb` at the given wavelength (Smits 1999).
+/// Works for both reflectances and (unbounded) emission colors.
+pub fn rgb_to_spectrum(rgb: Vec3, lambda: f64) -> f64 {
+    let bin = (((lambda - 380.0) / (720.0 - 380.0) * 10.0).floor().max(0.0) as usize).min(9);
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+
+    if r <= g && r <= b {
+        let base = r * SMITS_WHITE[bin] + (g.min(b) - r) * SMITS_CYAN[bin];
+        if g <= b {
+            base + (b - g) * SMITS_BLUE[bin]
+        } else {
+            base + (g - b) * SMITS_GREEN[bin]
+        }
+    } else if g <= r && g <= b {
+        let base = g * SMITS_WHITE[bin] + (r.min(b) - g) * SMITS_MAGENTA[bin];
+        if r <= b {
+            base + (b - r) * SMITS_BLUE[bin]
+        } else {
+            base + (r - b) * SMITS_RED[bin]
+        }
+    } else {
+        let base = b * SMITS_WHITE[bin] + (r.min(g) - b) * SMITS_YELLOW[bin];
+        if r <= g {
+            base + (g - r) * SMITS_GREEN[bin]
+        } else {
+            base + (r - g) * SMITS_RED[bin]
+        }
+    }
+}
+
+/// Spectral power distribution of an emitter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Spectrum {
+    Constant(f64),
+    /// Planck's law for a black body at `temperature` kelvin, normalized to peak at 1.
+    /// Black bodies without a positive temperature don't emit.
+    Blackbody {
+        temperature: f64,
+    },
+    /// Measured values at increasing wavelengths (nm), linearly interpolated and zero outside.
+    Tabulated {
+        wavelengths: Vec<f64>,
+        values: Vec<f64>,
+    },
+}
+
+/// Emitted spectral radiance of a black body at `lambda` nanometers (Planck's law).
+pub fn blackbody(lambda: f64, temperature: f64) -> f64 {
+    const C: f64 = 299_792_458.0;
+    const H: f64 = 6.626_070_15e-34;
+    const KB: f64 = 1.380_649e-23;
+
+    if temperature <= 0.0 {
+        return 0.0;
+    }
+
+    let l = lambda * 1e-9;
+    (2.0 * H * C * C) / (l.powi(5) * ((H * C / (l * KB * temperature)).exp() - 1.0))
+}
+
+impl Spectrum {
+    pub fn blackbody(temperature: f64) -> Spectrum {
+        Spectrum::Blackbody { temperature }
+    }
+
+    /// Reads a tabulated spectrum from `wavelength,value` lines.
+    /// Empty lines, `#` comments and a non numeric header line are skipped.
+    pub fn from_csv_reader<R: Read>(reader: R) -> io::Result<Spectrum> {
+        let mut samples: Vec<(f64, f64)> = Vec::new();
+
+        for (line_number, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split(&[',', ';'][..]).map(str::trim);
+            let parsed = match (fields.next(), fields.next()) {
+                (Some(lambda), Some(value)) => lambda
+                    .parse::<f64>()
+                    .and_then(|lambda| value.parse::<f64>().map(|value| (lambda, value))),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: expected `wavelength,value`", line_number + 1),
+                    ))
+                }
+            };
+
+            match parsed {
+                Ok((lambda, value)) if !lambda.is_finite() || !value.is_finite() => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: numbers should be finite", line_number + 1),
+                    ))
+                }
+                Ok(sample) => samples.push(sample),
+                Err(_) if samples.is_empty() && line_number == 0 => continue,
+                Err(e) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: {}", line_number + 1, e),
+                    ))
+                }
+            }
+        }
+
+        if samples.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "spectrum has no samples",
+            ));
+        }
+
+        samples.sort_by(|a, b| a.0.partial_cmp(&b.0).expect("parsed numbers are not NaN"));
+        let (wavelengths, values) = samples.into_iter().unzip();
+        Ok(Spectrum::Tabulated {
+            wavelengths,
+            values,
+        })
+    }
+
+    pub fn from_csv(path: impl AsRef<Path>) -> io::Result<Spectrum> {
+        Spectrum::from_csv_reader(File::open(path)?)
+    }
+
+    pub fn value(&self, lambda: f64) -> f64 {
+        match self {
+            Spectrum::Constant(value) => *value,
+            Spectrum::Blackbody { temperature } => {
+                if *temperature <= 0.0 || !temperature.is_finite() {
+                    return 0.0;
+                }
+                // Wien's displacement law gives the peak wavelength
+                let peak = 2.897_771_955e-3 / temperature * 1e9;
+                blackbody(lambda, *temperature) / blackbody(peak, *temperature)
+            }
+            Spectrum::Tabulated {
+                wavelengths,
+                values,
+            } => {
+                let i = wavelengths
+                    .iter()
+                    .position(|&w| w >= lambda)
+                    .unwrap_or(wavelengths.len());
+                if i == 0 {
+                    return if wavelengths[0] == lambda {
+                        values[0]
+                    } else {
+                        0.0
+                    };
+                }
+                if i == wavelengths.len() {
+                    return 0.0;
+                }
+                let t = (lambda - wavelengths[i - 1]) / (wavelengths[i] - wavelengths[i - 1]);
+                values[i - 1] * (1.0 - t) + values[i] * t
+            }
+        }
+    }
+
+    /// CIE XYZ color, normalized so a constant spectrum of 1 has luminance (Y) 1.
+    pub fn to_xyz(&self) -> Vec3 {
+        let mut xyz = Vec3::origin();
+        let mut lambda = LAMBDA_MIN;
+        while lambda <= LAMBDA_MAX {
+            let value = self.value(lambda);
+            xyz += Vec3::new(cie_x(lambda), cie_y(lambda), cie_z(lambda)) * value;
+            lambda += 1.0;
+        }
+        xyz / CIE_Y_INTEGRAL
+    }
+
+    pub fn luminance(&self) -> f64 {
+        self.to_xyz().y
+    }
+
+    pub fn to_rgb(&self) -> Vec3 {
+        xyz_to_rgb(self.to_xyz())
+    }
+}
+
+/// Wavelengths carried by a single path, using hero wavelength sampling: the first one is
+/// sampled uniformly and the others are evenly spaced after it, wrapping around the visible range.
+#[derive(Debug, Copy, Clone)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; WAVELENGTH_SAMPLES],
+    /// Number of wavelengths still carried, dispersion leaves only the hero wavelength.
+    active: usize,
+}
+
+impl SampledWavelengths {
+    pub fn sample(u: f64) -> SampledWavelengths {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut lambda = [0.0; WAVELENGTH_SAMPLES];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let offset = (u + i as f64 / WAVELENGTH_SAMPLES as f64).fract();
+            *l = LAMBDA_MIN + offset * range;
+        }
+
+        SampledWavelengths {
+            lambda,
+            active: WAVELENGTH_SAMPLES,
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// Drop all but the hero wavelength, for when paths diverge per wavelength.
+    pub fn terminate_secondary(&mut self) {
+        self.active = 1;
+    }
+
+    /// Evaluates a spectrum at the carried wavelengths.
+    pub fn spectrum_from_fn(&self, f: impl Fn(f64) -> f64) -> SampledSpectrum {
+        let mut values = [0.0; WAVELENGTH_SAMPLES];
+        for (value, &lambda) in values.iter_mut().zip(self.lambda.iter()).take(self.active) {
+            *value = f(lambda);
+        }
+        SampledSpectrum(values)
+    }
+
+    /// Upsamples an RGB color at the carried wavelengths.
+    pub fn spectrum_from_rgb(&self, rgb: Vec3) -> SampledSpectrum {
+        self.spectrum_from_fn(|lambda| rgb_to_spectrum(rgb, lambda))
+    }
+
+    /// Monte Carlo estimate of the XYZ color of the spectrum from its samples.
+    pub fn to_xyz(&self, spectrum: &SampledSpectrum) -> Vec3 {
+        let pdf = 1.0 / (LAMBDA_MAX - LAMBDA_MIN);
+        let mut xyz = Vec3::origin();
+        for (&value, &lambda) in spectrum.0.iter().zip(self.lambda.iter()).take(self.active) {
+            xyz += Vec3::new(cie_x(lambda), cie_y(lambda), cie_z(lambda)) * (value / pdf);
+        }
+        xyz / (self.active as f64 * CIE_Y_INTEGRAL)
+    }
+}
+
+/// Values of a spectral quantity at the wavelengths of a `SampledWavelengths`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SampledSpectrum(pub [f64; WAVELENGTH_SAMPLES]);
+
+impl SampledSpectrum {
+    pub fn constant(value: f64) -> SampledSpectrum {
+        SampledSpectrum([value; WAVELENGTH_SAMPLES])
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn add(self, rhs: SampledSpectrum) -> SampledSpectrum {
+        let mut values = self.0;
+        for (value, other) in values.iter_mut().zip(rhs.0.iter()) {
+            *value += other;
+        }
+        SampledSpectrum(values)
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(self, rhs: SampledSpectrum) -> SampledSpectrum {
+        let mut values = self.0;
+        for (value, other) in values.iter_mut().zip(rhs.0.iter()) {
+            *value *= other;
+        }
+        SampledSpectrum(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spectrum::{
+        rgb_to_spectrum, xyz_to_rgb, SampledSpectrum, SampledWavelengths, Spectrum,
+    };
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn white_spectrum_renders_white() {
+        let mut rgb = Vec3::origin();
+        let samples = 1000;
+        for i in 0..samples {
+            let wavelengths = SampledWavelengths::sample((f64::from(i) + 0.5) / f64::from(samples));
+            let white = wavelengths.spectrum_from_rgb(Vec3::new(1.0, 1.0, 1.0));
+            rgb += xyz_to_rgb(wavelengths.to_xyz(&white));
+        }
+        rgb /= samples;
+
+        assert!(
+            (rgb - Vec3::new(1.0, 1.0, 1.0)).length() < 0.01,
+            "{:?}",
+            rgb
+        );
+    }
+
+    #[test]
+    fn blackbody_color_temperatures() {
+        let candle = Spectrum::blackbody(1900.0).to_rgb();
+        let neutral = Spectrum::blackbody(5455.0).to_rgb();
+        let sky = Spectrum::blackbody(10000.0).to_rgb();
+
+        assert!(candle.x > candle.y && candle.y > candle.z);
+        assert!((neutral.x / neutral.z - 1.0).abs() < 0.1, "{:?}", neutral);
+        assert!(sky.z > sky.x);
+
+        for &temperature in [0.0, -100.0, f64::NAN].iter() {
+            assert_eq!(Spectrum::blackbody(temperature).value(550.0), 0.0);
+        }
+    }
+
+    #[test]
+    fn reads_tabulated_spectrum() {
+        let csv = "wavelength,power\n# comment\n500, 1.0\n400,0.0\n\n600,3.0\n";
+        let spectrum = Spectrum::from_csv_reader(csv.as_bytes()).expect("valid csv");
+
+        assert_eq!(spectrum.value(450.0), 0.5);
+        assert_eq!(spectrum.value(550.0), 2.0);
+        assert_eq!(spectrum.value(700.0), 0.0);
+        assert!(Spectrum::from_csv_reader("400,0\n500,x\n".as_bytes()).is_err());
+        assert!(Spectrum::from_csv_reader("400,0\nnan,1\n".as_bytes()).is_err());
+        assert!(Spectrum::from_csv_reader("400,inf\n500,1\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn upsampled_red_is_mostly_long_wavelengths() {
+        let red = Vec3::new(1.0, 0.0, 0.0);
+        assert!(rgb_to_spectrum(red, 650.0) > 0.9);
+        assert!(rgb_to_spectrum(red, 450.0) < 0.1);
+        assert_eq!(
+            SampledSpectrum::constant(2.0) * SampledSpectrum::constant(0.5),
+            SampledSpectrum::constant(1.0)
+        );
+    }
+}
@@ -1,8 +1,11 @@
+use crate::aabb::Aabb;
 use crate::hitable::HitRecord;
-use crate::hitable::Hitable;
+use crate::hitable::{Hitable, SurfaceSample};
 use crate::materials::Material;
 use crate::ray::Ray;
+use crate::sampler;
 use crate::vec3::Vec3;
+use std::f64;
 
 pub struct Sphere {
     center: Vec3,
@@ -18,10 +21,19 @@ impl Sphere {
             material,
         }
     }
+
+    /// Maps a point on the unit sphere to (u, v) in [0, 1], with `v` going from the bottom pole up.
+    fn uv(unit_point: Vec3) -> (f64, f64) {
+        let phi = unit_point.z.atan2(unit_point.x);
+        let theta = unit_point.y.asin();
+        let u = 1.0 - (phi + f64::consts::PI) / (2.0 * f64::consts::PI);
+        let v = (theta + f64::consts::FRAC_PI_2) / f64::consts::PI;
+        (u, v)
+    }
 }
 
 impl Hitable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let origin_center = ray.origin - self.center;
         let a = ray.direction.dot(ray.direction);
         let b = origin_center.dot(ray.direction);
@@ -36,16 +48,65 @@ impl Hitable for Sphere {
             if (temp < t_max) && (temp > t_min) {
                 let p = ray.point_at_parameter(temp);
                 let normal = (p - self.center) / self.radius;
-                return Some(HitRecord::new(temp, p, normal, self.material.as_ref()));
+                let (u, v) = Sphere::uv(normal);
+                return Some(HitRecord::new(
+                    temp,
+                    p,
+                    normal,
+                    u,
+                    v,
+                    self.material.as_ref(),
+                ));
             }
             // Check positive solution
             let temp = (-b + (b * b - a * c).sqrt()) / a;
             if (temp < t_max) && (temp > t_min) {
                 let p = ray.point_at_parameter(temp);
                 let normal = (p - self.center) / self.radius;
-                return Some(HitRecord::new(temp, p, normal, self.material.as_ref()));
+                let (u, v) = Sphere::uv(normal);
+                return Some(HitRecord::new(
+                    temp,
+                    p,
+                    normal,
+                    u,
+                    v,
+                    self.material.as_ref(),
+                ));
             }
         }
         None
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = self.radius.abs();
+        let extent = Vec3::new(radius, radius, radius);
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+
+    fn sample_surface(&self) -> Option<SurfaceSample<'_>> {
+        let area = 4.0 * f64::consts::PI * self.radius * self.radius;
+        if area <= 0.0 {
+            return None;
+        }
+
+        // Uniform on the unit sphere, by Archimedes' hat-box theorem
+        let z = 1.0 - 2.0 * sampler::random();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * f64::consts::PI * sampler::random();
+        let unit = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+
+        let normal = unit * self.radius.signum();
+        let (u, v) = Sphere::uv(normal);
+        Some(SurfaceSample {
+            hit: HitRecord::new(
+                0.0,
+                self.center + unit * self.radius.abs(),
+                normal,
+                u,
+                v,
+                self.material.as_ref(),
+            ),
+            area,
+        })
+    }
 }
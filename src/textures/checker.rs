@@ -0,0 +1,28 @@
+use crate::textures::Texture;
+use crate::vec3::Vec3;
+
+/// 3D checkerboard alternating between two textures, `scale` is the size of a single cell.
+pub struct CheckerTexture {
+    odd: Box<dyn Texture>,
+    even: Box<dyn Texture>,
+    scale: f64,
+}
+
+impl CheckerTexture {
+    pub fn new(odd: Box<dyn Texture>, even: Box<dyn Texture>, scale: f64) -> CheckerTexture {
+        CheckerTexture { odd, even, scale }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        let cell =
+            (p.x / self.scale).floor() + (p.y / self.scale).floor() + (p.z / self.scale).floor();
+
+        if (cell as i64).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use crate::textures::Texture;
+use crate::vec3::Vec3;
+
+#[derive(Debug)]
+pub struct ConstantTexture {
+    pub color: Vec3,
+}
+
+impl ConstantTexture {
+    pub fn new(color: Vec3) -> ConstantTexture {
+        ConstantTexture { color }
+    }
+
+    /// Same value in every channel, for scalar parameters like roughness.
+    pub fn scalar(value: f64) -> ConstantTexture {
+        ConstantTexture::new(Vec3::new(value, value, value))
+    }
+}
+
+impl Texture for ConstantTexture {
+    fn value(&self, _u: f64, _v: f64, _p: Vec3) -> Vec3 {
+        self.color
+    }
+}
@@ -0,0 +1,40 @@
+use crate::textures::Texture;
+use crate::vec3::Vec3;
+use image::{ImageResult, RgbImage};
+use std::path::Path;
+
+/// Texture looked up from an image by UV, with `v` = 0 at the bottom row.
+pub struct ImageTexture {
+    image: RgbImage,
+}
+
+impl ImageTexture {
+    pub fn new(image: RgbImage) -> ImageTexture {
+        ImageTexture { image }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> ImageResult<ImageTexture> {
+        Ok(ImageTexture::new(image::open(path)?.to_rgb()))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Vec3) -> Vec3 {
+        let (width, height) = self.image.dimensions();
+        if width == 0 || height == 0 {
+            return Vec3::origin();
+        }
+
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+        let x = ((u * f64::from(width)) as u32).min(width - 1);
+        let y = ((v * f64::from(height)) as u32).min(height - 1);
+
+        let pixel = self.image.get_pixel(x, y);
+        Vec3::new(
+            f64::from(pixel[0]) / 255.0,
+            f64::from(pixel[1]) / 255.0,
+            f64::from(pixel[2]) / 255.0,
+        )
+    }
+}
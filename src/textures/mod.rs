@@ -0,0 +1,10 @@
+pub mod checker;
+pub mod constant;
+pub mod image;
+
+use crate::vec3::Vec3;
+
+pub trait Texture: Send + Sync {
+    /// Value at surface coordinates (u, v) of the hit point `p`.
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3;
+}
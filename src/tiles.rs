@@ -0,0 +1,246 @@
+use crate::exr::{self, Channel};
+use crate::region::Region;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// `path` with `.<part>` added to the name before the extension.
+pub fn with_part(path: &Path, part: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("output");
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => path.with_file_name(format!("{}.{}.{}", stem, part, extension)),
+        None => path.with_file_name(format!("{}.{}", stem, part)),
+    }
+}
+
+/// Path `tile` of the image at `output` is saved at until the tiles are put together.
+pub fn tile_path(output: &Path, tile: &Region) -> PathBuf {
+    with_part(output, &format!("tile_{}_{}", tile.x0, tile.y0))
+}
+
+fn open(path: &Path) -> io::Result<image::DynamicImage> {
+    image::open(path).map_err(|e| match e {
+        image::ImageError::IoError(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    })
+}
+
+fn png_error(e: png::EncodingError) -> io::Error {
+    match e {
+        png::EncodingError::IoError(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+/// Puts the PNGs of `tiles`, as given by `Region::tiles`, together into the image of `region` at
+/// `output`, reading a row of tiles at a time.
+pub fn stitch_png(
+    tiles: &[Region],
+    region: Region,
+    tile_path: &dyn Fn(&Region) -> PathBuf,
+    output: &Path,
+) -> io::Result<()> {
+    let first = tiles
+        .first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no tiles"))?;
+    let alpha = matches!(open(&tile_path(first))?.color(), image::ColorType::RGBA(_));
+
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(output)?),
+        region.width() as u32,
+        region.height() as u32,
+    );
+    encoder.set_color(if alpha {
+        png::ColorType::RGBA
+    } else {
+        png::ColorType::RGB
+    });
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    let mut stream = writer.stream_writer();
+
+    for row in tiles.chunk_by(|a, b| a.y0 == b.y0) {
+        let images = row
+            .iter()
+            .map(|tile| {
+                let image = open(&tile_path(tile))?;
+                Ok(if alpha {
+                    image.to_rgba().into_raw()
+                } else {
+                    image.to_rgb().into_raw()
+                })
+            })
+            .collect::<io::Result<Vec<Vec<u8>>>>()?;
+        for y in 0..row[0].height() {
+            for (tile, image) in row.iter().zip(&images) {
+                let stride = tile.width() * if alpha { 4 } else { 3 };
+                let line = image
+                    .get(y * stride..(y + 1) * stride)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "tile too small"))?;
+                stream.write_all(line)?;
+            }
+        }
+    }
+    stream.finish().map_err(png_error)
+}
+
+/// Puts the EXRs of `tiles`, as given by `Region::tiles`, together into the image of `region` out
+/// of `display` at `output`, reading a row of tiles at a time.
+pub fn stitch_exr(
+    tiles: &[Region],
+    display: Region,
+    region: Region,
+    tile_path: &dyn Fn(&Region) -> PathBuf,
+    output: &Path,
+    metadata: &[(String, String)],
+) -> io::Result<()> {
+    let mut writer = None;
+    let mut channel_count = None;
+    for row in tiles.chunk_by(|a, b| a.y0 == b.y0) {
+        let images = row
+            .iter()
+            .map(|tile| match exr::read(tile_path(tile))? {
+                (data, channels) if data == *tile => Ok(channels),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "tile of the wrong size",
+                )),
+            })
+            .collect::<io::Result<Vec<Vec<Channel>>>>()?;
+        let count = *channel_count.get_or_insert(images[0].len());
+        if images.iter().any(|image| image.len() != count) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "tiles with different channels",
+            ));
+        }
+        let writer = match &mut writer {
+            Some(writer) => writer,
+            None => {
+                let names: Vec<String> = images[0].iter().map(|c| c.name.clone()).collect();
+                let file = BufWriter::new(File::create(output)?);
+                writer.get_or_insert(exr::Writer::new(file, display, region, &names, metadata)?)
+            }
+        };
+
+        for y in 0..row[0].height() {
+            let channels: Vec<Vec<f32>> = (0..count)
+                .map(|channel| {
+                    row.iter()
+                        .zip(&images)
+                        .flat_map(|(tile, image)| {
+                            image[channel].values[y * tile.width()..(y + 1) * tile.width()]
+                                .iter()
+                                .cloned()
+                        })
+                        .collect()
+                })
+                .collect();
+            let channels: Vec<&[f32]> = channels.iter().map(Vec::as_slice).collect();
+            writer.write_row(&channels)?;
+        }
+    }
+    writer
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no tiles"))?
+        .finish()
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::exr::{self, Channel};
+    use crate::region::Region;
+    use crate::tiles::{stitch_exr, stitch_png, tile_path};
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Empty directory for the files of the test `name`.
+    fn directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("rs_raytracer_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).expect("temporary directory");
+        directory
+    }
+
+    /// Value of pixel `x`, `y` of the whole image.
+    fn value(x: usize, y: usize) -> u8 {
+        (10 * y + x) as u8
+    }
+
+    #[test]
+    fn stitches_pngs_of_2x2_tiles() {
+        let directory = directory("stitch_png");
+        let output = directory.join("image.png");
+        let region = Region::new(1, 2, 4, 5);
+        let tiles = region.tiles(2);
+        assert_eq!(tiles.len(), 4);
+        for tile in &tiles {
+            let image =
+                image::ImageBuffer::from_fn(tile.width() as u32, tile.height() as u32, |x, y| {
+                    let v = value(tile.x0 + x as usize, tile.y0 + y as usize);
+                    image::Rgb([v, v / 2, 255 - v])
+                });
+            image.save(tile_path(&output, tile)).expect("tile written");
+        }
+
+        stitch_png(&tiles, region, &|tile| tile_path(&output, tile), &output).expect("stitched");
+        let stitched = image::open(&output).expect("readable png").to_rgb();
+        assert_eq!(stitched.dimensions(), (3, 3));
+        for (x, y, pixel) in stitched.enumerate_pixels() {
+            let v = value(region.x0 + x as usize, region.y0 + y as usize);
+            assert_eq!(pixel.0, [v, v / 2, 255 - v]);
+        }
+        fs::remove_dir_all(&directory).expect("temporary directory removed");
+    }
+
+    #[test]
+    fn stitches_exrs_of_2x2_tiles() {
+        let directory = directory("stitch_exr");
+        let output = directory.join("image.exr");
+        let display = Region::full(6, 6);
+        let region = Region::new(1, 2, 4, 5);
+        let tiles = region.tiles(2);
+        assert_eq!(tiles.len(), 4);
+        for tile in &tiles {
+            let values = |scale: f32| {
+                (tile.y0..tile.y1)
+                    .flat_map(|y| (tile.x0..tile.x1).map(move |x| scale * value(x, y) as f32))
+                    .collect()
+            };
+            let channels = [
+                Channel::new("R", values(1.0)),
+                Channel::new("G", values(0.5)),
+            ];
+            exr::write(tile_path(&output, tile), display, *tile, &channels, &[])
+                .expect("tile written");
+        }
+
+        let metadata = [("owner".to_string(), "me".to_string())];
+        stitch_exr(
+            &tiles,
+            display,
+            region,
+            &|tile| tile_path(&output, tile),
+            &output,
+            &metadata,
+        )
+        .expect("stitched");
+        let (window, channels) = exr::read(&output).expect("readable exr");
+        assert_eq!(window, region);
+        let expected: Vec<f32> = (region.y0..region.y1)
+            .flat_map(|y| (region.x0..region.x1).map(move |x| value(x, y) as f32))
+            .collect();
+        assert_eq!(channels[0].name, "G");
+        assert_eq!(
+            channels[0].values,
+            expected.iter().map(|v| 0.5 * v).collect::<Vec<_>>()
+        );
+        assert_eq!(channels[1].name, "R");
+        assert_eq!(channels[1].values, expected);
+        fs::remove_dir_all(&directory).expect("temporary directory removed");
+    }
+}
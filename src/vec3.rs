@@ -1,5 +1,6 @@
 extern crate num;
 
+use crate::sampler;
 use num_traits::AsPrimitive;
 
 use std::ops::AddAssign;
@@ -38,6 +39,11 @@ impl Vec3 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
+    /// Luminance of a linear Rec. 709 color.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
+
     pub fn make_unit_vec(self) -> Vec3 {
         let k = 1.0 / self.length();
         self * k
@@ -53,7 +59,7 @@ impl Vec3 {
 
     pub fn random_in_unit_sphere() -> Vec3 {
         loop {
-            let p = Vec3::new(rand::random(), rand::random(), rand::random()) * 2
+            let p = Vec3::new(sampler::random(), sampler::random(), sampler::random()) * 2
                 - Vec3::new(1.0, 1.0, 1.0);
             if p.squared_length() < 1.0 {
                 return p;
@@ -318,5 +324,4 @@ mod tests {
             }
         )
     }
-
 }
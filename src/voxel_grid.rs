@@ -0,0 +1,177 @@
+use crate::vec3::Vec3;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+/// Dense grid of scalar values (density, temperature..) sampled at voxel centers.
+///
+/// The on disk format is three little endian `u32` dimensions (nx, ny, nz)
+/// followed by `nx * ny * nz` little endian `f32` values with x varying fastest.
+#[derive(Debug, Clone)]
+pub struct VoxelGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    values: Vec<f32>,
+    max_value: f64,
+}
+
+impl VoxelGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, values: Vec<f32>) -> VoxelGrid {
+        assert!(
+            nx > 0 && ny > 0 && nz > 0,
+            "voxel grid should have voxels on every axis"
+        );
+        assert_eq!(
+            values.len(),
+            nx * ny * nz,
+            "voxel count should match the grid dimensions"
+        );
+
+        let max_value = values.iter().fold(0.0_f64, |max, &v| max.max(f64::from(v)));
+
+        VoxelGrid {
+            nx,
+            ny,
+            nz,
+            values,
+            max_value,
+        }
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> io::Result<VoxelGrid> {
+        let mut reader = BufReader::new(reader);
+
+        let read_u32 = |reader: &mut BufReader<R>| -> io::Result<u32> {
+            let mut buf = [0; 4];
+            reader.read_exact(&mut buf)?;
+            Ok(u32::from_le_bytes(buf))
+        };
+
+        let nx = read_u32(&mut reader)? as usize;
+        let ny = read_u32(&mut reader)? as usize;
+        let nz = read_u32(&mut reader)? as usize;
+
+        let count = nx
+            .checked_mul(ny)
+            .and_then(|count| count.checked_mul(nz))
+            .filter(|&count| count > 0)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid voxel grid dimensions {}x{}x{}", nx, ny, nz),
+                )
+            })?;
+
+        // The header can't be trusted, so the values grow with what the file actually holds
+        // rather than being allocated up front.
+        let mut values = Vec::new();
+        let mut buf = [0; 4];
+        for _ in 0..count {
+            reader.read_exact(&mut buf)?;
+            values.push(f32::from_le_bytes(buf));
+        }
+
+        Ok(VoxelGrid::new(nx, ny, nz, values))
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<VoxelGrid> {
+        VoxelGrid::from_reader(File::open(path)?)
+    }
+
+    /// Largest value in the grid, used as the majorant when tracking through the volume.
+    pub fn max_value(&self) -> f64 {
+        self.max_value
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        f64::from(self.values[(z * self.ny + y) * self.nx + x])
+    }
+
+    /// Trilinearly interpolated value at `p`, in grid space where the grid spans [0, 1] on every axis.
+    /// Values outside the grid are zero.
+    pub fn lookup(&self, p: Vec3) -> f64 {
+        if p.x < 0.0 || p.y < 0.0 || p.z < 0.0 || p.x > 1.0 || p.y > 1.0 || p.z > 1.0 {
+            return 0.0;
+        }
+
+        // Voxel centers sit at (i + 0.5) / n, clamp so the edges extend the outermost voxels.
+        let axis = |coordinate: f64, n: usize| {
+            let x = (coordinate * n as f64 - 0.5).max(0.0).min((n - 1) as f64);
+            let i0 = x.floor() as usize;
+            let i1 = (i0 + 1).min(n - 1);
+            (i0, i1, x - i0 as f64)
+        };
+
+        let (x0, x1, fx) = axis(p.x, self.nx);
+        let (y0, y1, fy) = axis(p.y, self.ny);
+        let (z0, z1, fz) = axis(p.z, self.nz);
+
+        let lerp = |a: f64, b: f64, t: f64| a * (1.0 - t) + b * t;
+
+        let c00 = lerp(self.voxel(x0, y0, z0), self.voxel(x1, y0, z0), fx);
+        let c10 = lerp(self.voxel(x0, y1, z0), self.voxel(x1, y1, z0), fx);
+        let c01 = lerp(self.voxel(x0, y0, z1), self.voxel(x1, y0, z1), fx);
+        let c11 = lerp(self.voxel(x0, y1, z1), self.voxel(x1, y1, z1), fx);
+
+        lerp(lerp(c00, c10, fy), lerp(c01, c11, fy), fz)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vec3::Vec3;
+    use crate::voxel_grid::VoxelGrid;
+
+    #[test]
+    fn reads_raw_grid() {
+        let mut raw = Vec::new();
+        for dim in [2_u32, 1, 1].iter() {
+            raw.extend_from_slice(&dim.to_le_bytes());
+        }
+        for value in [0.0_f32, 1.0].iter() {
+            raw.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let grid = VoxelGrid::from_reader(raw.as_slice()).expect("valid grid");
+        assert_eq!(grid.max_value(), 1.0);
+        assert_eq!(grid.lookup(Vec3::new(0.25, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.lookup(Vec3::new(0.75, 0.5, 0.5)), 1.0);
+    }
+
+    #[test]
+    fn interpolates_between_voxel_centers() {
+        let grid = VoxelGrid::new(2, 2, 2, vec![0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0]);
+
+        assert_eq!(grid.lookup(Vec3::new(0.5, 0.5, 0.5)), 0.5);
+        assert_eq!(grid.lookup(Vec3::new(0.375, 0.1, 0.9)), 0.25);
+        assert_eq!(grid.lookup(Vec3::new(1.5, 0.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn truncated_file_is_an_error() {
+        let mut raw = Vec::new();
+        for dim in [2_u32, 2, 2].iter() {
+            raw.extend_from_slice(&dim.to_le_bytes());
+        }
+
+        assert!(VoxelGrid::from_reader(raw.as_slice()).is_err());
+    }
+
+    #[test]
+    fn invalid_dimensions_are_an_error() {
+        for dims in [[0_u32, 1, 1], [1, 0, 1], [u32::MAX, u32::MAX, u32::MAX]].iter() {
+            let mut raw = Vec::new();
+            for dim in dims.iter() {
+                raw.extend_from_slice(&dim.to_le_bytes());
+            }
+            raw.extend_from_slice(&1.0_f32.to_le_bytes());
+
+            assert!(
+                VoxelGrid::from_reader(raw.as_slice()).is_err(),
+                "{:?}",
+                dims
+            );
+        }
+    }
+}